@@ -1,23 +1,17 @@
-use anyhow::Result;
-use http_body_util::BodyExt;
-use hypertor::Client;
+use hypertor::{Client, ResponseExt, Result};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let client = Client::new().await?;
 
-    let mut resp = client
+    let resp = client
         .get("https://duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion")
         .await?;
-    //let mut resp = client.get("http://example.com").await?;
+    //let resp = client.get("http://example.com").await?;
 
     println!("status: {}", resp.status());
     println!("headers: {:#?}", resp.headers());
-
-    while let Some(frame) = resp.body_mut().frame().await {
-        let bytes = frame?.into_data().unwrap();
-        println!("body: {}", std::str::from_utf8(&bytes)?);
-    }
+    println!("body: {}", resp.text().await?);
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,136 @@
+//! `ab`-style load test over Tor, for operators capacity testing their own
+//! onion services.
+//!
+//! Usage: `cargo run --example bench -- [-n REQUESTS] [-c CONCURRENCY] URL`
+
+use http_body_util::BodyExt;
+use hypertor::{Client, ConnectionError, ConnectionErrorKind};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Outcome of one request.
+enum Outcome {
+    Response { status: u16, bytes: usize },
+    CircuitFailure,
+    Error,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut requests = 100;
+    let mut concurrency = 10;
+    let mut url = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" => requests = args.next().ok_or("-n needs a value")?.parse()?,
+            "-c" => concurrency = args.next().ok_or("-c needs a value")?.parse()?,
+            _ if url.is_none() => url = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`").into()),
+        }
+    }
+    let Some(url) = url else {
+        return Err("usage: bench [-n REQUESTS] [-c CONCURRENCY] URL".into());
+    };
+    let uri: hyper::Uri = url.parse()?;
+
+    let client = Arc::new(Client::new().await?);
+    let next = Arc::new(AtomicUsize::new(0));
+    let results = Arc::new(Mutex::new(Vec::with_capacity(requests)));
+
+    let started = Instant::now();
+    let workers: Vec<_> = (0..concurrency.max(1))
+        .map(|_| {
+            let (client, next, results, uri) =
+                (client.clone(), next.clone(), results.clone(), uri.clone());
+            tokio::spawn(async move {
+                while next.fetch_add(1, Ordering::Relaxed) < requests {
+                    let sent = Instant::now();
+                    let outcome = request(&client, uri.clone()).await;
+                    results.lock().unwrap().push((sent.elapsed(), outcome));
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.await?;
+    }
+    let elapsed = started.elapsed();
+
+    let results = std::mem::take(&mut *results.lock().unwrap());
+    report(&results, elapsed);
+    Ok(())
+}
+
+async fn request(client: &Client, uri: hyper::Uri) -> Outcome {
+    let result = async {
+        let resp = client.get(uri).await?;
+        let status = resp.status().as_u16();
+        let body = resp
+            .into_body()
+            .collect()
+            .await
+            .map_err(ConnectionError::new)?
+            .to_bytes();
+        Ok::<_, hypertor::Error>((status, body.len()))
+    }
+    .await;
+
+    match result {
+        Ok((status, bytes)) => Outcome::Response { status, bytes },
+        Err(e) => {
+            let circuit = e
+                .connection_error()
+                .is_some_and(|e| e.kind() == ConnectionErrorKind::CircuitClosed);
+            if circuit {
+                Outcome::CircuitFailure
+            } else {
+                Outcome::Error
+            }
+        }
+    }
+}
+
+fn report(results: &[(Duration, Outcome)], elapsed: Duration) {
+    let mut latencies: Vec<Duration> = Vec::new();
+    let (mut non_2xx, mut circuit_failures, mut errors, mut bytes) = (0, 0, 0, 0);
+    for (latency, outcome) in results {
+        match outcome {
+            Outcome::Response { status, bytes: n } => {
+                latencies.push(*latency);
+                bytes += n;
+                if !(200..300).contains(status) {
+                    non_2xx += 1;
+                }
+            }
+            Outcome::CircuitFailure => circuit_failures += 1,
+            Outcome::Error => errors += 1,
+        }
+    }
+    latencies.sort();
+
+    let secs = elapsed.as_secs_f64();
+    println!("requests:          {}", results.len());
+    println!("completed:         {}", latencies.len());
+    println!("non-2xx responses: {non_2xx}");
+    println!("circuit failures:  {circuit_failures}");
+    println!("other errors:      {errors}");
+    println!("time taken:        {secs:.2} s");
+    println!("requests/s:        {:.2}", latencies.len() as f64 / secs);
+    println!(
+        "transfer rate:     {:.2} KiB/s",
+        bytes as f64 / 1024.0 / secs
+    );
+    if latencies.is_empty() {
+        return;
+    }
+    for percentile in [50, 90, 99] {
+        let index = (latencies.len() * percentile).div_ceil(100).max(1) - 1;
+        println!("p{percentile}:               {:?}", latencies[index]);
+    }
+    println!("max:               {:?}", latencies[latencies.len() - 1]);
+}
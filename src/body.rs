@@ -0,0 +1,600 @@
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+use crate::common::{DecodeLimits, DecodedBody};
+use crate::error::{Error, Result};
+use crate::pool::Checkin;
+use crate::scheduler::Permit;
+use crate::traffic::Traffic;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::{Body, Bytes, Frame, Incoming, SizeHint};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::oneshot;
+use tokio::time::{Instant, Sleep};
+
+/// Size of the chunks a [`ReplayableBody::from_reader`] body is read in.
+const READER_CHUNK: usize = 64 * 1024;
+
+/// A connection future driven by the response body instead of a spawned task.
+pub(crate) type Connection = Pin<Box<dyn Future<Output = hyper::Result<()>> + Send>>;
+
+/// The body of a response returned by [`Client`](crate::Client).
+///
+/// Most responses stream straight from the connection, but some (for example
+/// pages inspected for challenges) have already been read into memory.
+///
+/// Errors are [`hyper::Error`]s from the connection, or a
+/// [`DecompressionBomb`](crate::DecompressionBomb) or I/O error while a
+/// compressed body is decoded.
+pub struct ResponseBody {
+    inner: Inner,
+    // Keeps the request's scheduler slots until the body is dropped.
+    permits: Vec<Permit>,
+    // Returns the connection to the pool once the body has been read.
+    checkin: Option<Box<Checkin>>,
+    // Counts the frames read for the exchange's tracing span.
+    traffic: Option<Arc<Traffic>>,
+    // Fails the body once it takes too long.
+    timers: Option<Box<Timers>>,
+}
+
+/// The error a [`ResponseBody`] fails with once it has taken too long.
+#[derive(Debug)]
+pub(crate) enum BodyTimeout {
+    /// No data arrived for the read timeout.
+    Read(Duration),
+    /// The total timeout of the request ran out.
+    Total(Duration),
+}
+
+impl BodyTimeout {
+    /// Returns the timeout that ran out.
+    pub(crate) fn duration(&self) -> Duration {
+        match self {
+            BodyTimeout::Read(timeout) | BodyTimeout::Total(timeout) => *timeout,
+        }
+    }
+}
+
+impl std::fmt::Display for BodyTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyTimeout::Read(timeout) => write!(f, "no data arrived for {timeout:?}"),
+            BodyTimeout::Total(timeout) => {
+                write!(f, "request did not complete within {timeout:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BodyTimeout {}
+
+/// The deadlines a [`ResponseBody`] is read under.
+struct Timers {
+    read_timeout: Option<Duration>,
+    // Armed while the body waits for data.
+    idle: Option<Pin<Box<Sleep>>>,
+    total: Option<(Duration, Pin<Box<Sleep>>)>,
+}
+
+impl Timers {
+    /// Passes `poll` on, or fails the body once a deadline has passed.
+    fn watch(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: Poll<Option<Result<Frame<Bytes>, BoxError>>>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        if let Some((timeout, deadline)) = &mut self.total {
+            if deadline.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Some(Err(BodyTimeout::Total(*timeout).into())));
+            }
+        }
+        if poll.is_ready() {
+            self.idle = None;
+            return poll;
+        }
+        if let Some(timeout) = self.read_timeout {
+            let idle = self
+                .idle
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+            if idle.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Some(Err(BodyTimeout::Read(timeout).into())));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+enum Inner {
+    Incoming(Incoming),
+    Buffered(Full<Bytes>),
+    /// Streams from a connection that is polled whenever the body is.
+    Driven {
+        incoming: Incoming,
+        // Only ever accessed through `&mut`; the mutex keeps the body `Sync`.
+        connection: Option<Mutex<Connection>>,
+    },
+    /// Decodes a content-coded body.
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    Decoded(Box<DecodedBody<ResponseBody>>),
+}
+
+impl ResponseBody {
+    /// Creates a body from bytes that were already read.
+    pub(crate) fn buffered(bytes: Bytes) -> Self {
+        ResponseBody {
+            inner: Inner::Buffered(Full::new(bytes)),
+            permits: Vec::new(),
+            checkin: None,
+            traffic: None,
+            timers: None,
+        }
+    }
+
+    /// Creates a body that drives its connection while it is read.
+    pub(crate) fn driven(incoming: Incoming, connection: Connection) -> Self {
+        ResponseBody {
+            inner: Inner::Driven {
+                incoming,
+                connection: Some(Mutex::new(connection)),
+            },
+            permits: Vec::new(),
+            checkin: None,
+            traffic: None,
+            timers: None,
+        }
+    }
+
+    /// Ties scheduler slots to the lifetime of this body.
+    pub(crate) fn with_permits(mut self, permits: Vec<Permit>) -> Self {
+        self.permits = permits;
+        self
+    }
+
+    /// Decodes this body, the body of a response with `Content-Encoding`.
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    pub(crate) fn decoded(
+        response: hyper::Response<Self>,
+        limits: DecodeLimits,
+    ) -> hyper::Response<Self> {
+        // Responses to HEAD requests describe an encoding they do not carry.
+        if response.body().is_end_stream() {
+            return response;
+        }
+        match DecodedBody::from_response(response, limits) {
+            Ok(response) => response.map(|body| ResponseBody {
+                inner: Inner::Decoded(Box::new(body)),
+                permits: Vec::new(),
+                checkin: None,
+                traffic: None,
+                timers: None,
+            }),
+            Err(response) => response,
+        }
+    }
+
+    /// Counts the frames of this body into `traffic`.
+    pub(crate) fn with_traffic(mut self, traffic: Option<Arc<Traffic>>) -> Self {
+        self.traffic = traffic;
+        self
+    }
+
+    /// Fails reading this body once no data has arrived for `read_timeout`,
+    /// or once the deadline of a request's total timeout has passed.
+    pub(crate) fn with_timeouts(
+        mut self,
+        read_timeout: Option<Duration>,
+        total: Option<(Duration, Instant)>,
+    ) -> Self {
+        if read_timeout.is_none() && total.is_none() {
+            return self;
+        }
+        self.timers = Some(Box::new(Timers {
+            read_timeout,
+            idle: None,
+            total: total
+                .map(|(timeout, deadline)| (timeout, Box::pin(tokio::time::sleep_until(deadline)))),
+        }));
+        self
+    }
+
+    /// Returns the connection to its pool once this body has been read.
+    pub(crate) fn with_checkin(mut self, checkin: Checkin) -> Self {
+        self.checkin = Some(Box::new(checkin));
+        self
+    }
+
+    fn poll_inner(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        match &mut self.inner {
+            Inner::Incoming(incoming) => Pin::new(incoming).poll_frame(cx).map_err(Into::into),
+            Inner::Buffered(full) => Pin::new(full)
+                .poll_frame(cx)
+                .map_err(|never| match never {}),
+            #[cfg(any(
+                feature = "gzip",
+                feature = "deflate",
+                feature = "brotli",
+                feature = "zstd"
+            ))]
+            Inner::Decoded(decoded) => Pin::new(decoded.as_mut()).poll_frame(cx),
+            Inner::Driven {
+                incoming,
+                connection,
+            } => {
+                if let Some(conn) = connection {
+                    let conn = conn.get_mut().unwrap_or_else(|e| e.into_inner());
+                    if let Poll::Ready(result) = conn.as_mut().poll(cx) {
+                        *connection = None;
+                        if let Err(e) = result {
+                            return Poll::Ready(Some(Err(e.into())));
+                        }
+                    }
+                }
+                Pin::new(incoming).poll_frame(cx).map_err(Into::into)
+            }
+        }
+    }
+}
+
+impl Drop for ResponseBody {
+    fn drop(&mut self) {
+        // A body dropped before its end leaves the connection unusable.
+        if self.is_end_stream() {
+            if let Some(checkin) = self.checkin.take() {
+                checkin.run();
+            }
+        }
+    }
+}
+
+impl From<Incoming> for ResponseBody {
+    fn from(incoming: Incoming) -> Self {
+        ResponseBody {
+            inner: Inner::Incoming(incoming),
+            permits: Vec::new(),
+            checkin: None,
+            traffic: None,
+            timers: None,
+        }
+    }
+}
+
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = self.poll_inner(cx);
+        let poll = match &mut self.timers {
+            Some(timers) => timers.watch(cx, poll),
+            None => poll,
+        };
+        if let (Poll::Ready(Some(Ok(frame))), Some(traffic)) = (&poll, &self.traffic) {
+            if let Some(data) = frame.data_ref() {
+                traffic.received(data.len());
+            }
+        }
+        if matches!(poll, Poll::Ready(None)) || self.is_end_stream() {
+            if let Some(checkin) = self.checkin.take() {
+                checkin.run();
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.inner {
+            Inner::Incoming(incoming) | Inner::Driven { incoming, .. } => incoming.is_end_stream(),
+            Inner::Buffered(full) => full.is_end_stream(),
+            #[cfg(any(
+                feature = "gzip",
+                feature = "deflate",
+                feature = "brotli",
+                feature = "zstd"
+            ))]
+            Inner::Decoded(decoded) => decoded.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            Inner::Incoming(incoming) | Inner::Driven { incoming, .. } => incoming.size_hint(),
+            Inner::Buffered(full) => full.size_hint(),
+            #[cfg(any(
+                feature = "gzip",
+                feature = "deflate",
+                feature = "brotli",
+                feature = "zstd"
+            ))]
+            Inner::Decoded(decoded) => decoded.size_hint(),
+        }
+    }
+}
+
+pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A request body that can be sent more than once, as retries, solved
+/// challenges and `307`/`308` redirects require.
+///
+/// Each clone reads the body from the start. The body is either held in
+/// memory or recreated by a factory, such as one that reopens a file.
+pub struct ReplayableBody {
+    source: Source,
+    current: Option<BoxBody<Bytes, BoxError>>,
+}
+
+#[derive(Clone)]
+enum Source {
+    Full(Full<Bytes>),
+    // With the length of every body the factory makes, if known.
+    Factory(
+        Arc<dyn Fn() -> BoxBody<Bytes, BoxError> + Send + Sync>,
+        Option<u64>,
+    ),
+}
+
+impl ReplayableBody {
+    /// Creates an empty body.
+    pub fn empty() -> Self {
+        Self::from(Bytes::new())
+    }
+
+    /// Creates a body that calls `factory` for every attempt.
+    pub fn from_fn<F, B>(factory: F) -> Self
+    where
+        F: Fn() -> B + Send + Sync + 'static,
+        B: Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<BoxError>,
+    {
+        ReplayableBody {
+            source: Source::Factory(
+                Arc::new(move || factory().map_err(Into::into).boxed()),
+                None,
+            ),
+            current: None,
+        }
+    }
+
+    /// Creates a body streamed from `reader` until it reaches EOF, sent with
+    /// chunked transfer encoding.
+    ///
+    /// The reader can be read only once: sending the body again, as retries,
+    /// solved challenges and `307`/`308` redirects do, fails instead. Use
+    /// [`from_fn`](Self::from_fn) with a factory that reopens the source
+    /// where those have to work.
+    pub fn from_reader<R>(reader: R) -> Self
+    where
+        R: AsyncRead + Send + Sync + 'static,
+    {
+        let reader = Mutex::new(Some(reader));
+        Self::from_fn(move || ReaderBody {
+            reader: reader.lock().unwrap().take().map(Box::pin),
+            buf: vec![0; READER_CHUNK].into_boxed_slice(),
+            done: false,
+        })
+    }
+
+    /// Like [`from_fn`](Self::from_fn), for a factory whose bodies are all
+    /// `len` bytes long, so that the length can be sent up front.
+    pub(crate) fn from_fn_with_len<F, B>(factory: F, len: u64) -> Self
+    where
+        F: Fn() -> B + Send + Sync + 'static,
+        B: Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<BoxError>,
+    {
+        let mut body = Self::from_fn(factory);
+        if let Source::Factory(_, known) = &mut body.source {
+            *known = Some(len);
+        }
+        body
+    }
+
+    /// Reads `body` into memory so it can be replayed, failing if it is
+    /// larger than `limit` bytes.
+    pub async fn buffer<B>(body: B, limit: usize) -> Result<Self>
+    where
+        B: Body<Data = Bytes>,
+        B::Error: Into<BoxError>,
+    {
+        let bytes = Limited::new(body, limit)
+            .collect()
+            .await
+            .map_err(|e| match e.downcast::<LengthLimitError>() {
+                Ok(_) => Error::InvalidRequest(format!("body is larger than {limit} bytes")),
+                Err(e) => Error::Other(e),
+            })?
+            .to_bytes();
+        Ok(Self::from(bytes))
+    }
+
+    /// Returns whether the body is held in memory rather than recreated by
+    /// a factory.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub(crate) fn is_buffered(&self) -> bool {
+        matches!(self.source, Source::Full(_))
+    }
+
+    fn current(&mut self) -> &mut BoxBody<Bytes, BoxError> {
+        let source = &self.source;
+        self.current.get_or_insert_with(|| match source {
+            Source::Full(full) => full.clone().map_err(|never| match never {}).boxed(),
+            Source::Factory(factory, _) => factory(),
+        })
+    }
+}
+
+impl Clone for ReplayableBody {
+    fn clone(&self) -> Self {
+        ReplayableBody {
+            source: self.source.clone(),
+            current: None,
+        }
+    }
+}
+
+impl From<Bytes> for ReplayableBody {
+    fn from(bytes: Bytes) -> Self {
+        Self::from(Full::new(bytes))
+    }
+}
+
+impl From<Full<Bytes>> for ReplayableBody {
+    fn from(full: Full<Bytes>) -> Self {
+        ReplayableBody {
+            source: Source::Full(full),
+            current: None,
+        }
+    }
+}
+
+impl Body for ReplayableBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(self.current()).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match (&self.current, &self.source) {
+            (Some(current), _) => current.is_end_stream(),
+            (None, Source::Full(full)) => full.is_end_stream(),
+            (None, Source::Factory(_, len)) => *len == Some(0),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match (&self.current, &self.source) {
+            (Some(current), _) => current.size_hint(),
+            (None, Source::Full(full)) => full.size_hint(),
+            (None, Source::Factory(_, Some(len))) => SizeHint::with_exact(*len),
+            (None, Source::Factory(_, None)) => SizeHint::default(),
+        }
+    }
+}
+
+/// A request body read from an `AsyncRead` by
+/// [`ReplayableBody::from_reader`]; `reader` is gone if it was read before.
+struct ReaderBody<R> {
+    reader: Option<Pin<Box<R>>>,
+    buf: Box<[u8]>,
+    done: bool,
+}
+
+impl<R: AsyncRead> Body for ReaderBody<R> {
+    type Data = Bytes;
+    type Error = std::io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, std::io::Error>>> {
+        let this = &mut *self;
+        if this.done {
+            return Poll::Ready(None);
+        }
+        let Some(reader) = &mut this.reader else {
+            this.done = true;
+            return Poll::Ready(Some(Err(std::io::Error::other(
+                "request body read from a stream cannot be sent again",
+            ))));
+        };
+        let mut buf = ReadBuf::new(&mut this.buf);
+        match reader.as_mut().poll_read(cx, &mut buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => {
+                this.done = true;
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(Ok(())) if buf.filled().is_empty() => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Ready(Ok(())) => {
+                Poll::Ready(Some(Ok(Frame::data(Bytes::copy_from_slice(buf.filled())))))
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+}
+
+/// A request body that reports when its last frame has been handed to hyper.
+pub(crate) struct SignalOnEnd<B> {
+    inner: Pin<Box<B>>,
+    ended: Option<oneshot::Sender<()>>,
+}
+
+impl<B: Body> SignalOnEnd<B> {
+    /// Wraps `body`; the receiver completes once the body has been fully read.
+    pub(crate) fn new(body: B) -> (Self, oneshot::Receiver<()>) {
+        let (tx, rx) = oneshot::channel();
+        let mut body = SignalOnEnd {
+            inner: Box::pin(body),
+            ended: Some(tx),
+        };
+        if body.inner.is_end_stream() {
+            body.signal();
+        }
+        (body, rx)
+    }
+
+    fn signal(&mut self) {
+        if let Some(tx) = self.ended.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+impl<B: Body> Body for SignalOnEnd<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = self.inner.as_mut().poll_frame(cx);
+        match &poll {
+            Poll::Ready(None) => self.signal(),
+            Poll::Ready(Some(Ok(_))) if self.inner.is_end_stream() => self.signal(),
+            _ => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,35 @@
+use crate::common::{CircuitInfo, RelayInfo};
+use arti_client::DataStream;
+use tor_linkspec::{HasAddrs, HasRelayIds};
+use tor_proto::circuit::ClientCirc;
+
+/// Describes the circuit `stream` was opened on, if it is still open.
+pub(crate) fn circuit_info(stream: &DataStream) -> Option<CircuitInfo> {
+    let circuit = stream.client_stream_ctrl()?.circuit()?;
+    Some(describe_circuit(&circuit))
+}
+
+/// Describes the relays `circuit` was built through.
+pub(crate) fn describe_circuit(circuit: &ClientCirc) -> CircuitInfo {
+    let path = circuit.path_ref();
+    let mut relays = Vec::new();
+    let mut onion_service = false;
+    for hop in path.hops() {
+        // Hops added by the onion service handshake are not relays.
+        let Some(relay) = hop.as_chan_target() else {
+            onion_service = true;
+            continue;
+        };
+        relays.push(RelayInfo {
+            fingerprint: relay
+                .rsa_identity()
+                .map(|id| id.as_bytes().iter().map(|b| format!("{b:02X}")).collect()),
+            ed_identity: relay.ed_identity().map(ToString::to_string),
+            addrs: relay.addrs().to_vec(),
+        });
+    }
+    CircuitInfo {
+        relays,
+        onion_service,
+    }
+}
@@ -0,0 +1,2116 @@
+use crate::body::{Connection, ReplayableBody, ResponseBody, SignalOnEnd};
+use crate::circuit::circuit_info;
+#[cfg(feature = "exclude-exits")]
+use crate::circuit::describe_circuit;
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+use crate::common::accept_encoding;
+#[cfg(feature = "json")]
+use crate::common::SessionState;
+#[cfg(feature = "native-tls")]
+use crate::common::TlsInfo;
+use crate::common::{
+    announcements, authorization, challenges, host_header, is_onion, origin_of, redirect_target,
+    scope_redirect, with_host, with_origin, Challenge, ChallengeSolution, CircuitInfo, ClockSkew,
+    ConnectionDriver, ExitCountry, OnionRoute, Priority, ProxyHeader, RateLimit, ReadBuffer,
+    RedirectChain, RedirectHop, RedirectPolicy, RetryPolicy,
+};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::common::{encode, request_coding, ContentEncoding};
+#[cfg(feature = "exclude-exits")]
+use crate::common::{is_local_host, RelayInfo};
+use crate::config::{ClientConfig, ClientConfigBuilder};
+use crate::error::{ConnectionError, ConnectionErrorKind, Error, Result};
+use crate::exits::ExitSelector;
+#[cfg(feature = "chaos")]
+use crate::faults::FaultInjector;
+use crate::hooks::{ConnectTarget, ConnectedStream};
+use crate::onion_routes::OnionRoutes;
+use crate::pool::{Checkin, Pool, PoolBody, PoolKey, PooledConnection, StreamInfo};
+use crate::retry::RetryLimiter;
+use crate::scheduler::{AdaptiveLimiter, Scheduler};
+use crate::stall::StallGuard;
+use crate::tap::Tapped;
+use crate::traffic::{Counted, Traffic};
+use crate::usage::{Metered, Usage, UsageLimiter};
+use arti_client::{BootstrapBehavior, IsolationToken, StreamPrefs, TorClient};
+#[cfg(feature = "exclude-exits")]
+use arti_client::{DataStream, TorAddr};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Body, Bytes, Incoming};
+use hyper::client::conn::http2;
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_LANGUAGE, CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH,
+    CONTENT_TYPE, COOKIE, SET_COOKIE, USER_AGENT,
+};
+use hyper::http::request::Parts;
+use hyper::http::uri::{Authority, Scheme};
+use hyper::{Method, Request, Response, Uri, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io::Error as IoError;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+#[cfg(feature = "exclude-exits")]
+use tor_circmgr::isolation::StreamIsolation;
+#[cfg(feature = "exclude-exits")]
+use tor_circmgr::TargetPort;
+#[cfg(feature = "exclude-exits")]
+use tor_proto::stream::StreamParameters;
+use tor_rtcompat::PreferredRuntime;
+use tracing::Instrument;
+
+/// Upper bound on the size of a response body buffered for challenge detection.
+const MAX_CHALLENGE_BODY: usize = 1024 * 1024;
+/// Shortest interval between the requests keeping a hot host's connection
+/// open.
+const MIN_WARM_PERIOD: Duration = Duration::from_secs(1);
+/// Longest a request is held back for an exhausted rate limit.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(300);
+/// Circuits tried before giving up on finding one that avoids the excluded
+/// exits.
+#[cfg(feature = "exclude-exits")]
+const MAX_EXCLUDED_EXITS: usize = 5;
+
+/// A trait for types that implement both `AsyncRead` and `AsyncWrite`.
+pub trait AsyncReadWrite: AsyncRead + AsyncWrite {}
+
+impl<T> AsyncReadWrite for T where T: AsyncRead + AsyncWrite {}
+
+/// A client for making HTTP requests over Tor with optional TLS.
+///
+/// Cloning a `Client` is cheap: clones share the Tor client, connections,
+/// configuration and learned state.
+#[derive(Clone)]
+pub struct Client {
+    pub(crate) inner: Arc<Inner>,
+}
+
+/// The state shared by the clones of a [`Client`].
+pub(crate) struct Inner {
+    pub(crate) tor_client: TorClient<PreferredRuntime>,
+    pub(crate) config: ClientConfig,
+    clearances: Mutex<HashMap<String, HeaderMap>>,
+    // When each rate-limited origin accepts requests again.
+    paced: Mutex<HashMap<String, Instant>>,
+    // The coding each origin accepts in request bodies.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    request_codings: Mutex<HashMap<String, ContentEncoding>>,
+    scheduler: Option<Arc<Scheduler>>,
+    adaptive: Option<AdaptiveLimiter>,
+    exit_selector: Option<ExitSelector>,
+    retry_limiter: Option<RetryLimiter>,
+    pool: Arc<Pool>,
+    usage_limiter: Option<Arc<UsageLimiter>>,
+    // Isolation of requests without their own token since `new_identity`.
+    identity: Mutex<Option<IsolationToken>>,
+    onion_routes: Option<OnionRoutes>,
+    // Places exits in countries, loaded if any are excluded.
+    #[cfg(all(feature = "geoip", feature = "exclude-exits"))]
+    geoip: Option<Arc<tor_geoip::GeoipDb>>,
+    #[cfg(feature = "chaos")]
+    faults: FaultInjector,
+    // Tasks keeping connections to hot hosts open.
+    warmers: Mutex<Vec<JoinHandle<()>>>,
+    // Owner of the circuits taken from arti's circuit manager, keeping them
+    // to this client as arti does for its own streams.
+    #[cfg(feature = "exclude-exits")]
+    owner_isolation: IsolationToken,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        for warmer in self.warmers.get_mut().unwrap().drain(..) {
+            warmer.abort();
+        }
+    }
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("config", &self.inner.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What to do with a response after challenge inspection.
+enum Challenged {
+    Done(Response<ResponseBody>),
+    Retry(ChallengeSolution),
+}
+
+impl Client {
+    /// Creates a new `Client` with the provided `ClientConfig`.
+    pub async fn with_config(config: ClientConfig) -> Result<Self> {
+        let tor_client = Self::create_tor_client(&config)
+            .await
+            .map_err(|e| Error::Bootstrap(e.into()))?;
+        let scheduler = config.max_concurrent_requests.map(Scheduler::new);
+        let adaptive = config
+            .adaptive_concurrency
+            .clone()
+            .map(AdaptiveLimiter::new);
+        let exit_selector = config.fast_exit_selection.clone().map(ExitSelector::new);
+        let retry_limiter = config.retry_budget.clone().map(RetryLimiter::new);
+        let onion_routes = if config.learn_onion_routes {
+            let routes = OnionRoutes::open(config.onion_route_store.clone())
+                .map_err(|e| Error::Other(e.into()))?;
+            Some(routes)
+        } else {
+            None
+        };
+        let usage_limiter = config
+            .usage_caps
+            .clone()
+            .map(|caps| Arc::new(UsageLimiter::new(caps)));
+        let pool = Arc::new(Pool::new(
+            config.pool_max_idle_per_host,
+            config.pool_idle_timeout,
+            usage_limiter.clone(),
+        ));
+        #[cfg(feature = "chaos")]
+        let faults = FaultInjector::new(config.fault_injection.clone());
+        #[cfg(all(feature = "geoip", feature = "exclude-exits"))]
+        let geoip = (!config.exclude_countries.is_empty()).then(tor_geoip::GeoipDb::new_embedded);
+        let client = Client {
+            inner: Arc::new(Inner {
+                tor_client,
+                config,
+                clearances: Mutex::new(HashMap::new()),
+                paced: Mutex::new(HashMap::new()),
+                #[cfg(any(feature = "gzip", feature = "zstd"))]
+                request_codings: Mutex::new(HashMap::new()),
+                scheduler,
+                adaptive,
+                exit_selector,
+                retry_limiter,
+                pool,
+                usage_limiter,
+                identity: Mutex::new(None),
+                onion_routes,
+                #[cfg(all(feature = "geoip", feature = "exclude-exits"))]
+                geoip,
+                #[cfg(feature = "chaos")]
+                faults,
+                warmers: Mutex::new(Vec::new()),
+                #[cfg(feature = "exclude-exits")]
+                owner_isolation: IsolationToken::new(),
+            }),
+        };
+        let warmers = client
+            .inner
+            .config
+            .warm_origins
+            .iter()
+            .map(|origin| tokio::spawn(keep_warm(Arc::downgrade(&client.inner), origin.clone())))
+            .collect();
+        *client.inner.warmers.lock().unwrap() = warmers;
+        if let Some(hook) = &client.inner.config.sandbox_hook {
+            hook.install(&client.inner.config.sandbox_requirements())
+                .map_err(|e| Error::Other(e.into()))?;
+        }
+        Ok(client)
+    }
+
+    /// Creates a new `Client` with default configuration.
+    pub async fn new() -> Result<Self> {
+        let default_config = ClientConfigBuilder::new().build()?;
+        Self::with_config(default_config).await
+    }
+
+    /// Creates a Tor client using the given configuration.
+    ///
+    /// Clients with a custom connector never use Tor, so it is not
+    /// bootstrapped.
+    async fn create_tor_client(
+        config: &ClientConfig,
+    ) -> Result<TorClient<PreferredRuntime>, arti_client::Error> {
+        if config.connector.is_some() {
+            let tor_client = TorClient::builder()
+                .config(config.tor_config.clone())
+                .bootstrap_behavior(BootstrapBehavior::Manual)
+                .create_unbootstrapped()?;
+            return Ok(tor_client);
+        }
+        let tor_client = TorClient::create_bootstrapped(config.tor_config.clone()).await?;
+        Ok(tor_client)
+    }
+
+    /// Exports the client's session state as an opaque blob.
+    ///
+    /// The snapshot holds persisted challenge clearances per origin, the
+    /// cookies of the configured [`CookieStore`](crate::CookieStore) if it
+    /// can list them, and the learned onion routes (`Onion-Location` and
+    /// `Alt-Svc`). It can be restored with [`Client::import_state`] after a
+    /// restart. The client keeps no HSTS list or HTTP cache, so there are no
+    /// such entries to include.
+    #[cfg(feature = "json")]
+    pub fn export_state(&self) -> Result<Vec<u8>> {
+        let cookies = self
+            .inner
+            .config
+            .cookie_store
+            .as_ref()
+            .and_then(|store| store.all_cookies());
+        let onion_routes = self
+            .inner
+            .onion_routes
+            .as_ref()
+            .map(OnionRoutes::expiring_routes)
+            .unwrap_or_default();
+        SessionState::capture(
+            &self.inner.clearances.lock().unwrap(),
+            cookies,
+            onion_routes,
+        )
+        .to_bytes()
+    }
+
+    /// Restores session state previously produced by [`Client::export_state`],
+    /// replacing the current state.
+    ///
+    /// Cookies are restored into the configured cookie store, and onion
+    /// routes only if [`ClientConfigBuilder::learn_onion_routes`] is
+    /// enabled. A snapshot without cookies leaves the store as it is.
+    #[cfg(feature = "json")]
+    pub fn import_state(&self, state: &[u8]) -> Result<()> {
+        let state = SessionState::from_bytes(state)?;
+        let clearances = state.clearances()?;
+        *self.inner.clearances.lock().unwrap() = clearances;
+        if let (Some(store), Some(cookies)) = (&self.inner.config.cookie_store, state.cookies()) {
+            store.replace_cookies(cookies);
+        }
+        if let Some(routes) = &self.inner.onion_routes {
+            routes.replace(state.onion_routes());
+        }
+        Ok(())
+    }
+
+    /// Sends an HTTP HEAD request to the specified URI.
+    pub async fn head<T>(&self, uri: T) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::head(uri).body(Full::<Bytes>::default())?;
+
+        let resp = self.send_request(req).await?;
+        Ok(resp)
+    }
+
+    /// Like [`head`](Self::head), giving up as
+    /// [`get_with_timeout`](Self::get_with_timeout) does.
+    pub async fn head_with_timeout<T>(
+        &self,
+        uri: T,
+        timeout: Duration,
+    ) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        self.request(Method::HEAD, uri)
+            .timeout(timeout)
+            .send()
+            .await
+    }
+
+    /// Sends an HTTP GET request to the specified URI.
+    pub async fn get<T>(&self, uri: T) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::get(uri).body(Full::<Bytes>::default())?;
+
+        let resp = self.send_request(req).await?;
+        Ok(resp)
+    }
+
+    /// Like [`get`](Self::get), failing with [`Error::Timeout`] unless the
+    /// response headers arrive within `timeout`.
+    ///
+    /// The timeout covers opening the circuit and stream, the TLS handshake,
+    /// redirects and retries. Other requests take a timeout through
+    /// [`RequestBuilder::timeout`](crate::RequestBuilder::timeout).
+    pub async fn get_with_timeout<T>(
+        &self,
+        uri: T,
+        timeout: Duration,
+    ) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        self.request(Method::GET, uri).timeout(timeout).send().await
+    }
+
+    /// Sends an HTTP OPTIONS request to the specified URI.
+    pub async fn options<T>(&self, uri: T) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::options(uri).body(Full::<Bytes>::default())?;
+
+        let resp = self.send_request(req).await?;
+        Ok(resp)
+    }
+
+    /// Sends an HTTP TRACE request to the specified URI.
+    ///
+    /// Exit relays and other intermediaries may show up in the echoed request.
+    pub async fn trace<T>(&self, uri: T) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::trace(uri).body(Full::<Bytes>::default())?;
+
+        let resp = self.send_request(req).await?;
+        Ok(resp)
+    }
+
+    /// Sends an HTTP POST request to the specified URI with the given content type and body.
+    pub async fn post<T>(
+        &self,
+        uri: T,
+        content_type: &str,
+        body: Bytes,
+    ) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::post(uri)
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(Full::<Bytes>::from(body))?;
+
+        let resp = self.send_request(req).await?;
+        Ok(resp)
+    }
+
+    /// Sends an HTTP PUT request to the specified URI with the given content type and body.
+    pub async fn put<T>(
+        &self,
+        uri: T,
+        content_type: &str,
+        body: Bytes,
+    ) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::put(uri)
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(Full::<Bytes>::from(body))?;
+
+        let resp = self.send_request(req).await?;
+        Ok(resp)
+    }
+
+    /// Sends an HTTP PATCH request to the specified URI with the given content type and body.
+    pub async fn patch<T>(
+        &self,
+        uri: T,
+        content_type: &str,
+        body: Bytes,
+    ) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::patch(uri)
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(Full::<Bytes>::from(body))?;
+
+        let resp = self.send_request(req).await?;
+        Ok(resp)
+    }
+
+    /// Sends an HTTP DELETE request to the specified URI.
+    pub async fn delete<T>(&self, uri: T) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::delete(uri).body(Full::<Bytes>::default())?;
+
+        let resp = self.send_request(req).await?;
+        Ok(resp)
+    }
+
+    /// Sends a request with any method, including extension methods such as
+    /// `PROPFIND` or `REPORT`, to the specified URI with the given body.
+    ///
+    /// Use [`Method::from_bytes`] to create an extension method.
+    pub async fn request_with_method<T>(
+        &self,
+        method: Method,
+        uri: T,
+        body: Bytes,
+    ) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Full::<Bytes>::from(body))?;
+
+        let resp = self.send_request(req).await?;
+        Ok(resp)
+    }
+
+    /// Connects to an HTTPS URI and returns the DER-encoded certificates the
+    /// server presented, leaf first, without sending a request. native-tls
+    /// only exposes the leaf certificate; with a TLS session cache, the whole
+    /// chain is returned.
+    ///
+    /// The handshake is verified with the configured TLS connector; use one
+    /// that accepts invalid certificates to inspect self-signed onion sites.
+    pub async fn peer_certificates<T>(&self, uri: T) -> Result<Vec<Vec<u8>>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let mut uri = Uri::try_from(uri).map_err(Into::<hyper::http::Error>::into)?;
+        if let Some(aliased) = self.aliased(&uri) {
+            uri = aliased;
+        }
+        if uri.scheme() != Some(&Scheme::HTTPS) {
+            return Err(Error::InvalidRequest(
+                "peer certificates require an https URI".into(),
+            ));
+        }
+
+        let exit_country = self.exit_country(&uri, None);
+        let (_, info) = self.create_stream(&uri, None, exit_country, None).await?;
+        Ok(info
+            .tls
+            .map(|info| info.peer_certificates)
+            .unwrap_or_default())
+    }
+
+    /// Sends an HTTP request and returns the response, solving server
+    /// challenges and retrying along the way.
+    ///
+    /// A [`Priority`] in the request's extensions controls its place in the
+    /// queue when [`ClientConfigBuilder::max_concurrent_requests`] is set. An
+    /// [`IsolationToken`] in the extensions sends the request over a circuit
+    /// isolated to that token.
+    ///
+    /// Any method is accepted, including extension methods such as WebDAV's
+    /// `PROPFIND`, which may carry a body like `POST` does. The body is
+    /// resent whenever the request is repeated; pass a [`ReplayableBody`]
+    /// to stream one from a factory.
+    ///
+    /// A `Host` header set on the request is sent unchanged, so a virtual host
+    /// other than the connected authority can be requested; TLS still uses
+    /// the URI's host for SNI and verification. Otherwise `Host` is taken from
+    /// the URI, omitting the scheme's default port.
+    ///
+    /// The request is sent with its HTTP version, HTTP/1.1 unless set
+    /// otherwise; HTTP/1.0 can be chosen for servers that mishandle 1.1.
+    /// HTTP/2 is spoken with prior knowledge (h2c) to onion services over
+    /// plain HTTP, where Tor already encrypts the connection, and
+    /// concurrent requests share one connection.
+    ///
+    /// Responses to HTTPS requests carry a [`TlsInfo`](crate::TlsInfo) in
+    /// their extensions, and responses received through arti a
+    /// [`CircuitInfo`].
+    pub async fn send_request<B>(&self, req: Request<B>) -> Result<Response<ResponseBody>>
+    where
+        B: Into<ReplayableBody>,
+    {
+        let config = &self.inner.config;
+        let total = config
+            .total_request_timeout
+            .map(|timeout| (timeout, tokio::time::Instant::now() + timeout));
+        let resp = match total {
+            Some((timeout, deadline)) => {
+                tokio::time::timeout_at(deadline, self.follow_redirects(req))
+                    .await
+                    .map_err(|_| Error::Timeout(timeout))??
+            }
+            None => self.follow_redirects(req).await?,
+        };
+        Ok(resp.map(|body| body.with_timeouts(config.read_timeout, total)))
+    }
+
+    /// Sends a request, following redirects as the redirect policy allows.
+    async fn follow_redirects<B>(&self, req: Request<B>) -> Result<Response<ResponseBody>>
+    where
+        B: Into<ReplayableBody>,
+    {
+        let (mut parts, body) = req.into_parts();
+        let mut body = body.into();
+        if let Some(uri) = self.aliased(&parts.uri) {
+            parts.uri = uri;
+        }
+
+        if parts.method == hyper::Method::TRACE && !body.is_end_stream() {
+            return Err(Error::InvalidRequest(
+                "TRACE requests must not have a body".into(),
+            ));
+        }
+        if let Some(limiter) = &self.inner.retry_limiter {
+            limiter.deposit();
+        }
+
+        let RedirectPolicy::Follow {
+            max_hops,
+            allow_downgrade,
+        } = self.inner.config.redirect_policy
+        else {
+            return self.send_with_retries(parts, body).await;
+        };
+        let mut chain = RedirectChain::default();
+        loop {
+            let mut resp = self.send_with_retries(parts.clone(), body.clone()).await?;
+            chain.hops.push(RedirectHop {
+                uri: parts.uri.clone(),
+                status: resp.status(),
+            });
+
+            let Some(target) = redirect_target(&parts.uri, resp.status(), resp.headers()) else {
+                resp.extensions_mut().insert(chain);
+                return Ok(resp);
+            };
+            if chain.hops.len() > max_hops {
+                return Err(Error::Redirect(format!(
+                    "stopped after {max_hops} redirects"
+                )));
+            }
+
+            let status = resp.status().as_u16();
+            let to_get = status == 303 && parts.method != Method::HEAD
+                || matches!(status, 301 | 302) && parts.method == Method::POST;
+            if to_get {
+                parts.method = Method::GET;
+                body = ReplayableBody::empty();
+                for name in [CONTENT_TYPE, CONTENT_LENGTH, CONTENT_ENCODING] {
+                    parts.headers.remove(name);
+                }
+            }
+            let mut target = target?;
+            if let Some(uri) = self.aliased(&target) {
+                target = uri;
+            }
+            scope_redirect(
+                &mut parts.headers,
+                &parts.uri,
+                &target,
+                &self.inner.config.sensitive_headers,
+                allow_downgrade,
+            )?;
+            parts.uri = target;
+        }
+    }
+
+    /// Sends a request to a single URI, sending idempotent requests again
+    /// after transient failures as the retry policy allows.
+    async fn send_with_retries(
+        &self,
+        parts: Parts,
+        body: ReplayableBody,
+    ) -> Result<Response<ResponseBody>> {
+        let policy = match &self.inner.config.retry_policy {
+            Some(policy) if parts.method.is_idempotent() => policy,
+            _ => return self.send_hop(parts, body).await,
+        };
+        let mut attempt = 1;
+        loop {
+            let result = self.send_hop(parts.clone(), body.clone()).await;
+            if attempt >= policy.max_attempts || !is_transient(policy, &result) || !self.may_retry()
+            {
+                return result;
+            }
+            let delay = policy.backoff(attempt);
+            tracing::debug!(
+                uri = %parts.uri,
+                attempt,
+                ?delay,
+                "retrying after transient failure"
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Sends a request to a single URI, answering authentication and server
+    /// challenges.
+    async fn send_hop(
+        &self,
+        mut parts: Parts,
+        body: ReplayableBody,
+    ) -> Result<Response<ResponseBody>> {
+        let origin = origin_of(&parts.uri);
+        if let Some(origin) = &origin {
+            if let Some(headers) = self.inner.clearances.lock().unwrap().get(origin) {
+                parts.headers.extend(headers.clone());
+            }
+        }
+
+        // The body as given, while a compressed one is sent in its place.
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        let mut body = body;
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        let mut uncompressed = None;
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(compressed) = self
+            .compress_body(origin.as_deref(), &mut parts.headers, &body)
+            .await
+        {
+            uncompressed = Some(std::mem::replace(&mut body, compressed));
+        }
+
+        let mut attempts = 0;
+        let mut authenticated = false;
+        let mut fresh_circuit = false;
+        loop {
+            self.wait_for_rate_limit(origin.as_deref()).await;
+            let mut req = Request::from_parts(parts.clone(), body.clone());
+            self.add_cookies(&mut req);
+            let mut resp = match self.dispatch(req).await {
+                Err(Error::Connect(e))
+                    if !fresh_circuit && self.retries_on_fresh_circuit(&parts, &e) =>
+                {
+                    tracing::debug!(uri = %parts.uri, error = %e, "retrying over a fresh circuit");
+                    fresh_circuit = true;
+                    parts.extensions.insert(IsolationToken::new());
+                    continue;
+                }
+                result => result?,
+            };
+            if let Some(store) = &self.inner.config.cookie_store {
+                store.set_cookies(&parts.uri, &mut resp.headers().get_all(SET_COOKIE).iter());
+            }
+            if let Some(rate_limit) = RateLimit::from_headers(resp.headers()) {
+                self.record_rate_limit(origin.as_deref(), rate_limit);
+                resp.extensions_mut().insert(rate_limit);
+            }
+            self.check_clock_skew(origin.as_deref(), resp.headers());
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            if let Some(origin) = &origin {
+                self.learn_request_coding(origin, resp.headers());
+                if resp.status() == hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE {
+                    if let Some(original) = uncompressed.take() {
+                        self.inner.request_codings.lock().unwrap().remove(origin);
+                        parts.headers.remove(CONTENT_ENCODING);
+                        body = original;
+                        continue;
+                    }
+                }
+            }
+            if let (Some(routes), Some(origin)) = (&self.inner.onion_routes, &origin) {
+                routes.learn(origin, announcements(&parts.uri, resp.headers()));
+            }
+
+            if resp.status() == hyper::StatusCode::UNAUTHORIZED && !authenticated {
+                if let Some(value) = self.answer_auth_challenge(&parts, &resp).await {
+                    authenticated = true;
+                    parts.headers.insert(hyper::header::AUTHORIZATION, value);
+                    continue;
+                }
+            }
+
+            if attempts >= self.inner.config.max_challenge_attempts {
+                return Ok(resp);
+            }
+
+            match self.inspect_challenge(&parts.uri, resp).await? {
+                Challenged::Done(resp) => return Ok(resp),
+                Challenged::Retry(solution) => {
+                    if !self.may_retry() {
+                        return Err(Error::RetryBudgetExhausted);
+                    }
+                    attempts += 1;
+                    if solution.persist {
+                        if let Some(origin) = origin_of(&parts.uri) {
+                            self.inner
+                                .clearances
+                                .lock()
+                                .unwrap()
+                                .entry(origin)
+                                .or_default()
+                                .extend(solution.headers.clone());
+                        }
+                    }
+                    parts.headers.extend(solution.headers);
+                }
+            }
+        }
+    }
+
+    /// Adds the cookies the configured store has for `req` to its `Cookie`
+    /// header.
+    fn add_cookies<B>(&self, req: &mut Request<B>) {
+        let Some(store) = &self.inner.config.cookie_store else {
+            return;
+        };
+        let Some(stored) = store.cookies(req.uri()) else {
+            return;
+        };
+        let value = match req.headers().get(COOKIE) {
+            Some(own) => {
+                let mut value = own.as_bytes().to_vec();
+                value.extend_from_slice(b"; ");
+                value.extend_from_slice(stored.as_bytes());
+                HeaderValue::from_bytes(&value).unwrap_or(stored)
+            }
+            None => stored,
+        };
+        req.headers_mut().insert(COOKIE, value);
+    }
+
+    /// Compresses a buffered `body` of at least the configured size with the
+    /// coding `origin` accepts, updating `headers` to match.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    async fn compress_body(
+        &self,
+        origin: Option<&str>,
+        headers: &mut HeaderMap,
+        body: &ReplayableBody,
+    ) -> Option<ReplayableBody> {
+        let min_size = self.inner.config.compress_requests?;
+        let encoding = *self.inner.request_codings.lock().unwrap().get(origin?)?;
+        if headers.contains_key(CONTENT_ENCODING)
+            || !body.is_buffered()
+            || body.size_hint().exact()? < min_size as u64
+        {
+            return None;
+        }
+        let data = body.clone().collect().await.ok()?.to_bytes();
+        let encoded = encode(encoding, &data).ok()?;
+        if encoded.len() >= data.len() {
+            return None;
+        }
+        headers.insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+        headers.remove(CONTENT_LENGTH);
+        Some(ReplayableBody::from(Bytes::from(encoded)))
+    }
+
+    /// Remembers which coding `origin` accepts in request bodies, if the
+    /// response says.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn learn_request_coding(&self, origin: &str, headers: &HeaderMap) {
+        if self.inner.config.compress_requests.is_none() {
+            return;
+        }
+        let Some(coding) = request_coding(headers) else {
+            return;
+        };
+        let mut codings = self.inner.request_codings.lock().unwrap();
+        match coding {
+            Some(coding) => codings.insert(origin.to_string(), coding),
+            None => codings.remove(origin),
+        };
+    }
+
+    /// Waits until `origin` accepts requests again, if pacing has held it.
+    async fn wait_for_rate_limit(&self, origin: Option<&str>) {
+        let Some(origin) = origin else { return };
+        let until = self.inner.paced.lock().unwrap().get(origin).copied();
+        if let Some(until) = until {
+            tokio::time::sleep_until(until.into()).await;
+        }
+    }
+
+    /// Holds back `origin` until its rate limit resets, if it is used up.
+    fn record_rate_limit(&self, origin: Option<&str>, rate_limit: RateLimit) {
+        let (true, Some(origin)) = (self.inner.config.rate_limit_pacing, origin) else {
+            return;
+        };
+        let mut paced = self.inner.paced.lock().unwrap();
+        match (rate_limit.remaining, rate_limit.reset) {
+            (Some(0), Some(reset)) => {
+                let until = Instant::now() + reset.min(MAX_RATE_LIMIT_WAIT);
+                paced.insert(origin.to_string(), until);
+            }
+            _ => {
+                paced.remove(origin);
+            }
+        }
+    }
+
+    /// Reports a server clock that is far off the local one to the hook.
+    fn check_clock_skew(&self, origin: Option<&str>, headers: &HeaderMap) {
+        let (Some((threshold, hook)), Some(origin)) = (&self.inner.config.clock_skew_hook, origin)
+        else {
+            return;
+        };
+        if let Some(skew) = ClockSkew::from_headers(headers, SystemTime::now()) {
+            if skew.magnitude() > *threshold {
+                hook.report(origin, skew);
+            }
+        }
+    }
+
+    /// Builds an `Authorization` header answering a 401 response, if the
+    /// configured provider has credentials for the request's origin.
+    async fn answer_auth_challenge<B>(
+        &self,
+        request: &Parts,
+        response: &Response<B>,
+    ) -> Option<HeaderValue> {
+        let provider = self.inner.config.credentials_provider.as_ref()?;
+        let origin = origin_of(&request.uri)?;
+
+        for challenge in challenges(response.headers()) {
+            let Some(credentials) = provider.credentials(&origin, challenge.realm()).await else {
+                continue;
+            };
+            if let Some(value) = authorization(&challenge, credentials, request) {
+                return Some(value);
+            }
+        }
+
+        None
+    }
+
+    /// Hands a possible challenge response to the configured solvers.
+    async fn inspect_challenge(
+        &self,
+        uri: &Uri,
+        resp: Response<ResponseBody>,
+    ) -> Result<Challenged> {
+        let (parts, body) = resp.into_parts();
+
+        let candidates: Vec<_> = self
+            .inner
+            .config
+            .challenge_solvers
+            .iter()
+            .filter(|solver| solver.is_candidate(&parts))
+            .collect();
+        if candidates.is_empty() {
+            return Ok(Challenged::Done(Response::from_parts(parts, body)));
+        }
+
+        let body = Limited::new(body, MAX_CHALLENGE_BODY)
+            .collect()
+            .await
+            .map_err(Error::body)?
+            .to_bytes();
+        let challenge = Challenge {
+            uri: uri.clone(),
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body,
+        };
+
+        for solver in candidates {
+            if let Some(solution) = solver.solve(&challenge).await? {
+                return Ok(Challenged::Retry(solution));
+            }
+        }
+
+        Ok(Challenged::Done(Response::from_parts(
+            parts,
+            ResponseBody::buffered(challenge.body),
+        )))
+    }
+
+    /// Waits for the request's scheduling slots, then performs the exchange.
+    pub(crate) async fn dispatch<B>(&self, req: Request<B>) -> Result<Response<ResponseBody>>
+    where
+        B: hyper::body::Body<Data = Bytes> + Send + 'static,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let priority = req
+            .extensions()
+            .get::<Priority>()
+            .copied()
+            .unwrap_or_default();
+        let mut permits = Vec::new();
+
+        if let Some(scheduler) = &self.inner.scheduler {
+            permits.push(scheduler.acquire(priority).await);
+        }
+
+        let (Some(adaptive), Some(host)) = (&self.inner.adaptive, req.uri().host()) else {
+            let resp = self.exchange(req).await?;
+            return Ok(resp.map(|body| body.with_permits(permits)));
+        };
+
+        let (permit, host_limit) = adaptive.acquire(host, priority).await;
+        permits.push(permit);
+
+        let started = Instant::now();
+        let result = self.exchange(req).await;
+        let success = matches!(&result, Ok(resp) if !is_overloaded(resp.status()));
+        adaptive.record(&host_limit, started.elapsed(), success);
+
+        Ok(result?.map(|body| body.with_permits(permits)))
+    }
+
+    /// Performs a single HTTP exchange over a Tor stream.
+    async fn exchange<B>(&self, req: Request<B>) -> Result<Response<ResponseBody>>
+    where
+        B: hyper::body::Body<Data = Bytes> + Send + 'static, // B must implement Body and be sendable
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>, // B::Error must be convertible to a boxed error
+    {
+        #[cfg(any(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "brotli",
+            feature = "zstd"
+        ))]
+        let (req, decode) = self.ask_for_compression(req);
+
+        // Body traffic is recorded on the span once the response is read.
+        let span = tracing::debug_span!(
+            "exchange",
+            method = %req.method(),
+            host = req.uri().host().unwrap_or_default(),
+            bytes_sent = tracing::field::Empty,
+            frames_sent = tracing::field::Empty,
+            bytes_received = tracing::field::Empty,
+            frames_received = tracing::field::Empty,
+        );
+        let traffic = Traffic::new(&span);
+        let req = req.map(|body| Counted::new(body, traffic.clone()));
+        let result = self
+            .exchange_isolated(req)
+            .instrument(span)
+            .await
+            .map(|resp| resp.map(|body| body.with_traffic(traffic)));
+
+        #[cfg(any(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "brotli",
+            feature = "zstd"
+        ))]
+        if decode {
+            let limits = self.inner.config.decode_limits;
+            return result.map(|resp| ResponseBody::decoded(resp, limits));
+        }
+        result
+    }
+
+    /// Asks for a compressed response if responses are decompressed and the
+    /// request leaves `Accept-Encoding` to the client, and tells whether it
+    /// did.
+    ///
+    /// A range of a compressed representation cannot be decoded on its own,
+    /// so range requests are left alone.
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    fn ask_for_compression<B>(&self, mut req: Request<B>) -> (Request<B>, bool) {
+        use hyper::header::{ACCEPT_ENCODING, RANGE};
+
+        let headers = req.headers();
+        if !self.inner.config.decompression
+            || headers.contains_key(ACCEPT_ENCODING)
+            || headers.contains_key(RANGE)
+        {
+            return (req, false);
+        }
+        req.headers_mut().insert(ACCEPT_ENCODING, accept_encoding());
+        (req, true)
+    }
+
+    /// Picks the isolation for an exchange and records how it went.
+    async fn exchange_isolated<B>(&self, req: Request<B>) -> Result<Response<ResponseBody>>
+    where
+        B: hyper::body::Body<Data = Bytes> + Send + 'static,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        // An explicit isolation token takes precedence over exit selection.
+        if let Some(token) = req.extensions().get::<IsolationToken>().copied() {
+            return self.exchange_over(req, Some(token)).await;
+        }
+
+        let isolation = match (&self.inner.exit_selector, req.uri().host()) {
+            (Some(selector), Some(host)) if !is_onion(host) => Some(selector.choose()),
+            _ => self
+                .inner
+                .usage_limiter
+                .as_ref()
+                .and_then(|limiter| limiter.circuit())
+                .or(*self.inner.identity.lock().unwrap()),
+        };
+        let started = Instant::now();
+
+        let result = self.exchange_over(req, isolation).await;
+
+        if let (Some(selector), Some(token)) = (&self.inner.exit_selector, isolation) {
+            let exit = result.as_ref().ok().and_then(|resp| {
+                let circuit = resp.extensions().get::<CircuitInfo>()?;
+                circuit.exit()?.fingerprint.as_deref()
+            });
+            selector.record(token, exit, started.elapsed(), result.is_ok());
+        }
+        result
+    }
+
+    /// Performs the exchange over a stream with the given isolation, reusing
+    /// an idle connection when there is one.
+    async fn exchange_over<B>(
+        &self,
+        req: Request<B>,
+        isolation: Option<IsolationToken>,
+    ) -> Result<Response<ResponseBody>>
+    where
+        B: hyper::body::Body<Data = Bytes> + Send + 'static,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let proxy_header = req.extensions().get::<ProxyHeader>().copied();
+        let exit_country = self.exit_country(req.uri(), req.extensions().get().copied());
+        // A request asking to close its connection gets one of its own.
+        let key = self
+            .pool_key(req.uri(), isolation, exit_country, proxy_header)
+            .filter(|_| !closes_connection(req.headers()));
+
+        // Keep the request's extensions: hyper stores the original header
+        // casing of parsed requests there.
+        let (mut parts, body) = req.into_parts();
+        let h2c = parts.version == Version::HTTP_2;
+        if h2c {
+            let onion = parts.uri.host().is_some_and(is_onion);
+            if parts.uri.scheme() != Some(&Scheme::HTTP) || !onion {
+                return Err(Error::InvalidRequest(
+                    "HTTP/2 is only supported to onion services over plain HTTP".into(),
+                ));
+            }
+        } else if !matches!(parts.version, Version::HTTP_10 | Version::HTTP_11) {
+            return Err(Error::InvalidRequest(format!(
+                "{:?} is not supported",
+                parts.version
+            )));
+        }
+
+        // A caller-supplied Host is kept, which allows virtual-host probing.
+        // HTTP/2 carries the authority in a pseudo-header instead.
+        if !h2c && !parts.headers.contains_key(hyper::header::HOST) {
+            if let Some(host) = host_header(&parts.uri) {
+                parts.headers.insert(hyper::header::HOST, host);
+            }
+        }
+
+        if !parts.headers.contains_key(USER_AGENT) {
+            if let Some(user_agent) = &self.inner.config.user_agent {
+                let identity = isolation.map(|token| {
+                    let mut hasher = DefaultHasher::new();
+                    token.hash(&mut hasher);
+                    hasher.finish()
+                });
+                if let Some(value) = user_agent.choose(identity) {
+                    parts.headers.insert(USER_AGENT, value.clone());
+                }
+            }
+        }
+
+        if let Some(value) = &self.inner.config.accept_language {
+            parts.headers.insert(ACCEPT_LANGUAGE, value.clone());
+        }
+
+        let (body, sent) = SignalOnEnd::new(body);
+        let mut sent = Some(sent);
+        let mut req = Request::from_parts(parts, body.map_err(Into::into).boxed_unsync());
+        let timeout = self.inner.config.response_header_timeout;
+        if h2c {
+            return self
+                .exchange_h2c(req, key, isolation, exit_country, proxy_header, sent)
+                .await;
+        }
+
+        // An idle connection may have been closed by the server since it was
+        // last used; hyper then hands the request back unsent.
+        while let Some(mut conn) = match &key {
+            Some(key) => self.inner.pool.checkout(key).await,
+            None => None,
+        } {
+            let response = conn.sender.try_send_request(req);
+            match within(response, &mut sent, timeout).await? {
+                Ok(resp) => return Ok(self.pooled(resp, key, conn, sent)),
+                Err(mut e) => match e.take_message() {
+                    Some(unsent) => req = unsent,
+                    None => return Err(ConnectionError::new(e.into_error()).into()),
+                },
+            }
+        }
+
+        let (stream, info) = self
+            .create_stream(req.uri(), isolation, exit_country, proxy_header)
+            .await?;
+        let (stream, usage) = self.metered(stream, key.is_some());
+
+        let mut builder = hyper::client::conn::http1::Builder::new();
+        match self.inner.config.read_buffer {
+            ReadBuffer::Adaptive => {}
+            ReadBuffer::Bounded(max) => {
+                builder.max_buf_size(max);
+            }
+            ReadBuffer::Exact(size) => {
+                builder.read_buf_exact_size(Some(size));
+            }
+        }
+
+        let http1 = &self.inner.config.http1;
+        if let Some(max) = http1.max_headers {
+            builder.max_headers(max);
+        }
+        if let Some(writev) = http1.writev {
+            builder.writev(writev);
+        }
+        builder
+            .title_case_headers(http1.title_case_headers)
+            .preserve_header_case(http1.preserve_header_case)
+            .allow_obsolete_multiline_headers_in_responses(http1.allow_obsolete_multiline_headers)
+            .allow_spaces_after_header_name_in_responses(http1.allow_spaces_after_header_name)
+            .ignore_invalid_headers_in_responses(http1.ignore_invalid_headers)
+            .http09_responses(http1.http09_responses);
+
+        let (mut sender, connection) = builder
+            .handshake(TokioIo::new(stream))
+            .await
+            .map_err(ConnectionError::new)?;
+        let response = sender.send_request(req);
+
+        match self.inner.config.connection_driver {
+            ConnectionDriver::Spawned => {
+                // Spawn a task to poll the connection and drive the HTTP state.
+                // Errors that affect the request also reach it through the
+                // response future or body; anything left is only logged.
+                tokio::spawn(async move {
+                    if let Err(e) = connection.await {
+                        tracing::debug!(error = %ConnectionError::new(e), "connection closed");
+                    }
+                });
+
+                let resp = within(response, &mut sent, timeout)
+                    .await?
+                    .map_err(ConnectionError::new)?;
+                let conn = PooledConnection {
+                    sender,
+                    info,
+                    usage,
+                };
+                Ok(self.pooled(resp, key, conn, sent))
+            }
+            ConnectionDriver::Inline => {
+                let mut connection: Connection = Box::pin(connection);
+                let response = within(response, &mut sent, timeout);
+                tokio::pin!(response);
+
+                let resp = tokio::select! {
+                    biased;
+                    resp = &mut response => resp?,
+                    result = &mut connection => {
+                        // The connection ended before the response arrived;
+                        // surface its error rather than hyper's cancellation.
+                        result.map_err(ConnectionError::new)?;
+                        response.await?
+                    }
+                };
+                let mut resp = resp.map_err(ConnectionError::new)?;
+                info.annotate(&mut resp);
+                Ok(resp.map(|incoming| ResponseBody::driven(incoming, connection)))
+            }
+        }
+    }
+
+    /// Performs the exchange over cleartext HTTP/2 with prior knowledge,
+    /// sharing one connection per pool key between concurrent requests.
+    ///
+    /// HTTP/2 connections are always polled on spawned tasks.
+    async fn exchange_h2c(
+        &self,
+        req: Request<PoolBody>,
+        key: Option<PoolKey>,
+        isolation: Option<IsolationToken>,
+        exit_country: Option<ExitCountry>,
+        proxy_header: Option<ProxyHeader>,
+        mut sent: Option<oneshot::Receiver<()>>,
+    ) -> Result<Response<ResponseBody>> {
+        let shared = key.as_ref().and_then(|key| self.inner.pool.shared(key));
+        let (mut sender, usage, info) = match shared {
+            Some(shared) => shared,
+            None => {
+                let (stream, info) = self
+                    .create_stream(req.uri(), isolation, exit_country, proxy_header)
+                    .await?;
+                let (stream, usage) = self.metered(stream, key.is_some());
+                let (sender, connection) = http2::Builder::new(TokioExecutor::new())
+                    .handshake(TokioIo::new(stream))
+                    .await
+                    .map_err(ConnectionError::new)?;
+                tokio::spawn(async move {
+                    if let Err(e) = connection.await {
+                        tracing::debug!(error = %ConnectionError::new(e), "connection closed");
+                    }
+                });
+                if let Some(key) = key {
+                    self.inner
+                        .pool
+                        .share(key, sender.clone(), usage.clone(), info.clone());
+                }
+                (sender, usage, info)
+            }
+        };
+        if let Some(usage) = &usage {
+            usage.record_request();
+        }
+
+        // Waits while the server's limit on concurrent streams is reached.
+        sender.ready().await.map_err(ConnectionError::new)?;
+        let response = sender.send_request(req);
+        let resp = within(
+            response,
+            &mut sent,
+            self.inner.config.response_header_timeout,
+        )
+        .await?
+        .map_err(ConnectionError::new)?;
+        let mut resp = resp.map(ResponseBody::from);
+        info.annotate(&mut resp);
+        Ok(resp)
+    }
+
+    /// Counts the traffic of a connection that may be reused, if
+    /// [`UsageCaps`](crate::UsageCaps) are configured.
+    fn metered(
+        &self,
+        stream: ConnectedStream,
+        reusable: bool,
+    ) -> (ConnectedStream, Option<Arc<Usage>>) {
+        if self.inner.usage_limiter.is_none() || !reusable {
+            return (stream, None);
+        }
+        let usage = Arc::new(Usage::default());
+        let stream = Box::new(Metered::new(stream, Arc::clone(&usage)));
+        (stream, Some(usage))
+    }
+
+    /// Returns the pool key for a request, or `None` if its connection must
+    /// not be shared.
+    ///
+    /// Connections driven by their response body end with it, and a PROXY
+    /// header describes a single client connection.
+    fn pool_key(
+        &self,
+        uri: &Uri,
+        isolation: Option<IsolationToken>,
+        exit_country: Option<ExitCountry>,
+        proxy_header: Option<ProxyHeader>,
+    ) -> Option<PoolKey> {
+        if !self.inner.pool.is_enabled()
+            || proxy_header.is_some()
+            || self.inner.config.connection_driver != ConnectionDriver::Spawned
+        {
+            return None;
+        }
+        let https = uri.scheme() == Some(&Scheme::HTTPS);
+        Some(PoolKey {
+            scheme: if https { Scheme::HTTPS } else { Scheme::HTTP },
+            host: uri.host()?.to_ascii_lowercase(),
+            port: uri.port_u16().unwrap_or(if https { 443 } else { 80 }),
+            isolation,
+            exit_country,
+        })
+    }
+
+    /// Returns the country a request to `uri` exits from: the one it asked
+    /// for or the client's, unless it goes to an onion service.
+    pub(crate) fn exit_country(
+        &self,
+        uri: &Uri,
+        requested: Option<ExitCountry>,
+    ) -> Option<ExitCountry> {
+        if uri.host().is_some_and(is_onion) {
+            return None;
+        }
+        requested.or(self.inner.config.exit_country)
+    }
+
+    /// Wraps a response received over `conn`, which goes back to the pool
+    /// under `key` once the body has been read.
+    ///
+    /// A connection still sending the request body, because the server
+    /// answered early, is not reused.
+    fn pooled(
+        &self,
+        resp: Response<Incoming>,
+        key: Option<PoolKey>,
+        conn: PooledConnection,
+        sent: Option<oneshot::Receiver<()>>,
+    ) -> Response<ResponseBody> {
+        if let Some(usage) = &conn.usage {
+            usage.record_request();
+        }
+        let mut resp = resp.map(ResponseBody::from);
+        conn.info.annotate(&mut resp);
+        let sent = match sent {
+            Some(mut sent) => sent.try_recv().is_ok(),
+            None => true,
+        };
+        match key {
+            Some(key) if sent => resp.map(|body| {
+                body.with_checkin(Checkin::new(Arc::clone(&self.inner.pool), key, conn))
+            }),
+            _ => resp,
+        }
+    }
+
+    /// Moves later requests onto fresh circuits and forgets what origins
+    /// could recognize the client by, like Tor Browser's "New Identity".
+    ///
+    /// Idle connections are closed, and the cookie store, challenge
+    /// clearances, learned onion routes and the request codings origins
+    /// accepted are cleared. Cookie stores that cannot replace their
+    /// cookies keep them. Requests already in flight finish on their old
+    /// circuits, and those carrying their own [`IsolationToken`] keep it.
+    /// Credentials are asked of the provider for every challenge, so there
+    /// is no authentication state to drop. Applications that want to return
+    /// to the old session can save it with `Client::export_state` first.
+    pub fn new_identity(&self) {
+        *self.inner.identity.lock().unwrap() = Some(IsolationToken::new());
+        if let Some(selector) = &self.inner.exit_selector {
+            selector.renew();
+        }
+        if let Some(limiter) = &self.inner.usage_limiter {
+            limiter.renew();
+        }
+        self.inner.pool.clear();
+        self.inner.clearances.lock().unwrap().clear();
+        if let Some(store) = &self.inner.config.cookie_store {
+            store.replace_cookies(Vec::new());
+        }
+        if let Some(routes) = &self.inner.onion_routes {
+            routes.clear();
+        }
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        self.inner.request_codings.lock().unwrap().clear();
+    }
+
+    /// Returns the number of retries refused because the
+    /// [`RetryBudget`](crate::RetryBudget) was exhausted.
+    pub fn retry_budget_exhausted(&self) -> u64 {
+        self.inner
+            .retry_limiter
+            .as_ref()
+            .map_or(0, RetryLimiter::exhausted)
+    }
+
+    /// Returns `true` if a request that failed with `error` is sent again
+    /// over a newly built circuit: when Tor could not open the stream, or
+    /// when the circuit collapsed under an idempotent request. Requests
+    /// carrying their own [`IsolationToken`] stay on their circuit.
+    fn retries_on_fresh_circuit(&self, parts: &Parts, error: &ConnectionError) -> bool {
+        if parts.extensions.get::<IsolationToken>().is_some() {
+            return false;
+        }
+        let retry = error.is_circuit_refusal()
+            || (error.kind() == ConnectionErrorKind::CircuitClosed && parts.method.is_idempotent());
+        retry && self.may_retry()
+    }
+
+    /// Takes a retry from the budget; always allowed without one.
+    pub(crate) fn may_retry(&self) -> bool {
+        match &self.inner.retry_limiter {
+            Some(limiter) => limiter.withdraw(),
+            None => true,
+        }
+    }
+
+    /// Returns `uri` pointed at the configured alias of its host or the
+    /// onion service its origin announced with `Onion-Location`, if any.
+    fn aliased(&self, uri: &Uri) -> Option<Uri> {
+        let host = uri.host()?.to_ascii_lowercase();
+        if let Some(alias) = self.inner.config.host_aliases.get(&host) {
+            return with_host(uri, alias);
+        }
+        let location = self
+            .inner
+            .onion_routes
+            .as_ref()?
+            .location(&origin_of(uri)?)?;
+        with_origin(uri, &location)
+    }
+
+    /// Returns the onion routes learned from `Onion-Location` and `Alt-Svc`
+    /// headers that have not expired, by origin.
+    ///
+    /// Empty unless [`ClientConfigBuilder::learn_onion_routes`] is enabled.
+    pub fn onion_routes(&self) -> Vec<(String, OnionRoute)> {
+        self.inner
+            .onion_routes
+            .as_ref()
+            .map(OnionRoutes::routes)
+            .unwrap_or_default()
+    }
+
+    /// Opens a Tor stream to `host:port` once the connect hook allows it,
+    /// along with the circuit it runs over.
+    pub(crate) async fn open_stream(
+        &self,
+        host: &str,
+        port: u16,
+        scheme: Scheme,
+        isolation: Option<IsolationToken>,
+        exit_country: Option<ExitCountry>,
+    ) -> Result<(ConnectedStream, Option<CircuitInfo>), IoError> {
+        let target = ConnectTarget {
+            host: host.to_string(),
+            port,
+            scheme,
+            isolation,
+            exit_country,
+        };
+        if let Some(hook) = &self.inner.config.connect_hook {
+            if !hook.allow(&target).await {
+                return Err(IoError::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    format!("connection to {host}:{port} denied by connect hook"),
+                ));
+            }
+        }
+
+        if let Some(connector) = &self.inner.config.connector {
+            return Ok((connector.connect(&target).await?, None));
+        }
+
+        #[cfg(not(feature = "geoip"))]
+        if exit_country.is_some() || !self.inner.config.exclude_countries.is_empty() {
+            return Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                "choosing exits by country requires the `geoip` feature",
+            ));
+        }
+
+        #[cfg(feature = "exclude-exits")]
+        {
+            let config = &self.inner.config;
+            let excludes_exits =
+                !config.exclude_exit_nodes.is_empty() || !config.exclude_countries.is_empty();
+            if excludes_exits && !is_onion(host) {
+                let stream = self
+                    .open_checked_stream(host, port, isolation, exit_country)
+                    .await?;
+                let circuit = circuit_info(&stream);
+                return Ok((Box::new(stream), circuit));
+            }
+        }
+
+        let mut prefs = StreamPrefs::new();
+        if let Some(token) = isolation {
+            prefs.set_isolation(token);
+        }
+        #[cfg(feature = "geoip")]
+        if let Some(code) = country_code(exit_country)? {
+            prefs.exit_country(code);
+        }
+        let stream = self
+            .inner
+            .tor_client
+            .connect_with_prefs((host, port), &prefs)
+            .await
+            .map_err(|e| IoError::new(std::io::ErrorKind::Other, e))?;
+        let circuit = circuit_info(&stream);
+        Ok((Box::new(stream), circuit))
+    }
+
+    /// Opens a stream to `host:port` over a circuit whose exit is not
+    /// excluded.
+    ///
+    /// arti cannot leave relays out of its paths, so circuits are taken from
+    /// its circuit manager and checked before the stream is begun; nothing,
+    /// not even the destination, is sent through an excluded exit. Circuits
+    /// that fail the check are retired and another one is asked for with
+    /// the same isolation.
+    ///
+    /// The target is checked first as `connect_with_prefs` would: local
+    /// addresses are refused unless the client runs against a test network,
+    /// and IPv6 addresses are asked for over IPv6.
+    #[cfg(feature = "exclude-exits")]
+    #[cfg_attr(not(feature = "geoip"), allow(unused_variables))]
+    async fn open_checked_stream(
+        &self,
+        host: &str,
+        port: u16,
+        isolation: Option<IsolationToken>,
+        exit_country: Option<ExitCountry>,
+    ) -> Result<DataStream, IoError> {
+        let other = |e: Box<dyn std::error::Error + Send + Sync>| {
+            IoError::new(std::io::ErrorKind::Other, e)
+        };
+        TorAddr::from((host, port))
+            .map_err(|e| IoError::new(std::io::ErrorKind::InvalidInput, e))?;
+        if is_local_host(host) && !self.inner.config.allow_local_addrs {
+            return Err(IoError::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("refusing to connect to local address {host}"),
+            ));
+        }
+        let ipv6 = host.starts_with('[') || host.parse::<std::net::Ipv6Addr>().is_ok();
+
+        let tor_client = &self.inner.tor_client;
+        let netdir = tor_client
+            .dirmgr()
+            .timely_netdir()
+            .map_err(|e| other(e.into()))?;
+        let isolation = StreamIsolation::builder()
+            .stream_isolation(Box::new(
+                isolation.unwrap_or_else(IsolationToken::no_isolation),
+            ))
+            .owner_token(self.inner.owner_isolation)
+            .build()
+            .expect("stream isolation fields are all set");
+        let mut params = StreamParameters::default();
+        let ports = if ipv6 {
+            params.ipv6_ok(true).ipv6_preferred(true);
+            [TargetPort::ipv6(port)]
+        } else {
+            [TargetPort::ipv4(port)]
+        };
+        #[cfg(feature = "geoip")]
+        let country = country_code(exit_country)?;
+
+        for _ in 0..MAX_EXCLUDED_EXITS {
+            let circmgr = tor_client.circmgr();
+            #[cfg(feature = "geoip")]
+            let launch =
+                circmgr.get_or_launch_exit((&*netdir).into(), &ports, isolation.clone(), country);
+            #[cfg(not(feature = "geoip"))]
+            let launch = circmgr.get_or_launch_exit((&*netdir).into(), &ports, isolation.clone());
+            let circuit = launch.await.map_err(|e| other(e.into()))?;
+
+            let info = describe_circuit(&circuit);
+            let Some(exit) = info.exit().filter(|exit| self.is_excluded(exit)) else {
+                return circuit
+                    .begin_stream(host, port, Some(params))
+                    .await
+                    .map_err(|e| other(e.into()));
+            };
+            tracing::debug!(
+                exit = exit.fingerprint.as_deref().unwrap_or_default(),
+                "circuit exits from an excluded relay, retiring it"
+            );
+            circmgr.retire_circ(&circuit.unique_id());
+        }
+        Err(IoError::new(
+            std::io::ErrorKind::Other,
+            format!("no circuit to {host}:{port} avoided the excluded exits"),
+        ))
+    }
+
+    /// Returns `true` if the configuration rules out exiting from `exit`.
+    #[cfg(feature = "exclude-exits")]
+    fn is_excluded(&self, exit: &RelayInfo) -> bool {
+        let config = &self.inner.config;
+        if exit
+            .fingerprint
+            .as_ref()
+            .is_some_and(|fingerprint| config.exclude_exit_nodes.contains(fingerprint))
+        {
+            return true;
+        }
+        #[cfg(feature = "geoip")]
+        if let Some(db) = &self.inner.geoip {
+            let country = db.lookup_country_code_multi(exit.addrs.iter().map(|addr| addr.ip()));
+            return country.is_some_and(|country| {
+                config
+                    .exclude_countries
+                    .iter()
+                    .any(|excluded| excluded.as_str().eq_ignore_ascii_case(country.as_ref()))
+            });
+        }
+        false
+    }
+
+    /// Creates a stream for the specified URI, optionally wrapping it with
+    /// TLS, within the connect timeout.
+    pub(crate) async fn create_stream(
+        &self,
+        url: &Uri,
+        isolation: Option<IsolationToken>,
+        exit_country: Option<ExitCountry>,
+        proxy_header: Option<ProxyHeader>,
+    ) -> Result<(ConnectedStream, StreamInfo)> {
+        let connect = self.connect_origin(url, isolation, exit_country, proxy_header);
+        match self.inner.config.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect)
+                .await
+                .map_err(|_| Error::Timeout(timeout))?,
+            None => connect.await,
+        }
+    }
+
+    /// Opens a stream to the origin of `url`, wrapping it with TLS for HTTPS.
+    async fn connect_origin(
+        &self,
+        url: &Uri,
+        isolation: Option<IsolationToken>,
+        exit_country: Option<ExitCountry>,
+        proxy_header: Option<ProxyHeader>,
+    ) -> Result<(ConnectedStream, StreamInfo)> {
+        let host = url
+            .host()
+            .ok_or_else(|| Error::InvalidRequest(format!("missing host in `{url}`")))?;
+        let https = url.scheme() == Some(&Scheme::HTTPS);
+
+        #[cfg(not(feature = "native-tls"))]
+        if https {
+            return Err(Error::InvalidRequest(
+                "HTTPS requires the `native-tls` feature".into(),
+            ));
+        }
+
+        let port = match url.port_u16() {
+            Some(port) => port,
+            None if https => 443,
+            None => 80,
+        };
+
+        // An origin that announced an onion service with Alt-Svc is reached
+        // through it; TLS still authenticates the origin's own name.
+        let alt_svc = match (&self.inner.onion_routes, origin_of(url)) {
+            (Some(routes), Some(origin)) if https => routes
+                .alt_svc(&origin)
+                .and_then(|target| target.parse::<Authority>().ok()),
+            _ => None,
+        };
+        let (connect_host, connect_port) = match &alt_svc {
+            Some(authority) => (authority.host(), authority.port_u16().unwrap_or(port)),
+            None => (host, port),
+        };
+
+        let scheme = if https { Scheme::HTTPS } else { Scheme::HTTP };
+        let tap = self.inner.config.wire_tap.clone().map(|tap| {
+            let target = ConnectTarget {
+                host: host.to_string(),
+                port,
+                scheme: scheme.clone(),
+                isolation,
+                exit_country,
+            };
+            (tap, target)
+        });
+        let (mut stream, circuit) = self
+            .open_stream(connect_host, connect_port, scheme, isolation, exit_country)
+            .await
+            .map_err(ConnectionError::new)?;
+        if let Some(header) = proxy_header {
+            // The PROXY header precedes everything, including TLS.
+            stream
+                .write_all(&header.encode())
+                .await
+                .map_err(ConnectionError::new)?;
+        }
+        #[cfg(feature = "chaos")]
+        let stream = self
+            .inner
+            .faults
+            .inject(stream)
+            .await
+            .map_err(ConnectionError::new)?;
+        // Watched beneath TLS, so stalled handshakes are caught too.
+        let stream: ConnectedStream = match self.inner.config.upload_stall_timeout {
+            Some(timeout) => Box::new(StallGuard::new(stream, timeout)),
+            None => Box::new(stream),
+        };
+
+        #[cfg(feature = "native-tls")]
+        if https {
+            let (wrapped_stream, info) = self.handshake(host, stream).await?;
+            let stream: ConnectedStream = match tap {
+                Some((tap, target)) => Box::new(Tapped::new(wrapped_stream, tap, target)),
+                None => wrapped_stream,
+            };
+            let info = StreamInfo {
+                tls: Some(info),
+                circuit,
+            };
+            return Ok((stream, info));
+        }
+
+        // Return the unwrapped stream directly for HTTP
+        let info = StreamInfo { tls: None, circuit };
+        match tap {
+            Some((tap, target)) => Ok((Box::new(Tapped::new(stream, tap, target)), info)),
+            None => Ok((stream, info)),
+        }
+    }
+
+    /// Wraps `stream` with TLS for `host`.
+    #[cfg(feature = "native-tls")]
+    async fn handshake(
+        &self,
+        host: &str,
+        stream: ConnectedStream,
+    ) -> Result<(ConnectedStream, TlsInfo)> {
+        #[cfg(feature = "tls-session-cache")]
+        if let Some(connector) = &self.inner.config.tls_session_cache {
+            let name = host.trim_start_matches('[').trim_end_matches(']');
+            let name = tokio_rustls::rustls::pki_types::ServerName::try_from(name.to_string())
+                .map_err(|e| Error::InvalidRequest(format!("invalid TLS server name: {e}")))?;
+            let wrapped_stream = connector
+                .connect(name, stream)
+                .await
+                .map_err(|e| Error::Tls(e.into()))?;
+            let (_, session) = wrapped_stream.get_ref();
+            let info = TlsInfo {
+                alpn_protocol: session.alpn_protocol().map(<[u8]>::to_vec),
+                peer_certificates: session
+                    .peer_certificates()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|cert| cert.to_vec())
+                    .collect(),
+            };
+            return Ok((Box::new(wrapped_stream), info));
+        }
+
+        let tls_connector = &self.inner.config.tls_config;
+        let cx = tokio_native_tls::TlsConnector::from(tls_connector.clone());
+        let wrapped_stream = cx
+            .connect(host, stream)
+            .await
+            .map_err(|e| Error::Tls(e.into()))?;
+        let tls = wrapped_stream.get_ref();
+        let info = TlsInfo {
+            alpn_protocol: tls.negotiated_alpn().ok().flatten(),
+            peer_certificates: tls
+                .peer_certificate()
+                .ok()
+                .flatten()
+                .and_then(|cert| cert.to_der().ok())
+                .into_iter()
+                .collect(),
+        };
+        Ok((Box::new(wrapped_stream), info))
+    }
+}
+
+/// Keeps a connection to `origin` open for the client behind `inner`,
+/// sending a `HEAD` request often enough that the pool does not retire it.
+///
+/// Holds the client only while a request is in flight, so that dropping the
+/// last handle to it is not held up by the task.
+async fn keep_warm(inner: Weak<Inner>, origin: Uri) {
+    let Some(period) = inner
+        .upgrade()
+        .map(|inner| inner.config.pool_idle_timeout / 2)
+    else {
+        return;
+    };
+    let mut ticks = tokio::time::interval(period.max(MIN_WARM_PERIOD));
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        let client = Client { inner };
+        let req = Request::head(origin.clone())
+            .body(ReplayableBody::empty())
+            .expect("a request to a valid URI can be built");
+        match client.send_request(req).await {
+            // Reading the body to its end returns the connection.
+            Ok(resp) => {
+                let _ = resp.into_body().collect().await;
+            }
+            Err(e) => tracing::debug!(%origin, error = %e, "failed to keep connection warm"),
+        }
+    }
+}
+
+/// Tells whether `policy` retries a request that ended in `result`.
+fn is_transient(policy: &RetryPolicy, result: &Result<Response<ResponseBody>>) -> bool {
+    match result {
+        Ok(resp) => policy.statuses.contains(&resp.status()),
+        Err(Error::Connect(e)) => policy.error_kinds.contains(&e.kind()),
+        Err(Error::Timeout(_) | Error::Stalled(_)) => policy.timeouts,
+        Err(_) => false,
+    }
+}
+
+/// Returns `true` for statuses that signal an overloaded or unreachable upstream.
+fn is_overloaded(status: hyper::StatusCode) -> bool {
+    matches!(status.as_u16(), 502..=504)
+}
+
+/// Converts an exit country into arti's country code.
+#[cfg(feature = "geoip")]
+fn country_code(country: Option<ExitCountry>) -> Result<Option<arti_client::CountryCode>, IoError> {
+    country
+        .map(|country| {
+            country
+                .as_str()
+                .parse()
+                .map_err(|e| IoError::new(std::io::ErrorKind::InvalidInput, e))
+        })
+        .transpose()
+}
+
+/// Returns `true` if `headers` carry `Connection: close`.
+fn closes_connection(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("close"))
+}
+
+/// Waits for `response`, giving up `timeout` after the request body has been
+/// handed to hyper.
+async fn within<F: Future>(
+    response: F,
+    sent: &mut Option<oneshot::Receiver<()>>,
+    timeout: Option<Duration>,
+) -> Result<F::Output> {
+    let Some(timeout) = timeout else {
+        return Ok(response.await);
+    };
+    let expired = async {
+        // The clock starts once the body has been handed over; if
+        // sending fails, the response future reports the error.
+        if let Some(sent) = sent.as_mut() {
+            let _ = sent.await;
+        }
+        *sent = None;
+        tokio::time::sleep(timeout).await;
+    };
+    tokio::select! {
+        resp = response => Ok(resp),
+        () = expired => Err(Error::Timeout(timeout)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{Credentials, CredentialsStore};
+    use crate::testing::{client, respond, ONION};
+    use hyper::header::{HeaderName, AUTHORIZATION, COOKIE};
+    use hyper::StatusCode;
+
+    #[tokio::test]
+    async fn redirects_scope_credentials_to_their_origin() {
+        let api_key = HeaderName::from_static("x-api-key");
+        let builder = ClientConfigBuilder::new()
+            .follow_redirects(5)
+            .sensitive_header(api_key.clone());
+        let (client, log) = client(builder, |req| match req.uri.path() {
+            "/same" => respond(StatusCode::FOUND, &[("location", "/landing")]),
+            "/other" => respond(
+                StatusCode::TEMPORARY_REDIRECT,
+                &[("location", "http://other.example/landing")],
+            ),
+            _ => respond(StatusCode::OK, &[]),
+        })
+        .await;
+
+        for path in ["/same", "/other"] {
+            let req = Request::post(format!("http://example.com{path}"))
+                .header(AUTHORIZATION, "Bearer secret")
+                .header(COOKIE, "session=1")
+                .header(&api_key, "key")
+                .body(Full::<Bytes>::default())
+                .unwrap();
+            let resp = client.send_request(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        let requests = log.requests();
+        assert_eq!(requests.len(), 4);
+        let same = &requests[1];
+        assert_eq!(same.target.host, "example.com");
+        assert_eq!(same.method, Method::GET);
+        for name in [&AUTHORIZATION, &COOKIE, &api_key] {
+            assert!(same.headers.contains_key(name), "{name}");
+        }
+        let other = &requests[3];
+        assert_eq!(other.target.host, "other.example");
+        assert_eq!(other.method, Method::POST);
+        for name in [&AUTHORIZATION, &COOKIE, &api_key] {
+            assert!(!other.headers.contains_key(name), "{name}");
+        }
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[tokio::test]
+    async fn redirects_from_https_to_http_are_refused() {
+        let builder = ClientConfigBuilder::new().follow_redirects(5);
+        let (client, log) = client(builder, |_| {
+            respond(StatusCode::FOUND, &[("location", "http://example.com/")])
+        })
+        .await;
+
+        let result = client.get("https://example.com/").await;
+        assert!(matches!(result, Err(Error::Redirect(_))));
+        assert_eq!(log.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn bearer_challenges_are_answered_only_where_exits_cannot_read() {
+        let store = CredentialsStore::new();
+        store.insert(format!("http://{ONION}:80"), Credentials::bearer("secret"));
+        store.insert("http://example.com:80", Credentials::bearer("secret"));
+        let builder = ClientConfigBuilder::new().credentials_provider(store);
+        let (client, log) = client(builder, |req| match req.headers.get(AUTHORIZATION) {
+            Some(value) if value == "Bearer secret" => respond(StatusCode::OK, &[]),
+            _ => respond(
+                StatusCode::UNAUTHORIZED,
+                &[("www-authenticate", "Bearer realm=\"api\"")],
+            ),
+        })
+        .await;
+
+        let resp = client.get(format!("http://{ONION}/")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(log.requests().len(), 2);
+
+        let resp = client.get("http://example.com/").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(log.requests().len(), 3);
+    }
+
+    #[cfg(feature = "auth")]
+    #[tokio::test]
+    async fn digest_challenges_are_answered() {
+        use sha2::{Digest, Sha256};
+
+        let store = CredentialsStore::new();
+        store.insert(
+            format!("http://{ONION}:80"),
+            Credentials::password("alice", "wonderland"),
+        );
+        let builder = ClientConfigBuilder::new().credentials_provider(store);
+        let (client, log) = client(builder, |req| match req.headers.get(AUTHORIZATION) {
+            Some(_) => respond(StatusCode::OK, &[]),
+            None => respond(
+                StatusCode::UNAUTHORIZED,
+                &[(
+                    "www-authenticate",
+                    "Digest realm=\"files, private\", qop=\"auth,auth-int\", \
+                     algorithm=SHA-256, nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\"",
+                )],
+            ),
+        })
+        .await;
+
+        let resp = client
+            .get(format!("http://{ONION}/dir/index.html"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let requests = log.requests();
+        let header = requests[1].headers[AUTHORIZATION].to_str().unwrap();
+        let param = |name: &str| {
+            let start = header.find(&format!(" {name}="))? + name.len() + 2;
+            let value = &header[start..];
+            Some(match value.strip_prefix('"') {
+                Some(quoted) => quoted[..quoted.find('"')?].to_string(),
+                None => value[..value.find(',').unwrap_or(value.len())].to_string(),
+            })
+        };
+        assert!(header.starts_with("Digest "));
+        assert_eq!(param("username").unwrap(), "alice");
+        assert_eq!(param("realm").unwrap(), "files, private");
+        assert_eq!(param("uri").unwrap(), "/dir/index.html");
+        assert_eq!(param("qop").unwrap(), "auth");
+
+        let hash = |data: String| hex::encode(Sha256::digest(data.as_bytes()));
+        let ha1 = hash("alice:files, private:wonderland".into());
+        let ha2 = hash("GET:/dir/index.html".into());
+        let expected = hash(format!(
+            "{ha1}:{}:{}:{}:auth:{ha2}",
+            param("nonce").unwrap(),
+            param("nc").unwrap(),
+            param("cnonce").unwrap()
+        ));
+        assert_eq!(param("response").unwrap(), expected);
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[tokio::test]
+    async fn onion_location_moves_later_requests_to_the_onion_service() {
+        let builder = ClientConfigBuilder::new()
+            .learn_onion_routes(true)
+            .pool_max_idle_per_host(0);
+        let location = format!("http://{ONION}/ignored/path");
+        let (client, log) = client(builder, move |_| {
+            respond(StatusCode::OK, &[("onion-location", location.as_str())])
+        })
+        .await;
+
+        client.get("https://example.com/a").await.unwrap();
+        assert_eq!(
+            client.onion_routes(),
+            vec![(
+                "https://example.com:443".to_string(),
+                crate::OnionRoute::Location(format!("http://{ONION}"))
+            )]
+        );
+        client.get("https://example.com/b?q=1").await.unwrap();
+
+        let requests = log.requests();
+        assert_eq!(requests[1].target.host, ONION);
+        assert_eq!(requests[1].target.scheme, Scheme::HTTP);
+        assert_eq!(requests[1].uri.path_and_query().unwrap(), "/b?q=1");
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[tokio::test]
+    async fn alt_svc_connects_to_the_onion_service_under_the_origin_name() {
+        let builder = ClientConfigBuilder::new()
+            .learn_onion_routes(true)
+            .pool_max_idle_per_host(0);
+        let alt_svc = format!("h3=\":443\", h2=\"{ONION}:8443\"; ma=3600");
+        let (client, log) = client(builder, move |_| {
+            respond(StatusCode::OK, &[("alt-svc", alt_svc.as_str())])
+        })
+        .await;
+
+        client.get("https://example.com/a").await.unwrap();
+        client.get("https://example.com/b").await.unwrap();
+
+        let requests = log.requests();
+        assert_eq!(requests[0].target.host, "example.com");
+        let second = &requests[1];
+        assert_eq!(
+            (second.target.host.as_str(), second.target.port),
+            (ONION, 8443)
+        );
+        assert_eq!(second.target.scheme, Scheme::HTTPS);
+        assert_eq!(second.headers[hyper::header::HOST], "example.com");
+    }
+
+    #[tokio::test]
+    async fn onion_routes_are_not_learned_over_plain_http() {
+        let builder = ClientConfigBuilder::new().learn_onion_routes(true);
+        let location = format!("http://{ONION}/");
+        let (client, _log) = client(builder, move |_| {
+            respond(StatusCode::OK, &[("onion-location", location.as_str())])
+        })
+        .await;
+
+        client.get("http://example.com/").await.unwrap();
+        assert!(client.onion_routes().is_empty());
+    }
+}
@@ -0,0 +1,363 @@
+use super::uri::is_onion;
+use super::BoxFuture;
+#[cfg(feature = "auth")]
+use base64::Engine;
+use http::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
+use http::request::Parts;
+use http::Uri;
+#[cfg(feature = "auth")]
+use md5::Md5;
+#[cfg(feature = "auth")]
+use rand::RngCore;
+#[cfg(feature = "auth")]
+use sha2::{Digest as _, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+/// Credentials for answering `401 Unauthorized` challenges.
+///
+/// Passwords and tokens are wiped from memory when the credentials are
+/// dropped.
+#[derive(Clone)]
+pub enum Credentials {
+    /// A username and password, used for Basic and Digest authentication.
+    Password {
+        /// The username.
+        username: String,
+        /// The password.
+        password: Zeroizing<String>,
+    },
+    /// A token sent with the Bearer scheme.
+    Bearer(Zeroizing<String>),
+}
+
+impl Credentials {
+    /// Creates credentials for Basic and Digest authentication.
+    pub fn password(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Credentials::Password {
+            username: username.into(),
+            password: Zeroizing::new(password.into()),
+        }
+    }
+
+    /// Creates credentials for the Bearer scheme.
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Credentials::Bearer(Zeroizing::new(token.into()))
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::Password { username, .. } => f
+                .debug_struct("Password")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+            Credentials::Bearer(_) => f.debug_tuple("Bearer").field(&"<redacted>").finish(),
+        }
+    }
+}
+
+/// Supplies credentials for origins, e.g. from the platform keychain.
+pub trait CredentialsProvider: Send + Sync {
+    /// Returns the credentials for `origin` (`scheme://host:port`) and the
+    /// realm named in the challenge, if any.
+    fn credentials<'a>(
+        &'a self,
+        origin: &'a str,
+        realm: Option<&'a str>,
+    ) -> BoxFuture<'a, Option<Credentials>>;
+}
+
+impl<F, Fut> CredentialsProvider for F
+where
+    F: Fn(String, Option<String>) -> Fut + Send + Sync,
+    Fut: Future<Output = Option<Credentials>> + Send + 'static,
+{
+    fn credentials<'a>(
+        &'a self,
+        origin: &'a str,
+        realm: Option<&'a str>,
+    ) -> BoxFuture<'a, Option<Credentials>> {
+        Box::pin(self(origin.to_string(), realm.map(str::to_string)))
+    }
+}
+
+/// An in-memory [`CredentialsProvider`] keyed by origin.
+#[derive(Debug, Default)]
+pub struct CredentialsStore {
+    entries: Mutex<HashMap<String, Credentials>>,
+}
+
+impl CredentialsStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores credentials for an origin such as `https://example.com:443`.
+    pub fn insert(&self, origin: impl Into<String>, credentials: Credentials) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(origin.into(), credentials);
+    }
+
+    /// Removes the credentials stored for an origin.
+    pub fn remove(&self, origin: &str) -> Option<Credentials> {
+        self.entries.lock().unwrap().remove(origin)
+    }
+}
+
+impl CredentialsProvider for CredentialsStore {
+    fn credentials<'a>(
+        &'a self,
+        origin: &'a str,
+        _realm: Option<&'a str>,
+    ) -> BoxFuture<'a, Option<Credentials>> {
+        let credentials = self.entries.lock().unwrap().get(origin).cloned();
+        Box::pin(async move { credentials })
+    }
+}
+
+/// A parsed `WWW-Authenticate` challenge.
+#[derive(Debug)]
+pub(crate) struct AuthChallenge {
+    scheme: String,
+    params: HashMap<String, String>,
+}
+
+impl AuthChallenge {
+    /// Returns the realm named by the challenge, if any.
+    pub(crate) fn realm(&self) -> Option<&str> {
+        self.param("realm")
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+}
+
+/// Parses the challenges in a response's `WWW-Authenticate` headers.
+pub(crate) fn challenges(headers: &HeaderMap) -> Vec<AuthChallenge> {
+    headers
+        .get_all(WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(parse_challenges)
+        .collect()
+}
+
+/// Builds an `Authorization` value answering `challenge` for `request`.
+///
+/// Basic and Bearer credentials are only used where exit relays cannot read
+/// them. Basic and Digest need the `auth` feature.
+pub(crate) fn authorization(
+    challenge: &AuthChallenge,
+    credentials: Credentials,
+    request: &Parts,
+) -> Option<HeaderValue> {
+    let value = match (challenge.scheme.as_str(), credentials) {
+        #[cfg(feature = "auth")]
+        ("digest", Credentials::Password { username, password }) => {
+            digest_authorization(challenge, request, &username, &password).map(Zeroizing::new)
+        }
+        #[cfg(feature = "auth")]
+        ("basic", Credentials::Password { username, password })
+            if is_confidential(&request.uri) =>
+        {
+            let pair = Zeroizing::new(format!("{username}:{}", *password));
+            let token = Zeroizing::new(base64::engine::general_purpose::STANDARD.encode(&*pair));
+            Some(Zeroizing::new(format!("Basic {}", *token)))
+        }
+        ("bearer", Credentials::Bearer(token)) if is_confidential(&request.uri) => {
+            Some(Zeroizing::new(format!("Bearer {}", *token)))
+        }
+        _ => None,
+    };
+
+    let mut value = HeaderValue::from_str(&value?).ok()?;
+    value.set_sensitive(true);
+    Some(value)
+}
+
+/// Returns `true` if plaintext credentials sent to `uri` cannot be read by an exit relay.
+fn is_confidential(uri: &Uri) -> bool {
+    uri.scheme_str() == Some("https") || uri.host().is_some_and(is_onion)
+}
+
+/// Computes a Digest `Authorization` value (RFC 7616).
+#[cfg(feature = "auth")]
+fn digest_authorization(
+    challenge: &AuthChallenge,
+    request: &Parts,
+    username: &str,
+    password: &str,
+) -> Option<String> {
+    let realm = challenge.param("realm").unwrap_or("");
+    let nonce = challenge.param("nonce")?;
+    let algorithm = challenge.param("algorithm").unwrap_or("MD5");
+    let hash: fn(&str) -> String = match algorithm.to_ascii_uppercase().as_str() {
+        "MD5" | "MD5-SESS" => |data| hex::encode(Md5::digest(data.as_bytes())),
+        "SHA-256" | "SHA-256-SESS" => |data| hex::encode(Sha256::digest(data.as_bytes())),
+        _ => return None,
+    };
+
+    let uri = request
+        .uri
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let qop = challenge.param("qop").map(|qop| {
+        qop.split(',')
+            .any(|q| q.trim().eq_ignore_ascii_case("auth"))
+    });
+
+    let mut cnonce_bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut cnonce_bytes);
+    let cnonce = hex::encode(cnonce_bytes);
+    let nc = "00000001";
+
+    let mut ha1 = hash(&Zeroizing::new(format!("{username}:{realm}:{password}")));
+    if algorithm.to_ascii_uppercase().ends_with("-SESS") {
+        ha1 = hash(&format!("{ha1}:{nonce}:{cnonce}"));
+    }
+    let ha2 = hash(&format!("{}:{uri}", request.method));
+
+    let mut value = format!(
+        "Digest username=\"{username}\", realm=\"{realm}\", nonce=\"{nonce}\", uri=\"{uri}\", algorithm={algorithm}"
+    );
+    match qop {
+        Some(true) => {
+            let response = hash(&format!("{ha1}:{nonce}:{nc}:{cnonce}:auth:{ha2}"));
+            value.push_str(&format!(
+                ", response=\"{response}\", qop=auth, nc={nc}, cnonce=\"{cnonce}\""
+            ));
+        }
+        // Only `auth-int` was offered, which needs the entity body.
+        Some(false) => return None,
+        None => {
+            let response = hash(&format!("{ha1}:{nonce}:{ha2}"));
+            value.push_str(&format!(", response=\"{response}\""));
+        }
+    }
+    if let Some(opaque) = challenge.param("opaque") {
+        value.push_str(&format!(", opaque=\"{opaque}\""));
+    }
+
+    Some(value)
+}
+
+/// Parses the challenges in a `WWW-Authenticate` header value.
+fn parse_challenges(value: &str) -> Vec<AuthChallenge> {
+    let mut challenges: Vec<AuthChallenge> = Vec::new();
+    let mut rest = value;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        if rest.is_empty() {
+            break;
+        }
+
+        let end = rest
+            .find(|c: char| c == ',' || c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let token = &rest[..end];
+        rest = rest[end..].trim_start();
+
+        if let Some(after_eq) = rest.strip_prefix('=') {
+            let after_eq = after_eq.trim_start();
+            let (value, remaining) = match after_eq.strip_prefix('"') {
+                Some(quoted) => parse_quoted(quoted),
+                None => {
+                    let end = after_eq.find(',').unwrap_or(after_eq.len());
+                    (after_eq[..end].trim().to_string(), &after_eq[end..])
+                }
+            };
+            rest = remaining;
+            if let Some(challenge) = challenges.last_mut() {
+                challenge.params.insert(token.to_ascii_lowercase(), value);
+            }
+        } else {
+            challenges.push(AuthChallenge {
+                scheme: token.to_ascii_lowercase(),
+                params: HashMap::new(),
+            });
+        }
+    }
+
+    challenges
+}
+
+/// Parses a quoted string (after the opening quote), returning its unescaped
+/// contents and the remaining input.
+fn parse_quoted(input: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    value.push(escaped);
+                }
+            }
+            '"' => return (value, &input[i + 1..]),
+            c => value.push(c),
+        }
+    }
+
+    (value, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenges_in_one_header() {
+        let parsed = parse_challenges(
+            "Basic realm=\"a, b\", Digest realm=\"r\", qop=\"auth,auth-int\", nonce=n1, Bearer",
+        );
+        let schemes: Vec<&str> = parsed.iter().map(|c| c.scheme.as_str()).collect();
+        assert_eq!(schemes, ["basic", "digest", "bearer"]);
+        assert_eq!(parsed[0].realm(), Some("a, b"));
+        assert_eq!(parsed[1].param("qop"), Some("auth,auth-int"));
+        assert_eq!(parsed[1].param("nonce"), Some("n1"));
+        assert!(parsed[2].params.is_empty());
+    }
+
+    #[test]
+    fn quoted_strings_are_unescaped() {
+        let parsed = parse_challenges(r#"Basic realm="say \"hi\" \\ bye""#);
+        assert_eq!(parsed[0].realm(), Some(r#"say "hi" \ bye"#));
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        let parsed = parse_challenges("DIGEST Realm = x , NONCE=\"y\"");
+        assert_eq!(parsed[0].scheme, "digest");
+        assert_eq!(parsed[0].realm(), Some("x"));
+        assert_eq!(parsed[0].param("nonce"), Some("y"));
+    }
+
+    #[test]
+    fn challenges_across_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"one\""),
+        );
+        headers.append(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static("Bearer realm=\"two\""),
+        );
+        let parsed = challenges(&headers);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].scheme, "bearer");
+        assert_eq!(parsed[1].realm(), Some("two"));
+    }
+}
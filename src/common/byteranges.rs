@@ -0,0 +1,335 @@
+use super::{Error, Result};
+use bytes::{Buf, Bytes, BytesMut};
+use http::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_RANGE, CONTENT_TYPE};
+use http::Response;
+use http_body::Body;
+use http_body_util::BodyExt;
+
+/// Upper bound on the size of a part's header block.
+const MAX_PART_HEADERS: usize = 16 * 1024;
+
+/// A parsed `Content-Range: bytes start-end/length` value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    /// Offset of the first byte in the range.
+    pub start: u64,
+    /// Offset of the last byte in the range (inclusive).
+    pub end: u64,
+    /// Length of the complete representation, if the server knows it.
+    pub complete_length: Option<u64>,
+}
+
+impl ContentRange {
+    /// Parses a `Content-Range` header value.
+    pub fn parse(value: &str) -> Option<Self> {
+        let rest = value.trim().strip_prefix("bytes")?.trim_start();
+        let (range, length) = rest.split_once('/')?;
+        let (start, end) = range.split_once('-')?;
+        let complete_length = match length.trim() {
+            "*" => None,
+            length => Some(length.parse().ok()?),
+        };
+
+        Some(ContentRange {
+            start: start.trim().parse().ok()?,
+            end: end.trim().parse().ok()?,
+            complete_length,
+        })
+    }
+}
+
+/// One part of a `multipart/byteranges` response.
+#[derive(Debug, Clone)]
+pub struct BytesPart {
+    /// The part's headers.
+    pub headers: HeaderMap,
+    /// The range covered by this part, from its `Content-Range` header.
+    pub range: Option<ContentRange>,
+    /// The part's data.
+    pub body: Bytes,
+}
+
+/// Reads the parts of a `multipart/byteranges` body as they arrive.
+///
+/// Each part is yielded once its data is complete, so only one part is held
+/// in memory at a time.
+pub struct ByteRanges<B> {
+    body: B,
+    delimiter: Vec<u8>,
+    buf: BytesMut,
+    started: bool,
+    done: bool,
+}
+
+impl<B> ByteRanges<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Creates a parser for a body using the given multipart boundary.
+    pub fn new(body: B, boundary: &str) -> Self {
+        // Seed the buffer with a CRLF so that a delimiter at the very start of
+        // the body looks like every other delimiter.
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"\r\n");
+
+        ByteRanges {
+            body,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            buf,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Creates a parser for a `206 Partial Content` response with a
+    /// `multipart/byteranges` content type.
+    pub fn from_response(response: Response<B>) -> Result<Self> {
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| malformed("missing content type"))?;
+        let boundary = multipart_boundary(content_type, "multipart/byteranges")
+            .ok_or_else(|| malformed("not a multipart/byteranges response"))?;
+
+        Ok(Self::new(response.into_body(), &boundary))
+    }
+
+    /// Returns the next part, or `None` once the closing delimiter is reached.
+    pub async fn next_part(&mut self) -> Option<Result<BytesPart>> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.read_part().await;
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+
+    async fn read_part(&mut self) -> Result<Option<BytesPart>> {
+        if !self.started {
+            // Skip the preamble.
+            let delimiter = self.delimiter.clone();
+            let pos = self.find(&delimiter, None).await?;
+            self.buf.advance(pos + delimiter.len());
+            self.started = true;
+        }
+
+        // After a delimiter comes either `--` (the end) or optional padding and a line break.
+        while self.buf.len() < 2 {
+            if !self.read_more().await? {
+                return Err(malformed("unexpected end of multipart body"));
+            }
+        }
+        if self.buf.starts_with(b"--") {
+            return Ok(None);
+        }
+        let line_end = self.find(b"\r\n", Some(MAX_PART_HEADERS)).await?;
+        self.buf.advance(line_end + 2);
+
+        let headers = if self.buf.starts_with(b"\r\n") {
+            self.buf.advance(2);
+            HeaderMap::new()
+        } else {
+            let end = self.find(b"\r\n\r\n", Some(MAX_PART_HEADERS)).await?;
+            let block = self.buf.split_to(end + 4);
+            parse_headers(&block[..end])?
+        };
+
+        let delimiter = self.delimiter.clone();
+        let end = self.find(&delimiter, None).await?;
+        let body = self.buf.split_to(end).freeze();
+        self.buf.advance(delimiter.len());
+
+        let range = headers
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(ContentRange::parse);
+
+        Ok(Some(BytesPart {
+            headers,
+            range,
+            body,
+        }))
+    }
+
+    /// Reads until `needle` is in the buffer and returns its position.
+    async fn find(&mut self, needle: &[u8], limit: Option<usize>) -> Result<usize> {
+        let mut searched = 0;
+        loop {
+            if let Some(pos) = self.buf[searched..]
+                .windows(needle.len())
+                .position(|window| window == needle)
+            {
+                return Ok(searched + pos);
+            }
+            searched = self.buf.len().saturating_sub(needle.len() - 1);
+
+            if limit.is_some_and(|limit| self.buf.len() > limit) {
+                return Err(malformed("multipart part headers too large"));
+            }
+            if !self.read_more().await? {
+                return Err(malformed("unexpected end of multipart body"));
+            }
+        }
+    }
+
+    /// Appends the next data frame to the buffer; returns `false` at the end of the body.
+    async fn read_more(&mut self) -> Result<bool> {
+        while let Some(frame) = self.body.frame().await {
+            let frame = frame.map_err(|e| Error::Http(e.into()))?;
+            if let Ok(data) = frame.into_data() {
+                self.buf.extend_from_slice(&data);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Extracts the boundary parameter from a multipart content type of the given kind.
+pub(crate) fn multipart_boundary(content_type: &str, kind: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params.next()?.trim().eq_ignore_ascii_case(kind) {
+        return None;
+    }
+
+    params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn parse_headers(block: &[u8]) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for line in block.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let colon = line
+            .iter()
+            .position(|b| *b == b':')
+            .ok_or_else(|| malformed("malformed multipart header"))?;
+        let name = HeaderName::from_bytes(&line[..colon]).map_err(|e| Error::Decode(e.into()))?;
+        let value = HeaderValue::from_bytes(line[colon + 1..].trim_ascii())
+            .map_err(|e| Error::Decode(e.into()))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::Decode(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body::Frame;
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// A body delivered in the given frames.
+    struct Frames(VecDeque<Bytes>);
+
+    impl Frames {
+        fn split(body: &[u8], size: usize) -> Self {
+            Frames(body.chunks(size).map(Bytes::copy_from_slice).collect())
+        }
+    }
+
+    impl Body for Frames {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<std::result::Result<Frame<Bytes>, Infallible>>> {
+            Poll::Ready(self.0.pop_front().map(|data| Ok(Frame::data(data))))
+        }
+    }
+
+    const TWO_PARTS: &[u8] = b"preamble\r\n--sep\r\n\
+        Content-Type: text/plain\r\nContent-Range: bytes 0-4/20\r\n\r\n\
+        hello\r\n--sep\r\n\
+        Content-Type: text/plain\r\nContent-Range: bytes 15-19/20\r\n\r\n\
+        world\r\n--sep--\r\n";
+
+    async fn parts(body: Frames) -> Result<Vec<BytesPart>> {
+        let mut ranges = ByteRanges::new(body, "sep");
+        let mut parts = Vec::new();
+        while let Some(part) = ranges.next_part().await {
+            parts.push(part?);
+        }
+        Ok(parts)
+    }
+
+    #[tokio::test]
+    async fn boundary_split_across_frames() {
+        for size in [1, 2, 3, 7, TWO_PARTS.len()] {
+            let parts = parts(Frames::split(TWO_PARTS, size)).await.unwrap();
+            assert_eq!(parts.len(), 2, "frames of {size} bytes");
+            assert_eq!(parts[0].body, "hello");
+            assert_eq!(
+                parts[0].range,
+                Some(ContentRange {
+                    start: 0,
+                    end: 4,
+                    complete_length: Some(20),
+                })
+            );
+            assert_eq!(parts[1].body, "world");
+            assert_eq!(parts[1].range.map(|range| range.start), Some(15));
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_part_headers() {
+        let body = b"--sep\r\nContent-Range bytes 0-4/20\r\n\r\nhello\r\n--sep--\r\n";
+        let err = parts(Frames::split(body, 4)).await.unwrap_err();
+        assert!(matches!(err, Error::Decode(_)), "{err:?}");
+
+        let body = b"--sep\r\nBad Name: x\r\n\r\nhello\r\n--sep--\r\n";
+        let err = parts(Frames::split(body, 4)).await.unwrap_err();
+        assert!(matches!(err, Error::Decode(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn missing_content_range() {
+        let body = b"--sep\r\nContent-Type: text/plain\r\n\r\nhello\r\n--sep--\r\n";
+        let parts = parts(Frames::split(body, 5)).await.unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].range, None);
+        assert_eq!(parts[0].body, "hello");
+    }
+
+    #[tokio::test]
+    async fn truncated_body() {
+        let body = &TWO_PARTS[..TWO_PARTS.len() - 20];
+        let err = parts(Frames::split(body, 8)).await.unwrap_err();
+        assert!(matches!(err, Error::Decode(_)), "{err:?}");
+    }
+
+    #[test]
+    fn parse_content_range() {
+        assert_eq!(
+            ContentRange::parse("bytes 10-19/*"),
+            Some(ContentRange {
+                start: 10,
+                end: 19,
+                complete_length: None,
+            })
+        );
+        assert_eq!(ContentRange::parse("items 0-1/2"), None);
+        assert_eq!(ContentRange::parse("bytes 0-x/2"), None);
+    }
+}
@@ -0,0 +1,144 @@
+use super::{BoxFuture, Result};
+use bytes::Bytes;
+use http::header::{HeaderMap, HeaderName, HeaderValue, COOKIE};
+use http::response::Parts;
+use http::{StatusCode, Uri};
+use std::future::Future;
+use std::sync::Arc;
+
+/// A response that a [`ChallengeSolver`] flagged as a possible challenge.
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    /// The URI of the request that was challenged.
+    pub uri: Uri,
+    /// The status of the challenge response.
+    pub status: StatusCode,
+    /// The headers of the challenge response.
+    pub headers: HeaderMap,
+    /// The buffered body of the challenge response.
+    pub body: Bytes,
+}
+
+/// The result of solving a [`Challenge`].
+#[derive(Debug, Clone, Default)]
+pub struct ChallengeSolution {
+    /// Headers to add to the request before it is retried (e.g. a clearance cookie or token).
+    pub headers: HeaderMap,
+    /// Whether the headers should also be sent on later requests to the same origin.
+    pub persist: bool,
+}
+
+/// Recognizes and solves proof-of-work style challenges (Anubis, custom onion
+/// service gates, ...) so that requests can be retried transparently.
+pub trait ChallengeSolver: Send + Sync {
+    /// Returns `true` if a response with these parts might be a challenge.
+    ///
+    /// This is checked before the body is read; only candidate responses are
+    /// buffered and passed to [`ChallengeSolver::solve`].
+    fn is_candidate(&self, response: &Parts) -> bool;
+
+    /// Solves `challenge`, or returns `None` if it is not a challenge this
+    /// solver understands.
+    fn solve<'a>(
+        &'a self,
+        challenge: &'a Challenge,
+    ) -> BoxFuture<'a, Result<Option<ChallengeSolution>>>;
+}
+
+impl ChallengeSolution {
+    /// Adds a header to send with the retried request.
+    pub fn header(mut self, name: HeaderName, value: &str) -> Result<Self> {
+        self.headers.insert(
+            name,
+            HeaderValue::from_str(value).map_err(http::Error::from)?,
+        );
+        Ok(self)
+    }
+
+    /// Adds a cookie to send with the retried request.
+    pub fn cookie(mut self, name: &str, value: &str) -> Result<Self> {
+        let pair = format!("{name}={value}");
+        let cookie = match self.headers.get(COOKIE).and_then(|v| v.to_str().ok()) {
+            Some(existing) => format!("{existing}; {pair}"),
+            None => pair,
+        };
+        self.headers.insert(
+            COOKIE,
+            HeaderValue::from_str(&cookie).map_err(http::Error::from)?,
+        );
+        Ok(self)
+    }
+
+    /// Marks the solution to be reused for later requests to the same origin.
+    pub fn persistent(mut self) -> Self {
+        self.persist = true;
+        self
+    }
+}
+
+type BodyMatcher = dyn Fn(&[u8]) -> bool + Send + Sync;
+type Resolver =
+    dyn Fn(Challenge) -> BoxFuture<'static, Result<Option<ChallengeSolution>>> + Send + Sync;
+
+/// A [`ChallengeSolver`] for captcha and queue interstitials.
+///
+/// Responses with one of the configured statuses (`503 Service Unavailable` by
+/// default) whose body matches every configured matcher are passed to the
+/// application-supplied resolver, which returns the token or cookie to retry
+/// the original request with.
+pub struct Interstitial {
+    statuses: Vec<StatusCode>,
+    matchers: Vec<Box<BodyMatcher>>,
+    resolver: Arc<Resolver>,
+}
+
+impl Interstitial {
+    /// Creates a new `Interstitial` that resolves matching pages with `resolver`.
+    pub fn new<F, Fut>(resolver: F) -> Self
+    where
+        F: Fn(Challenge) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Option<ChallengeSolution>>> + Send + 'static,
+    {
+        Interstitial {
+            statuses: vec![StatusCode::SERVICE_UNAVAILABLE],
+            matchers: Vec::new(),
+            resolver: Arc::new(move |challenge| Box::pin(resolver(challenge))),
+        }
+    }
+
+    /// Sets the response statuses that may indicate an interstitial.
+    pub fn statuses(mut self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Requires the body to contain `needle`.
+    pub fn body_contains(self, needle: impl Into<Vec<u8>>) -> Self {
+        let needle = needle.into();
+        self.body_matches(move |body| {
+            needle.is_empty() || body.windows(needle.len()).any(|w| w == needle.as_slice())
+        })
+    }
+
+    /// Requires the body to satisfy `matcher`.
+    pub fn body_matches(mut self, matcher: impl Fn(&[u8]) -> bool + Send + Sync + 'static) -> Self {
+        self.matchers.push(Box::new(matcher));
+        self
+    }
+}
+
+impl ChallengeSolver for Interstitial {
+    fn is_candidate(&self, response: &Parts) -> bool {
+        self.statuses.contains(&response.status)
+    }
+
+    fn solve<'a>(
+        &'a self,
+        challenge: &'a Challenge,
+    ) -> BoxFuture<'a, Result<Option<ChallengeSolution>>> {
+        if !self.matchers.iter().all(|matcher| matcher(&challenge.body)) {
+            return Box::pin(async { Ok(None) });
+        }
+        (self.resolver)(challenge.clone())
+    }
+}
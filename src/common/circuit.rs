@@ -0,0 +1,40 @@
+use std::net::SocketAddr;
+
+/// The Tor circuit a response was received over.
+///
+/// Inserted into the extensions of responses received through arti; absent
+/// with a custom [`Connector`](crate::Connector). The circuit path only
+/// names relays by their keys and addresses, so nicknames and countries
+/// have to be looked up in the consensus.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CircuitInfo {
+    /// The relays the circuit was built through, guard first.
+    ///
+    /// For an onion service this ends at the rendezvous point; the relays
+    /// on the service's side are unknown.
+    pub relays: Vec<RelayInfo>,
+    /// Whether the circuit leads to an onion service rather than an exit.
+    pub onion_service: bool,
+}
+
+impl CircuitInfo {
+    /// Returns the relay the stream left the Tor network from, or `None`
+    /// for onion services, which are reached without one.
+    pub fn exit(&self) -> Option<&RelayInfo> {
+        if self.onion_service {
+            return None;
+        }
+        self.relays.last()
+    }
+}
+
+/// One relay of a [`CircuitInfo`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelayInfo {
+    /// The relay's fingerprint: its RSA identity in uppercase hex.
+    pub fingerprint: Option<String>,
+    /// The relay's Ed25519 identity, in unpadded base64.
+    pub ed_identity: Option<String>,
+    /// The addresses the relay listens on for other relays.
+    pub addrs: Vec<SocketAddr>,
+}
@@ -0,0 +1,80 @@
+use http::header::{HeaderMap, DATE};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How far the local clock is from a server's, judged by its `Date` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSkew {
+    /// The local clock is behind the server's by this much.
+    Slow(Duration),
+    /// The local clock is ahead of the server's by this much.
+    Fast(Duration),
+}
+
+impl ClockSkew {
+    /// Compares the `Date` header in `headers` with `now`.
+    ///
+    /// Returns `None` if there is no header in the IMF-fixdate format, e.g.
+    /// `Sun, 06 Nov 1994 08:49:37 GMT`, which servers are required to use.
+    pub fn from_headers(headers: &HeaderMap, now: SystemTime) -> Option<Self> {
+        let date = parse_http_date(headers.get(DATE)?.to_str().ok()?)?;
+        Some(match date.duration_since(now) {
+            Ok(ahead) => ClockSkew::Slow(ahead),
+            Err(e) => ClockSkew::Fast(e.duration()),
+        })
+    }
+
+    /// Returns how far the clocks are apart.
+    pub fn magnitude(&self) -> Duration {
+        match self {
+            ClockSkew::Slow(skew) | ClockSkew::Fast(skew) => *skew,
+        }
+    }
+}
+
+/// Parses an IMF-fixdate (RFC 9110, section 5.6.7).
+pub(crate) fn parse_http_date(value: &str) -> Option<SystemTime> {
+    // "Sun, 06 Nov 1994 08:49:37 GMT"
+    let mut parts = value.split(' ');
+    let (_weekday, day, month, year, time, zone) = (
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+    );
+    if parts.next().is_some() || zone != "GMT" || day.len() != 2 || year.len() != 4 {
+        return None;
+    }
+
+    let day: u32 = day.parse().ok()?;
+    let month = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ]
+    .iter()
+    .position(|name| *name == month)? as u32
+        + 1;
+    let year: i64 = year.parse().ok()?;
+    let mut clock = time.split(':').map(|n| n.parse::<u64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if clock.next().is_some() || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60
+    {
+        return None;
+    }
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Counts the days from 1970-01-01 to the given date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Howard Hinnant's algorithm, with years starting in March.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
@@ -0,0 +1,352 @@
+use super::clock::parse_http_date;
+use super::uri::is_onion;
+use http::header::HeaderValue;
+use http::Uri;
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// A cookie set by a server with `Set-Cookie` (RFC 6265).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Cookie {
+    /// The cookie's name.
+    pub name: String,
+    /// The cookie's value.
+    pub value: String,
+    /// The lowercase domain the cookie is sent to.
+    pub domain: String,
+    /// Sent to `domain` only, not to its subdomains.
+    pub host_only: bool,
+    /// The path prefix the cookie is sent to.
+    pub path: String,
+    /// Only sent over HTTPS or to onion services.
+    pub secure: bool,
+    /// Not meant to be exposed to scripts; kept for stores that care.
+    pub http_only: bool,
+    /// When the cookie expires; `None` for a session cookie.
+    pub expires: Option<SystemTime>,
+}
+
+impl Cookie {
+    /// Parses a `Set-Cookie` value received in a response from `uri`.
+    ///
+    /// Returns `None` for malformed cookies and for cookies the origin may
+    /// not set, e.g. for another domain or `Secure` ones over plain HTTP.
+    pub fn parse(set_cookie: &str, uri: &Uri, now: SystemTime) -> Option<Self> {
+        let host = uri.host()?.to_ascii_lowercase();
+        let mut attributes = set_cookie.split(';');
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(uri),
+            secure: false,
+            http_only: false,
+            expires: None,
+        };
+        let mut max_age = None;
+        for attribute in attributes {
+            let (key, value) = match attribute.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (attribute.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    // Without a public suffix list, at least refuse cookies
+                    // for top-level domains.
+                    let allowed =
+                        domain == host || (domain.contains('.') && domain_matches(&host, &domain));
+                    if !allowed {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = domain_is_host(&cookie.domain, &host);
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "max-age" => {
+                    let Ok(seconds) = value.parse::<i64>() else {
+                        continue;
+                    };
+                    max_age = Some(match u64::try_from(seconds) {
+                        Ok(seconds) if seconds > 0 => now + Duration::from_secs(seconds),
+                        _ => SystemTime::UNIX_EPOCH,
+                    });
+                }
+                "expires" => cookie.expires = parse_http_date(value),
+                _ => {}
+            }
+        }
+        if max_age.is_some() {
+            cookie.expires = max_age;
+        }
+        if cookie.secure && !is_secure(uri) {
+            return None;
+        }
+        Some(cookie)
+    }
+
+    /// Returns `true` if the cookie has expired at `now`.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Returns `true` if the cookie is sent with a request to `uri` at `now`.
+    pub fn matches(&self, uri: &Uri, now: SystemTime) -> bool {
+        let Some(host) = uri.host().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        let domain = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+        domain
+            && path_matches(uri.path(), &self.path)
+            && (!self.secure || is_secure(uri))
+            && !self.is_expired(now)
+    }
+
+    /// Returns `true` if `other` takes this cookie's place when stored.
+    fn replaces(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+}
+
+/// Stores cookies between requests.
+///
+/// Installed with
+/// [`ClientConfigBuilder::cookie_store`](crate::ClientConfigBuilder::cookie_store),
+/// a store sees every response's `Set-Cookie` headers and supplies the
+/// `Cookie` header of every request. Cookies link requests to each other,
+/// so use a separate store per identity.
+pub trait CookieStore: Send + Sync {
+    /// Stores the cookies set by a response from `uri`.
+    fn set_cookies(&self, uri: &Uri, set_cookies: &mut dyn Iterator<Item = &HeaderValue>);
+
+    /// Returns the `Cookie` header for a request to `uri`, if any cookies
+    /// apply.
+    fn cookies(&self, uri: &Uri) -> Option<HeaderValue>;
+
+    /// Returns `true` if the store writes cookies to disk, which
+    /// [memory-only](crate::ClientConfigBuilder::memory_only) clients refuse.
+    fn is_persistent(&self) -> bool {
+        self.path().is_some()
+    }
+
+    /// Returns the file the store writes cookies to, if any.
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Returns every stored cookie, for session snapshots. Stores that
+    /// cannot list their cookies return `None` and are left out of them.
+    fn all_cookies(&self) -> Option<Vec<Cookie>> {
+        None
+    }
+
+    /// Replaces the stored cookies with those of a restored session
+    /// snapshot, or with none on [`Client::new_identity`](crate::Client::new_identity).
+    /// Does nothing by default.
+    fn replace_cookies(&self, cookies: Vec<Cookie>) {
+        let _ = cookies;
+    }
+}
+
+impl<T: CookieStore + ?Sized> CookieStore for Arc<T> {
+    fn set_cookies(&self, uri: &Uri, set_cookies: &mut dyn Iterator<Item = &HeaderValue>) {
+        (**self).set_cookies(uri, set_cookies)
+    }
+
+    fn cookies(&self, uri: &Uri) -> Option<HeaderValue> {
+        (**self).cookies(uri)
+    }
+
+    fn is_persistent(&self) -> bool {
+        (**self).is_persistent()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        (**self).path()
+    }
+
+    fn all_cookies(&self) -> Option<Vec<Cookie>> {
+        (**self).all_cookies()
+    }
+
+    fn replace_cookies(&self, cookies: Vec<Cookie>) {
+        (**self).replace_cookies(cookies)
+    }
+}
+
+/// An in-memory [`CookieStore`].
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    /// Creates an empty jar.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `cookie`, replacing one with the same name, domain and path.
+    ///
+    /// An expired cookie removes the one it replaces.
+    pub fn insert(&self, cookie: Cookie) {
+        self.insert_all([cookie], SystemTime::now());
+    }
+
+    /// Returns the cookies that have not expired.
+    pub fn all(&self) -> Vec<Cookie> {
+        let now = SystemTime::now();
+        let cookies = self.cookies.lock().unwrap();
+        cookies
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .cloned()
+            .collect()
+    }
+
+    /// Removes every cookie.
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    /// Stores `new` cookies, returning `true` if the jar changed.
+    pub(crate) fn insert_all(
+        &self,
+        new: impl IntoIterator<Item = Cookie>,
+        now: SystemTime,
+    ) -> bool {
+        let mut cookies = self.cookies.lock().unwrap();
+        let before = cookies.len();
+        cookies.retain(|cookie| !cookie.is_expired(now));
+        let mut changed = cookies.len() != before;
+        for cookie in new {
+            let expired = cookie.is_expired(now);
+            // A replaced cookie keeps its place, which orders cookies of
+            // equal path length by creation (RFC 6265, 5.4).
+            match cookies.iter().position(|old| old.replaces(&cookie)) {
+                Some(index) if expired => {
+                    cookies.remove(index);
+                    changed = true;
+                }
+                Some(index) => {
+                    changed |= cookies[index] != cookie;
+                    cookies[index] = cookie;
+                }
+                None if expired => {}
+                None => {
+                    cookies.push(cookie);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Stores the cookies set by a response from `uri`, returning `true`
+    /// if the jar changed.
+    pub(crate) fn update(
+        &self,
+        uri: &Uri,
+        set_cookies: &mut dyn Iterator<Item = &HeaderValue>,
+    ) -> bool {
+        let now = SystemTime::now();
+        let cookies = set_cookies
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| Cookie::parse(value, uri, now))
+            .collect::<Vec<_>>();
+        self.insert_all(cookies, now)
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, uri: &Uri, set_cookies: &mut dyn Iterator<Item = &HeaderValue>) {
+        self.update(uri, set_cookies);
+    }
+
+    fn cookies(&self, uri: &Uri) -> Option<HeaderValue> {
+        let now = SystemTime::now();
+        let cookies = self.cookies.lock().unwrap();
+        let mut matching: Vec<&Cookie> = cookies
+            .iter()
+            .filter(|cookie| cookie.matches(uri, now))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        // Cookies with longer paths are listed first (RFC 6265, 5.4).
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        let header = matching
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        HeaderValue::from_str(&header).ok()
+    }
+
+    fn all_cookies(&self) -> Option<Vec<Cookie>> {
+        Some(self.all())
+    }
+
+    fn replace_cookies(&self, cookies: Vec<Cookie>) {
+        self.clear();
+        self.insert_all(cookies, SystemTime::now());
+    }
+}
+
+/// Returns `true` if secure cookies may be exchanged with `uri`: over HTTPS,
+/// or with an onion service, which Tor authenticates and encrypts.
+fn is_secure(uri: &Uri) -> bool {
+    uri.scheme_str() == Some("https") || uri.host().is_some_and(is_onion)
+}
+
+/// Returns `true` if `host` is `domain` or one of its subdomains.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    host.parse::<IpAddr>().is_err()
+        && host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Returns `true` if a `Domain` attribute naming `domain` still only
+/// matches `host`, which is the case for IP addresses.
+fn domain_is_host(domain: &str, host: &str) -> bool {
+    domain == host && host.parse::<IpAddr>().is_ok()
+}
+
+/// Returns `true` if `cookie_path` covers `request_path` (RFC 6265, 5.1.4).
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    match request_path.strip_prefix(cookie_path) {
+        Some(rest) => cookie_path.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Returns the path a cookie without a `Path` attribute is scoped to.
+fn default_path(uri: &Uri) -> String {
+    let path = uri.path();
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(end) => path[..end].to_string(),
+    }
+}
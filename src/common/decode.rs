@@ -0,0 +1,495 @@
+use bytes::Bytes;
+use http::header::{HeaderMap, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH};
+use http::Response;
+use http_body::{Body, Frame, SizeHint};
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Decoded bytes allowed regardless of the ratio limit, so that small,
+/// highly compressible documents are not mistaken for bombs.
+const RATIO_GRACE: u64 = 1024 * 1024;
+
+/// A content coding hypertor can encode and decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    /// `gzip` (RFC 1952).
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// `deflate`: zlib-wrapped DEFLATE (RFC 1950).
+    #[cfg(feature = "deflate")]
+    Deflate,
+    /// `br` (RFC 7932).
+    #[cfg(feature = "brotli")]
+    Brotli,
+    /// `zstd` (RFC 8878).
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl ContentEncoding {
+    /// Returns the token used for this coding in HTTP headers.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            ContentEncoding::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            ContentEncoding::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd => "zstd",
+        }
+    }
+
+    /// Parses a `Content-Encoding` token.
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token.trim().to_ascii_lowercase().as_str() {
+            #[cfg(feature = "gzip")]
+            "gzip" | "x-gzip" => Some(ContentEncoding::Gzip),
+            #[cfg(feature = "deflate")]
+            "deflate" => Some(ContentEncoding::Deflate),
+            #[cfg(feature = "brotli")]
+            "br" => Some(ContentEncoding::Brotli),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(ContentEncoding::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Every coding that can be decoded, in the order browsers list them.
+const DECODABLE: &[ContentEncoding] = &[
+    #[cfg(feature = "gzip")]
+    ContentEncoding::Gzip,
+    #[cfg(feature = "deflate")]
+    ContentEncoding::Deflate,
+    #[cfg(feature = "brotli")]
+    ContentEncoding::Brotli,
+    #[cfg(feature = "zstd")]
+    ContentEncoding::Zstd,
+];
+
+/// Returns the `Accept-Encoding` value asking for any decodable coding.
+pub(crate) fn accept_encoding() -> HeaderValue {
+    let codings: Vec<&str> = DECODABLE.iter().map(ContentEncoding::as_str).collect();
+    HeaderValue::from_str(&codings.join(", ")).expect("coding tokens are valid header values")
+}
+
+/// Bounds on how far a compressed body may expand while it is decoded.
+///
+/// Hostile servers can send a few kilobytes that expand to gigabytes; these
+/// limits abort decoding with [`DecompressionBomb`] long before that.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeLimits {
+    /// Largest allowed ratio of decoded to encoded bytes. The first MiB of
+    /// output is always allowed.
+    pub max_ratio: u64,
+    /// Largest allowed decoded size in bytes; `None` means unlimited.
+    pub max_decoded_size: Option<u64>,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_ratio: 100,
+            max_decoded_size: Some(256 * 1024 * 1024),
+        }
+    }
+}
+
+/// Error returned when a body exceeds its [`DecodeLimits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecompressionBomb {
+    /// Encoded bytes read when decoding was aborted.
+    pub encoded: u64,
+    /// Decoded bytes produced when decoding was aborted.
+    pub decoded: u64,
+}
+
+impl std::fmt::Display for DecompressionBomb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "decompression limit exceeded after expanding {} bytes to {} bytes",
+            self.encoded, self.decoded
+        )
+    }
+}
+
+impl std::error::Error for DecompressionBomb {}
+
+/// Collects decoded output and refuses to grow past its budget.
+struct Sink {
+    buf: Vec<u8>,
+    budget: u64,
+    exceeded: bool,
+}
+
+impl Write for Sink {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if self.buf.len() as u64 + data.len() as u64 > self.budget {
+            self.exceeded = true;
+            return Err(std::io::Error::other("decompression limit exceeded"));
+        }
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Decoder {
+    #[cfg(feature = "gzip")]
+    Gzip(Box<flate2::write::GzDecoder<Sink>>),
+    #[cfg(feature = "deflate")]
+    Deflate(Box<flate2::write::ZlibDecoder<Sink>>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::DecompressorWriter<Sink>>),
+    #[cfg(feature = "zstd")]
+    Zstd(Box<zstd::stream::write::Decoder<'static, Sink>>),
+}
+
+impl Decoder {
+    fn new(encoding: ContentEncoding) -> Self {
+        let sink = Sink {
+            buf: Vec::new(),
+            budget: 0,
+            exceeded: false,
+        };
+        match encoding {
+            #[cfg(feature = "gzip")]
+            ContentEncoding::Gzip => Decoder::Gzip(Box::new(flate2::write::GzDecoder::new(sink))),
+            #[cfg(feature = "deflate")]
+            ContentEncoding::Deflate => {
+                Decoder::Deflate(Box::new(flate2::write::ZlibDecoder::new(sink)))
+            }
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => {
+                Decoder::Brotli(Box::new(brotli::DecompressorWriter::new(sink, 8192)))
+            }
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd => {
+                let mut decoder = zstd::stream::write::Decoder::new(sink)
+                    .expect("failed to allocate a zstd context");
+                // RFC 9659 caps the window at 8 MiB for HTTP, which bounds
+                // the memory a hostile frame can make the decoder allocate.
+                decoder
+                    .window_log_max(23)
+                    .expect("window size is within zstd's bounds");
+                Decoder::Zstd(Box::new(decoder))
+            }
+        }
+    }
+
+    fn sink(&mut self) -> &mut Sink {
+        match self {
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(decoder) => decoder.get_mut(),
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate(decoder) => decoder.get_mut(),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(decoder) => decoder.get_mut(),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(decoder) => decoder.get_mut(),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(decoder) => decoder.write_all(data),
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate(decoder) => decoder.write_all(data),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(decoder) => decoder.write_all(data),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(decoder) => decoder.write_all(data),
+        }
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(decoder) => decoder.try_finish(),
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate(decoder) => decoder.try_finish(),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(decoder) => decoder.close(),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(decoder) => decoder.flush(),
+        }
+    }
+}
+
+/// A body that decodes a content-coded body as it is read, within
+/// [`DecodeLimits`].
+///
+/// Exceeding the limits fails the body with a [`DecompressionBomb`] error,
+/// which can be recovered with `downcast_ref`.
+pub struct DecodedBody<B> {
+    inner: B,
+    decoder: Decoder,
+    limits: DecodeLimits,
+    encoded: u64,
+    decoded: u64,
+    done: bool,
+    trailers: Option<Frame<Bytes>>,
+}
+
+impl<B> DecodedBody<B> {
+    /// Wraps `body`, which is encoded with `encoding`.
+    pub fn new(body: B, encoding: ContentEncoding, limits: DecodeLimits) -> Self {
+        DecodedBody {
+            inner: body,
+            decoder: Decoder::new(encoding),
+            limits,
+            encoded: 0,
+            decoded: 0,
+            done: false,
+            trailers: None,
+        }
+    }
+
+    /// Decodes `response` if its `Content-Encoding` is a single supported
+    /// coding, removing the headers that described the encoded body.
+    ///
+    /// Returns the response unchanged otherwise.
+    pub fn from_response(
+        response: Response<B>,
+        limits: DecodeLimits,
+    ) -> Result<Response<Self>, Response<B>> {
+        let Some(encoding) = single_coding(response.headers()) else {
+            return Err(response);
+        };
+
+        let (mut parts, body) = response.into_parts();
+        parts.headers.remove(CONTENT_ENCODING);
+        parts.headers.remove(CONTENT_LENGTH);
+        Ok(Response::from_parts(
+            parts,
+            DecodedBody::new(body, encoding, limits),
+        ))
+    }
+
+    /// Number of output bytes the decoder may still produce.
+    fn budget(&self) -> u64 {
+        let by_ratio = self
+            .encoded
+            .saturating_mul(self.limits.max_ratio)
+            .max(RATIO_GRACE);
+        let cap = self
+            .limits
+            .max_decoded_size
+            .unwrap_or(u64::MAX)
+            .min(by_ratio);
+        cap.saturating_sub(self.decoded)
+    }
+
+    /// Runs `step` against the decoder and returns the output it produced.
+    fn run(
+        &mut self,
+        step: impl FnOnce(&mut Decoder) -> std::io::Result<()>,
+    ) -> Result<Bytes, Box<dyn std::error::Error + Send + Sync>> {
+        let budget = self.budget();
+        self.decoder.sink().budget = budget;
+
+        let result = step(&mut self.decoder);
+        let sink = self.decoder.sink();
+        let output = std::mem::take(&mut sink.buf);
+        self.decoded += output.len() as u64;
+        if sink.exceeded {
+            return Err(Box::new(DecompressionBomb {
+                encoded: self.encoded,
+                decoded: self.decoded,
+            }));
+        }
+        result?;
+        Ok(Bytes::from(output))
+    }
+}
+
+impl<B> Body for DecodedBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    type Data = Bytes;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(this.trailers.take().map(Ok));
+        }
+
+        loop {
+            match Pin::new(&mut this.inner).poll_frame(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Err(e))) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                Poll::Ready(Some(Ok(frame))) => match frame.into_data() {
+                    Ok(data) => {
+                        this.encoded += data.len() as u64;
+                        match this.run(|decoder| decoder.write(&data)) {
+                            Ok(output) if output.is_empty() => continue,
+                            Ok(output) => return Poll::Ready(Some(Ok(Frame::data(output)))),
+                            Err(e) => {
+                                this.done = true;
+                                return Poll::Ready(Some(Err(e)));
+                            }
+                        }
+                    }
+                    Err(frame) => {
+                        // Trailers end the body; flush the decoder first.
+                        this.done = true;
+                        return match this.run(Decoder::finish) {
+                            Ok(output) if !output.is_empty() => {
+                                this.trailers = Some(frame);
+                                Poll::Ready(Some(Ok(Frame::data(output))))
+                            }
+                            Ok(_) => Poll::Ready(Some(Ok(frame))),
+                            Err(e) => Poll::Ready(Some(Err(e))),
+                        };
+                    }
+                },
+                Poll::Ready(None) => {
+                    this.done = true;
+                    return match this.run(Decoder::finish) {
+                        Ok(output) if output.is_empty() => Poll::Ready(None),
+                        Ok(output) => Poll::Ready(Some(Ok(Frame::data(output)))),
+                        Err(e) => Poll::Ready(Some(Err(e))),
+                    };
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
+
+/// Returns the coding of a body encoded exactly once with a supported coding.
+fn single_coding(headers: &HeaderMap) -> Option<ContentEncoding> {
+    let mut codings = headers
+        .get_all(CONTENT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|token| !token.is_empty() && !token.eq_ignore_ascii_case("identity"));
+
+    let encoding = ContentEncoding::from_token(codings.next()?)?;
+    codings.next().is_none().then_some(encoding)
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use http_body_util::BodyExt;
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+
+    /// A body delivered in the given frames.
+    struct Frames(VecDeque<Bytes>);
+
+    impl Frames {
+        fn split(body: &[u8], size: usize) -> Self {
+            Frames(body.chunks(size).map(Bytes::copy_from_slice).collect())
+        }
+    }
+
+    impl Body for Frames {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            Poll::Ready(self.0.pop_front().map(|data| Ok(Frame::data(data))))
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    async fn decode(
+        encoded: &[u8],
+        limits: DecodeLimits,
+    ) -> Result<Bytes, Box<dyn std::error::Error + Send + Sync>> {
+        let body = DecodedBody::new(Frames::split(encoded, 1024), ContentEncoding::Gzip, limits);
+        Ok(body.collect().await?.to_bytes())
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let text = "hypertor decodes gzip responses as they arrive. ".repeat(2000);
+        let decoded = decode(&gzip(text.as_bytes()), DecodeLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(decoded, text.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn gzip_bomb_exceeds_ratio() {
+        // 16 MiB of zeros compress to about 16 KiB, a ratio of roughly 1000.
+        let bomb = gzip(&vec![0; 16 * 1024 * 1024]);
+        let err = decode(&bomb, DecodeLimits::default()).await.unwrap_err();
+        let bomb = err
+            .downcast_ref::<DecompressionBomb>()
+            .expect("decoding fails with DecompressionBomb");
+        assert!(bomb.decoded <= (bomb.encoded * 100).max(RATIO_GRACE));
+    }
+
+    #[tokio::test]
+    async fn gzip_bomb_exceeds_size() {
+        let limits = DecodeLimits {
+            max_ratio: u64::MAX,
+            max_decoded_size: Some(64 * 1024),
+        };
+        let err = decode(&gzip(&[0; 256 * 1024]), limits).await.unwrap_err();
+        let bomb = err.downcast_ref::<DecompressionBomb>().unwrap();
+        assert!(bomb.decoded <= 64 * 1024);
+    }
+
+    #[tokio::test]
+    async fn from_response_strips_encoding_headers() {
+        let encoded = gzip(b"hello");
+        let response = Response::builder()
+            .header(CONTENT_ENCODING, "gzip")
+            .header(CONTENT_LENGTH, encoded.len())
+            .body(Frames::split(&encoded, 4))
+            .unwrap();
+        let response = DecodedBody::from_response(response, DecodeLimits::default())
+            .ok()
+            .unwrap();
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+        assert!(!response.headers().contains_key(CONTENT_LENGTH));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+
+        let response = Response::builder()
+            .header(CONTENT_ENCODING, "gzip, br")
+            .body(Frames::split(&encoded, 4))
+            .unwrap();
+        assert!(DecodedBody::from_response(response, DecodeLimits::default()).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use std::net::IpAddr;
+
+/// The outcome of a single self-test check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The check succeeded.
+    Passed,
+    /// The check failed, with a human readable reason.
+    Failed(String),
+}
+
+/// A named self-test check and its outcome.
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// Short identifier of the check.
+    pub name: &'static str,
+    /// What the check verifies, suitable for showing to users.
+    pub description: &'static str,
+    /// The outcome.
+    pub status: CheckStatus,
+}
+
+/// A report produced by [`Client::self_test`](crate::Client::self_test).
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    /// The individual checks, in the order they ran.
+    pub checks: Vec<Check>,
+    /// The exit address seen by the Tor Project's check service, if reached.
+    pub exit_ip: Option<IpAddr>,
+}
+
+impl SelfTestReport {
+    /// Returns `true` if every check passed.
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status == CheckStatus::Passed)
+    }
+}
+
+/// The answer of the Tor Project's check service, see
+/// [`Client::verify_tor`](crate::Client::verify_tor).
+#[derive(Debug, Clone)]
+pub struct TorVerification {
+    /// Whether the request arrived from a known Tor exit.
+    pub is_tor: bool,
+    /// The address the request arrived from.
+    pub exit_ip: IpAddr,
+}
@@ -0,0 +1,22 @@
+/// How far a download has come, as reported to the progress callback of
+/// [`Client::download_with_progress`](crate::Client::download_with_progress).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// Bytes written to disk so far.
+    pub received: u64,
+    /// Size of the whole body, if the response announced it with
+    /// `Content-Length`.
+    pub total: Option<u64>,
+}
+
+impl DownloadProgress {
+    /// Returns the share of the body received, from 0.0 to 1.0, if the
+    /// total is known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.received as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use super::ContentEncoding;
+use http::header::{HeaderMap, ACCEPT_ENCODING};
+#[cfg(feature = "gzip")]
+use std::io::Write;
+
+/// Codings offered for request bodies, most preferred first.
+const REQUEST_CODINGS: &[ContentEncoding] = &[
+    #[cfg(feature = "zstd")]
+    ContentEncoding::Zstd,
+    #[cfg(feature = "gzip")]
+    ContentEncoding::Gzip,
+];
+
+/// Returns the coding to compress request bodies with, given the headers of
+/// a response from the same origin.
+///
+/// A server lists the codings it accepts in request bodies in an
+/// `Accept-Encoding` response header (RFC 7694). `None` inside means the
+/// header is absent and nothing was learned; `Some(None)` means the server
+/// accepts none of the codings offered.
+pub(crate) fn request_coding(headers: &HeaderMap) -> Option<Option<ContentEncoding>> {
+    if !headers.contains_key(ACCEPT_ENCODING) {
+        return None;
+    }
+    let accepted: Vec<ContentEncoding> = headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let coding = ContentEncoding::from_token(params.next()?)?;
+            let refused = params.any(|param| {
+                let param = param.trim();
+                param
+                    .strip_prefix("q=")
+                    .is_some_and(|q| q.trim().parse::<f32>().is_ok_and(|q| q <= 0.0))
+            });
+            (!refused).then_some(coding)
+        })
+        .collect();
+    Some(
+        REQUEST_CODINGS
+            .iter()
+            .copied()
+            .find(|coding| accepted.contains(coding)),
+    )
+}
+
+/// Compresses `data` with `encoding` at the coding's default level.
+pub(crate) fn encode(encoding: ContentEncoding, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        #[cfg(feature = "gzip")]
+        ContentEncoding::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "zstd")]
+        ContentEncoding::Zstd => zstd::stream::encode_all(data, 0),
+        #[allow(unreachable_patterns)]
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("{} is not offered for request bodies", encoding.as_str()),
+        )),
+    }
+}
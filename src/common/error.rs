@@ -0,0 +1,173 @@
+use std::error::Error as StdError;
+
+/// A `Result` whose error is [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by [`Client`](crate::Client).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The Tor client could not be created or bootstrapped. The source is
+    /// an `arti_client::Error`.
+    Bootstrap(Box<dyn StdError + Send + Sync>),
+    /// The client configuration is invalid.
+    Config(String),
+    /// The request URI is invalid or the request could not be built.
+    InvalidUri(http::Error),
+    /// The request cannot be sent as given.
+    InvalidRequest(String),
+    /// The connection could not be opened or failed during the exchange.
+    Connect(ConnectionError),
+    /// The TLS handshake with the origin failed.
+    Tls(Box<dyn StdError + Send + Sync>),
+    /// The server answered with a status the operation cannot use, as
+    /// when a download is answered with `404 Not Found`.
+    Status(http::StatusCode),
+    /// A response body could not be read.
+    Http(Box<dyn StdError + Send + Sync>),
+    /// A response body could not be decoded, as when it is not valid JSON.
+    Decode(Box<dyn StdError + Send + Sync>),
+    /// A response body could not be written to its destination.
+    Write(std::io::Error),
+    /// The request, or a step of it such as connecting or reading the
+    /// response body, did not finish within its configured timeout.
+    Timeout(std::time::Duration),
+    /// The request could not be written for longer than the configured
+    /// stall timeout, as when its circuit is wedged.
+    Stalled(std::time::Duration),
+    /// A redirect could not be followed.
+    Redirect(String),
+    /// A retry was needed, but the retry budget is spent.
+    RetryBudgetExhausted,
+    /// A challenge solver, hook or state snapshot failed.
+    Other(Box<dyn StdError + Send + Sync>),
+}
+
+impl Error {
+    /// Returns the connection error behind this error, if any.
+    pub fn connection_error(&self) -> Option<&ConnectionError> {
+        match self {
+            Error::Connect(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Bootstrap(e) => write!(f, "failed to bootstrap Tor: {e}"),
+            Error::Config(reason) => write!(f, "invalid configuration: {reason}"),
+            Error::InvalidUri(e) => write!(f, "invalid request: {e}"),
+            Error::InvalidRequest(reason) => write!(f, "invalid request: {reason}"),
+            Error::Connect(e) => e.fmt(f),
+            Error::Tls(e) => write!(f, "TLS handshake failed: {e}"),
+            Error::Status(status) => write!(f, "unexpected response status {status}"),
+            Error::Http(e) => write!(f, "failed to read response: {e}"),
+            Error::Decode(e) => write!(f, "failed to decode response: {e}"),
+            Error::Write(e) => write!(f, "failed to write response: {e}"),
+            Error::Timeout(timeout) => write!(f, "timed out after {timeout:?}"),
+            Error::Stalled(timeout) => write!(f, "upload made no progress for {timeout:?}"),
+            Error::Redirect(reason) => write!(f, "redirect failed: {reason}"),
+            Error::RetryBudgetExhausted => f.write_str("retry budget exhausted"),
+            Error::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::InvalidUri(e) => Some(e),
+            Error::Connect(e) => Some(e),
+            Error::Write(e) => Some(e),
+            Error::Bootstrap(e)
+            | Error::Tls(e)
+            | Error::Http(e)
+            | Error::Decode(e)
+            | Error::Other(e) => Some(e.as_ref()),
+            Error::Config(_)
+            | Error::InvalidRequest(_)
+            | Error::Status(_)
+            | Error::Timeout(_)
+            | Error::Stalled(_)
+            | Error::Redirect(_)
+            | Error::RetryBudgetExhausted => None,
+        }
+    }
+}
+
+impl From<http::Error> for Error {
+    fn from(error: http::Error) -> Self {
+        Error::InvalidUri(error)
+    }
+}
+
+/// The broad cause of a failed connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConnectionErrorKind {
+    /// The server or exit closed or reset the stream.
+    ResetByPeer,
+    /// The Tor circuit carrying the stream collapsed.
+    CircuitClosed,
+    /// The peer violated HTTP or the Tor protocol.
+    Protocol,
+    /// Anything else, such as a refused connection.
+    Other,
+}
+
+/// An error on the connection a request was sent over.
+///
+/// Returned as [`Error::Connect`] from requests whose connection failed.
+/// Errors from reading a response body can be classified by passing them to
+/// [`ConnectionError::new`].
+#[derive(Debug)]
+pub struct ConnectionError {
+    pub(crate) kind: ConnectionErrorKind,
+    pub(crate) source: Box<dyn StdError + Send + Sync>,
+}
+
+impl ConnectionError {
+    /// Creates an error whose cause is already known.
+    pub(crate) fn with_kind(
+        kind: ConnectionErrorKind,
+        error: impl Into<Box<dyn StdError + Send + Sync>>,
+    ) -> Self {
+        ConnectionError {
+            kind,
+            source: error.into(),
+        }
+    }
+
+    /// Returns the broad cause of the failure.
+    pub fn kind(&self) -> ConnectionErrorKind {
+        self.kind
+    }
+
+    /// Returns `true` if the request may succeed over a fresh circuit.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self.kind,
+            ConnectionErrorKind::ResetByPeer | ConnectionErrorKind::CircuitClosed
+        )
+    }
+}
+
+impl std::fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let what = match self.kind {
+            ConnectionErrorKind::ResetByPeer => "connection reset by peer",
+            ConnectionErrorKind::CircuitClosed => "circuit closed",
+            ConnectionErrorKind::Protocol => "protocol error",
+            ConnectionErrorKind::Other => "connection error",
+        };
+        write!(f, "{what}: {}", self.source)
+    }
+}
+
+impl StdError for ConnectionError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+/// Faults injected into every connection, for testing how an application
+/// copes with slow, failing and truncated Tor streams.
+///
+/// Only available with the `chaos` feature, which should not be enabled in
+/// production builds. Random decisions are drawn from `seed`, so a test that
+/// opens connections in a fixed order sees the same faults on every run.
+/// Byte counts apply to the stream below TLS.
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjection {
+    /// Delay added before each connection is handed to HTTP.
+    pub connect_delay: Duration,
+    /// Share of connections, from `0.0` to `1.0`, that fail with a reset
+    /// before any data is exchanged.
+    pub reset_rate: f64,
+    /// End the stream as if the peer closed it after this many bytes have
+    /// been read.
+    pub truncate_after: Option<u64>,
+    /// Fail reads with a reset after this many bytes have been read.
+    pub reset_after: Option<u64>,
+    /// Seed for the random decisions.
+    pub seed: u64,
+}
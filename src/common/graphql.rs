@@ -0,0 +1,63 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A location in a GraphQL document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct GraphQlLocation {
+    /// The line, starting at 1.
+    pub line: u32,
+    /// The column, starting at 1.
+    pub column: u32,
+}
+
+/// An entry of the `errors` list of a GraphQL response.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GraphQlError {
+    /// A description of the error.
+    pub message: String,
+    /// Locations in the query the error refers to.
+    #[serde(default)]
+    pub locations: Vec<GraphQlLocation>,
+    /// Path of the response field that failed, as names and list indices.
+    #[serde(default)]
+    pub path: Vec<Value>,
+    /// Server-defined details, such as an error code.
+    #[serde(default)]
+    pub extensions: Option<Value>,
+}
+
+impl std::fmt::Display for GraphQlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GraphQL error: {}", self.message)
+    }
+}
+
+impl std::error::Error for GraphQlError {}
+
+/// The `data`/`errors` envelope of a GraphQL response.
+///
+/// A response can carry partial `data` together with `errors` for the fields
+/// that could not be resolved.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GraphQlResponse<T> {
+    /// The result of the operation, if execution started.
+    pub data: Option<T>,
+    /// Errors raised while parsing, validating or executing the operation.
+    #[serde(default)]
+    pub errors: Vec<GraphQlError>,
+}
+
+impl<T> GraphQlResponse<T> {
+    /// Returns the data if the response has no errors, or the first error.
+    pub fn into_data(self) -> Result<T, GraphQlError> {
+        if let Some(error) = self.errors.into_iter().next() {
+            return Err(error);
+        }
+        self.data.ok_or_else(|| GraphQlError {
+            message: "response contains neither data nor errors".into(),
+            locations: Vec::new(),
+            path: Vec::new(),
+            extensions: None,
+        })
+    }
+}
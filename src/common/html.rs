@@ -0,0 +1,278 @@
+use crate::common::{Error, Result, Url};
+use bytes::{Buf, Bytes, BytesMut};
+use http_body::Body;
+use http_body_util::BodyExt;
+use std::collections::VecDeque;
+
+/// Upper bound on the size of a single tag; longer ones are skipped.
+const MAX_TAG: usize = 64 * 1024;
+
+/// Extracts the links of an HTML body as it arrives.
+///
+/// Yields the `href` of `<a>`, `<area>` and `<link>` elements and the `src`
+/// of frames, resolved against the page URL or its `<base>`. Links that do
+/// not resolve to an `http` or `https` URL, such as `mailto:` links, are
+/// skipped, and fragments are removed. Only the tag being read is buffered,
+/// so pages of any size are scanned in bounded memory.
+pub struct HtmlLinks<B> {
+    body: B,
+    base: Url,
+    buf: BytesMut,
+    links: VecDeque<Url>,
+    /// End of a comment, script or style whose content is being skipped.
+    skip_until: Option<&'static [u8]>,
+    done: bool,
+}
+
+impl<B> HtmlLinks<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Creates an extractor for the body of the page at `base`.
+    pub fn new(body: B, base: Url) -> Self {
+        HtmlLinks {
+            body,
+            base,
+            buf: BytesMut::new(),
+            links: VecDeque::new(),
+            skip_until: None,
+            done: false,
+        }
+    }
+
+    /// Returns the next link, or `None` at the end of the body.
+    pub async fn next_link(&mut self) -> Option<Result<Url>> {
+        loop {
+            if let Some(link) = self.links.pop_front() {
+                return Some(Ok(link));
+            }
+            if self.done {
+                return None;
+            }
+            match self.read_more().await {
+                Ok(true) => self.scan(),
+                Ok(false) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    /// Appends the next data frame to the buffer; returns `false` at the end of the body.
+    async fn read_more(&mut self) -> Result<bool> {
+        while let Some(frame) = self.body.frame().await {
+            let frame = frame.map_err(|e| Error::Http(e.into()))?;
+            if let Ok(data) = frame.into_data() {
+                self.buf.extend_from_slice(&data);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Consumes every complete tag in the buffer, leaving a partial one.
+    fn scan(&mut self) {
+        loop {
+            if let Some(end) = self.skip_until {
+                match find_ignore_case(&self.buf, end) {
+                    Some(pos) => {
+                        self.buf.advance(pos + end.len());
+                        self.skip_until = None;
+                    }
+                    None => {
+                        // Keep what could be the start of the end marker.
+                        let keep = end.len() - 1;
+                        self.buf.advance(self.buf.len().saturating_sub(keep));
+                        return;
+                    }
+                }
+            }
+
+            let Some(start) = self.buf.iter().position(|&b| b == b'<') else {
+                self.buf.clear();
+                return;
+            };
+            self.buf.advance(start);
+
+            if self.buf.starts_with(b"<!--") {
+                self.buf.advance(4);
+                self.skip_until = Some(b"-->");
+                continue;
+            }
+            if b"<!--".starts_with(&self.buf) {
+                return;
+            }
+
+            match tag_end(&self.buf) {
+                Some(end) => {
+                    let tag = self.buf.split_to(end + 1);
+                    self.tag(&tag[1..end]);
+                }
+                None if self.buf.len() > MAX_TAG => self.buf.advance(1),
+                None => return,
+            }
+        }
+    }
+
+    /// Handles the text of a tag between its angle brackets.
+    fn tag(&mut self, tag: &[u8]) {
+        let name_len = tag
+            .iter()
+            .position(|b| b.is_ascii_whitespace() || *b == b'/')
+            .unwrap_or(tag.len());
+        let name = String::from_utf8_lossy(&tag[..name_len]).to_ascii_lowercase();
+        let wanted = match name.as_str() {
+            "script" => {
+                self.skip_until = Some(b"</script");
+                return;
+            }
+            "style" => {
+                self.skip_until = Some(b"</style");
+                return;
+            }
+            "a" | "area" | "link" | "base" => "href",
+            "frame" | "iframe" => "src",
+            _ => return,
+        };
+
+        let Some(value) = attribute(&tag[name_len..], wanted) else {
+            return;
+        };
+        let Ok(url) = self.base.join(value.trim()) else {
+            return;
+        };
+        if name == "base" {
+            self.base = url;
+        } else {
+            self.links.push_back(url);
+        }
+    }
+}
+
+impl<B> std::fmt::Debug for HtmlLinks<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HtmlLinks")
+            .field("base", &self.base.as_str())
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns the position of the `>` closing the tag at the start of `buf`,
+/// ignoring any inside quoted attribute values.
+fn tag_end(buf: &[u8]) -> Option<usize> {
+    let mut quote = None;
+    let mut after_equals = false;
+    for (i, &b) in buf.iter().enumerate().skip(1) {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'>' => return Some(i),
+            None if after_equals && (b == b'"' || b == b'\'') => quote = Some(b),
+            None => {}
+        }
+        if !b.is_ascii_whitespace() {
+            after_equals = quote.is_none() && b == b'=';
+        }
+    }
+    None
+}
+
+/// Returns the decoded value of the attribute `name` in the attribute text
+/// of a tag.
+fn attribute(mut attrs: &[u8], name: &str) -> Option<String> {
+    loop {
+        attrs = trim_start(attrs, |b| b.is_ascii_whitespace() || b == b'/');
+        if attrs.is_empty() {
+            return None;
+        }
+        let name_len = attrs
+            .iter()
+            .position(|&b| b.is_ascii_whitespace() || b == b'=' || b == b'/')
+            .unwrap_or(attrs.len())
+            .max(1);
+        let found = attrs[..name_len].eq_ignore_ascii_case(name.as_bytes());
+        attrs = trim_start(&attrs[name_len..], |b| b.is_ascii_whitespace());
+
+        let mut value: &[u8] = &[];
+        if let Some(rest) = attrs.strip_prefix(b"=") {
+            let rest = trim_start(rest, |b| b.is_ascii_whitespace());
+            (value, attrs) = match rest.first() {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let inner = &rest[1..];
+                    let end = inner
+                        .iter()
+                        .position(|&b| b == quote)
+                        .unwrap_or(inner.len());
+                    (&inner[..end], inner.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = rest
+                        .iter()
+                        .position(|b| b.is_ascii_whitespace())
+                        .unwrap_or(rest.len());
+                    rest.split_at(end)
+                }
+            };
+        }
+        if found {
+            return Some(decode_entities(&String::from_utf8_lossy(value)));
+        }
+    }
+}
+
+fn trim_start(bytes: &[u8], strip: impl Fn(u8) -> bool) -> &[u8] {
+    let start = bytes.iter().position(|&b| !strip(b)).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// Decodes the character references that commonly appear in URLs.
+fn decode_entities(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let reference = rest[1..]
+            .find(';')
+            .map(|end| &rest[1..end + 1])
+            .filter(|reference| reference.len() <= 8);
+        let c = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let number = reference.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (c, reference) {
+            (Some(c), Some(reference)) => {
+                decoded.push(c);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Returns the position of `needle` in `haystack`, ignoring ASCII case.
+fn find_ignore_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// An error object returned by a JSON-RPC 2.0 server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    /// The error code; -32768 to -32000 are reserved by the specification.
+    pub code: i64,
+    /// A short description of the error.
+    pub message: String,
+    /// Additional, server-defined information.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JSON-RPC error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// A JSON-RPC 2.0 response object.
+#[derive(Debug, Deserialize)]
+pub(crate) struct RpcResponse {
+    #[serde(default)]
+    pub(crate) id: Value,
+    #[serde(default)]
+    result: Value,
+    #[serde(default)]
+    error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    /// Returns the call's result, or the error the server reported.
+    pub(crate) fn into_result(self) -> Result<Value, RpcError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.result),
+        }
+    }
+}
+
+/// Builds a request object; notifications have no `id`.
+pub(crate) fn rpc_request(id: Option<u64>, method: &str, params: Value) -> Value {
+    let mut request = json!({ "jsonrpc": "2.0", "method": method });
+    if !params.is_null() {
+        request["params"] = params;
+    }
+    if let Some(id) = id {
+        request["id"] = id.into();
+    }
+    request
+}
@@ -0,0 +1,131 @@
+//! Transport-independent types and policy logic.
+//!
+//! Nothing in this module depends on arti or tokio, nor on the rest of the
+//! crate. Request and response helpers, configuration values, the [`Error`]
+//! type and the policies applied by `Client` can be used and unit tested on
+//! their own, or reused on top of other transports. Building hypertor
+//! without its default `runtime` feature leaves only this module, without
+//! arti, tokio or hyper among the dependencies.
+
+mod auth;
+mod byteranges;
+mod challenge;
+mod circuit;
+mod clock;
+mod cookie;
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+mod decode;
+mod diagnostics;
+mod download;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod encode;
+mod error;
+#[cfg(feature = "chaos")]
+mod faults;
+#[cfg(feature = "json")]
+mod graphql;
+#[cfg(feature = "html")]
+mod html;
+#[cfg(feature = "json")]
+mod jsonrpc;
+mod network;
+mod onion_route;
+mod proxy_protocol;
+mod ratelimit;
+mod redirect;
+mod sandbox;
+#[cfg(feature = "json")]
+mod session;
+mod settings;
+#[cfg(feature = "s3")]
+mod sigv4;
+mod socks;
+mod storage;
+mod text;
+mod tls;
+mod uri;
+#[cfg(feature = "webdav")]
+mod webdav;
+
+use std::future::Future;
+use std::pin::Pin;
+
+pub use auth::{Credentials, CredentialsProvider, CredentialsStore};
+pub use byteranges::{ByteRanges, BytesPart, ContentRange};
+pub use challenge::{Challenge, ChallengeSolution, ChallengeSolver, Interstitial};
+pub use circuit::{CircuitInfo, RelayInfo};
+pub use clock::ClockSkew;
+pub use cookie::{Cookie, CookieJar, CookieStore};
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+pub use decode::{ContentEncoding, DecodeLimits, DecodedBody, DecompressionBomb};
+pub use diagnostics::{Check, CheckStatus, SelfTestReport, TorVerification};
+pub use download::DownloadProgress;
+pub use error::{ConnectionError, ConnectionErrorKind, Error, Result};
+#[cfg(feature = "chaos")]
+pub use faults::FaultInjection;
+#[cfg(feature = "json")]
+pub use graphql::{GraphQlError, GraphQlLocation, GraphQlResponse};
+#[cfg(feature = "html")]
+pub use html::HtmlLinks;
+#[cfg(feature = "json")]
+pub use jsonrpc::RpcError;
+pub use network::{DirectoryAuthority, FallbackDirectory, TorNetwork};
+pub use onion_route::OnionRoute;
+pub use proxy_protocol::ProxyHeader;
+pub use ratelimit::RateLimit;
+pub use redirect::{RedirectChain, RedirectHop, RedirectPolicy};
+pub use sandbox::{SandboxPath, SandboxRequirements, SocketFamily};
+pub use settings::{
+    AdaptiveConcurrency, ConnectionDriver, ExitCountry, FastExitSelection, Http1Options, Priority,
+    ReadBuffer, RetryBudget, RetryPolicy, UsageCaps, UserAgent,
+};
+#[cfg(feature = "s3")]
+pub use sigv4::AwsCredentials;
+pub use socks::Socks5Proxy;
+pub use storage::StorageUsage;
+pub use tls::TlsInfo;
+pub use uri::Url;
+#[cfg(feature = "webdav")]
+pub use webdav::{DavResource, Depth};
+
+pub(crate) use auth::{authorization, challenges};
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+pub(crate) use decode::accept_encoding;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub(crate) use encode::{encode, request_coding};
+#[cfg(feature = "json")]
+pub(crate) use jsonrpc::{rpc_request, RpcResponse};
+pub(crate) use onion_route::{announcements, Announcement};
+pub(crate) use redirect::{redirect_target, scope_redirect};
+#[cfg(feature = "json")]
+pub(crate) use session::SessionState;
+pub(crate) use settings::MIN_MAX_READ_BUFFER;
+#[cfg(feature = "s3")]
+pub(crate) use sigv4::{sign_v4, uri_encode};
+pub(crate) use socks::{
+    refused, socks_connect, socks_greeting, socks_method, socks_password_auth, socks_reply,
+};
+pub(crate) use text::decode_text;
+#[cfg(feature = "exclude-exits")]
+pub(crate) use uri::is_local_host;
+pub(crate) use uri::{host_header, is_onion, origin_of, with_host, with_origin};
+#[cfg(feature = "webdav")]
+pub(crate) use webdav::parse_multistatus;
+
+/// A boxed future, as returned by the hooks in this crate.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
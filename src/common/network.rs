@@ -0,0 +1,35 @@
+use std::net::SocketAddr;
+
+/// The directory servers of a Tor network other than the public one, such
+/// as a private test network run with chutney or shadow.
+///
+/// Replaces arti's built-in authorities and fallback directories, so the
+/// client bootstraps from and only trusts the given network.
+#[derive(Debug, Clone, Default)]
+pub struct TorNetwork {
+    /// Authorities whose signatures make a consensus valid.
+    pub authorities: Vec<DirectoryAuthority>,
+    /// Relays asked for directory information before a consensus is known.
+    pub fallback_directories: Vec<FallbackDirectory>,
+}
+
+/// A directory authority of a [`TorNetwork`].
+#[derive(Debug, Clone)]
+pub struct DirectoryAuthority {
+    /// Nickname, used in log messages.
+    pub name: String,
+    /// Fingerprint of the authority's v3 signing identity, in hex, as in
+    /// the `v3ident` of a `DirAuthority` line in torrc.
+    pub v3ident: String,
+}
+
+/// A fallback directory of a [`TorNetwork`].
+#[derive(Debug, Clone)]
+pub struct FallbackDirectory {
+    /// Fingerprint of the relay's RSA identity, in hex.
+    pub rsa_identity: String,
+    /// The relay's Ed25519 identity, in unpadded base64.
+    pub ed_identity: String,
+    /// Addresses of the relay's onion router ports.
+    pub orports: Vec<SocketAddr>,
+}
@@ -0,0 +1,191 @@
+use super::uri::is_valid_onion;
+use http::header::{HeaderMap, HeaderName, ALT_SVC};
+use http::uri::Authority;
+use http::Uri;
+use std::time::Duration;
+
+/// The `Onion-Location` header (Tor Browser).
+const ONION_LOCATION: HeaderName = HeaderName::from_static("onion-location");
+
+/// How long an `Onion-Location` mapping is kept; the header carries no
+/// lifetime of its own.
+pub(crate) const ONION_LOCATION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// `Alt-Svc` lifetime when the header gives no `ma` (RFC 7838).
+const ALT_SVC_DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A way to reach a clearnet origin through an onion service, learned from
+/// its responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OnionRoute {
+    /// Announced with `Onion-Location`: requests are sent to this onion
+    /// origin (`scheme://host[:port]`) instead.
+    Location(String),
+    /// Announced with `Alt-Svc`: connections go to this onion `host:port`,
+    /// while the URI, `Host` and TLS name stay those of the origin.
+    AltSvc(String),
+}
+
+/// What a response says about reaching its origin over an onion service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Announcement {
+    /// The origin can be reached over `route` for `ttl`.
+    Route(OnionRoute, Duration),
+    /// `Alt-Svc: clear` withdrew every alternative.
+    Clear,
+}
+
+/// Reads the onion routes announced by a response from `uri`.
+///
+/// Like Tor Browser, only HTTPS responses are trusted with announcements,
+/// and only well-formed v3 onion addresses are accepted.
+pub(crate) fn announcements(uri: &Uri, headers: &HeaderMap) -> Vec<Announcement> {
+    if uri.scheme_str() != Some("https") {
+        return Vec::new();
+    }
+
+    let mut found = Vec::new();
+    if let Some(route) = onion_location(headers) {
+        found.push(Announcement::Route(route, ONION_LOCATION_TTL));
+    }
+
+    for value in headers.get_all(ALT_SVC) {
+        let Ok(value) = value.to_str() else { continue };
+        if value.trim() == "clear" {
+            found.push(Announcement::Clear);
+            continue;
+        }
+        found.extend(value.split(',').filter_map(alt_svc_entry));
+    }
+    found
+}
+
+/// Reads the onion origin named by `Onion-Location`.
+fn onion_location(headers: &HeaderMap) -> Option<OnionRoute> {
+    let location: Uri = headers
+        .get(ONION_LOCATION)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    let scheme = location.scheme_str()?;
+    let authority = location.authority()?;
+    let valid = matches!(scheme, "http" | "https")
+        && is_valid_onion(authority.host())
+        && !authority.as_str().contains('@');
+    valid.then(|| OnionRoute::Location(format!("{scheme}://{authority}")))
+}
+
+/// Parses one `protocol-id="host:port"; ma=seconds` entry of `Alt-Svc`.
+///
+/// Only HTTP/1.1 and HTTP/2 alternatives are used: both are reached over
+/// TLS, where hypertor negotiates HTTP/1.1.
+fn alt_svc_entry(entry: &str) -> Option<Announcement> {
+    let mut params = entry.split(';').map(str::trim);
+    let (protocol, authority) = params.next()?.split_once('=')?;
+    if !matches!(protocol, "h2" | "http%2F1.1" | "http%2f1.1") {
+        return None;
+    }
+    let authority: Authority = authority.trim_matches('"').parse().ok()?;
+    if !is_valid_onion(authority.host()) {
+        return None;
+    }
+    let port = authority.port_u16()?;
+
+    let mut ttl = ALT_SVC_DEFAULT_MAX_AGE;
+    for param in params {
+        if let Some(("ma", seconds)) = param.split_once('=') {
+            ttl = Duration::from_secs(seconds.trim_matches('"').parse().ok()?);
+        }
+    }
+    let route = OnionRoute::AltSvc(format!("{}:{port}", authority.host()));
+    Some(Announcement::Route(route, ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    const ONION: &str = "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion";
+
+    fn announced(uri: &str, headers: &[(&str, &str)]) -> Vec<Announcement> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        announcements(&uri.parse().unwrap(), &map)
+    }
+
+    #[test]
+    fn onion_location_names_an_origin() {
+        let location = format!("https://{ONION}:8443/path?query");
+        assert_eq!(
+            announced("https://example.com/", &[("onion-location", &location)]),
+            [Announcement::Route(
+                OnionRoute::Location(format!("https://{ONION}:8443")),
+                ONION_LOCATION_TTL
+            )]
+        );
+    }
+
+    #[test]
+    fn malformed_onion_locations_are_ignored() {
+        for location in [
+            "http://example.com/".to_string(),
+            "http://short.onion/".to_string(),
+            format!("ftp://{ONION}/"),
+            format!("http://user@{ONION}/"),
+            format!("/{ONION}"),
+        ] {
+            assert!(
+                announced("https://example.com/", &[("onion-location", &location)]).is_empty(),
+                "{location}"
+            );
+        }
+    }
+
+    #[test]
+    fn alt_svc_entries() {
+        let alt_svc = format!(
+            "h3=\"{ONION}:443\", h2=\"{ONION}:443\"; ma=60; persist=1, \
+             http%2F1.1=\"{ONION}:8443\", h2=\"example.net:443\", h2=\"{ONION}\""
+        );
+        assert_eq!(
+            announced("https://example.com/", &[("alt-svc", &alt_svc)]),
+            [
+                Announcement::Route(
+                    OnionRoute::AltSvc(format!("{ONION}:443")),
+                    Duration::from_secs(60)
+                ),
+                Announcement::Route(
+                    OnionRoute::AltSvc(format!("{ONION}:8443")),
+                    ALT_SVC_DEFAULT_MAX_AGE
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn alt_svc_clear() {
+        assert_eq!(
+            announced("https://example.com/", &[("alt-svc", "clear")]),
+            [Announcement::Clear]
+        );
+    }
+
+    #[test]
+    fn plain_http_responses_announce_nothing() {
+        let location = format!("http://{ONION}/");
+        let alt_svc = format!("h2=\"{ONION}:443\"");
+        assert!(announced(
+            "http://example.com/",
+            &[("onion-location", &location), ("alt-svc", &alt_svc)]
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+use std::net::{IpAddr, SocketAddr};
+
+/// Signature that starts every PROXY protocol v2 header.
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// A PROXY protocol v2 header to send before anything else on a connection.
+///
+/// Insert one into a request's extensions, typically one passed to
+/// `Client::forward`, for upstreams behind haproxy-style listeners that
+/// expect to learn the original client address this way. Onion services never
+/// see client addresses, so the addresses are usually synthetic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyHeader {
+    /// The connection was made by the proxy itself and carries no addresses.
+    Local,
+    /// The connection relays one from `source` to `destination`.
+    Proxied {
+        /// Address of the original client.
+        source: SocketAddr,
+        /// Address the original client connected to.
+        destination: SocketAddr,
+    },
+}
+
+impl ProxyHeader {
+    /// Encodes the header in the binary v2 format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut header = SIGNATURE.to_vec();
+        let (source, destination) = match *self {
+            ProxyHeader::Local => {
+                header.extend_from_slice(&[0x20, 0x00, 0, 0]);
+                return header;
+            }
+            ProxyHeader::Proxied {
+                source,
+                destination,
+            } => (source, destination),
+        };
+
+        header.push(0x21);
+        let mut addresses = Vec::with_capacity(36);
+        match (source.ip(), destination.ip()) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                header.push(0x11);
+                addresses.extend_from_slice(&src.octets());
+                addresses.extend_from_slice(&dst.octets());
+            }
+            (src, dst) => {
+                header.push(0x21);
+                addresses.extend_from_slice(&to_v6(src));
+                addresses.extend_from_slice(&to_v6(dst));
+            }
+        }
+        addresses.extend_from_slice(&source.port().to_be_bytes());
+        addresses.extend_from_slice(&destination.port().to_be_bytes());
+
+        header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+        header.extend_from_slice(&addresses);
+        header
+    }
+}
+
+fn to_v6(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
@@ -0,0 +1,77 @@
+use http::header::HeaderMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Reset values above this are Unix timestamps rather than seconds to wait.
+const TIMESTAMP_THRESHOLD: u64 = 1_000_000_000;
+
+/// Rate-limit state announced by a server.
+///
+/// Parsed from the `RateLimit-Limit`, `RateLimit-Remaining` and
+/// `RateLimit-Reset` headers, their `X-RateLimit-*` predecessors, or a
+/// structured `RateLimit: limit=…, remaining=…, reset=…` header, and inserted
+/// into the extensions of responses that carry them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests allowed in the current window.
+    pub limit: Option<u64>,
+    /// Requests left in the current window.
+    pub remaining: Option<u64>,
+    /// Time until the window resets.
+    pub reset: Option<Duration>,
+}
+
+impl RateLimit {
+    /// Parses rate-limit headers, returning `None` if there are none.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut rate_limit = RateLimit::default();
+
+        if let Some(value) = headers.get("ratelimit").and_then(|v| v.to_str().ok()) {
+            for item in value.split([',', ';']) {
+                let Some((key, value)) = item.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().parse().ok();
+                match key.trim() {
+                    "limit" => rate_limit.limit = value,
+                    "remaining" | "r" => rate_limit.remaining = value,
+                    "reset" | "t" => rate_limit.reset = value.map(reset_after),
+                    _ => {}
+                }
+            }
+        }
+
+        let number = |name: &str| {
+            [name.to_string(), format!("x-{name}")]
+                .iter()
+                .find_map(|name| {
+                    headers
+                        .get(name.as_str())?
+                        .to_str()
+                        .ok()?
+                        .trim()
+                        .parse()
+                        .ok()
+                })
+        };
+        rate_limit.limit = rate_limit.limit.or_else(|| number("ratelimit-limit"));
+        rate_limit.remaining = rate_limit
+            .remaining
+            .or_else(|| number("ratelimit-remaining"));
+        rate_limit.reset = rate_limit
+            .reset
+            .or_else(|| number("ratelimit-reset").map(reset_after));
+
+        (rate_limit != RateLimit::default()).then_some(rate_limit)
+    }
+}
+
+/// Converts a reset value, in seconds or as a Unix timestamp, to a duration.
+fn reset_after(value: u64) -> Duration {
+    if value < TIMESTAMP_THRESHOLD {
+        return Duration::from_secs(value);
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Duration::from_secs(value).saturating_sub(now)
+}
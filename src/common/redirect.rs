@@ -0,0 +1,96 @@
+use super::{origin_of, Error, Result, Url};
+use http::header::{
+    HeaderMap, HeaderName, AUTHORIZATION, COOKIE, HOST, LOCATION, PROXY_AUTHORIZATION,
+};
+use http::{StatusCode, Uri};
+
+/// Which redirects [`Client::send_request`](crate::Client::send_request)
+/// follows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    /// Return redirect responses as they are.
+    #[default]
+    None,
+    /// Follow redirects.
+    Follow {
+        /// Maximum number of redirects followed per request.
+        max_hops: usize,
+        /// Follow redirects from HTTPS to plain HTTP instead of failing.
+        allow_downgrade: bool,
+    },
+}
+
+/// A response received while following redirects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectHop {
+    /// The URI that was requested.
+    pub uri: Uri,
+    /// The status it answered with.
+    pub status: StatusCode,
+}
+
+/// Every URI visited to reach a response, oldest first and ending with the
+/// response itself.
+///
+/// Inserted into the extensions of responses when redirect following is
+/// enabled, so crawlers can record how URLs were canonicalized.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RedirectChain {
+    /// The responses received, in order.
+    pub hops: Vec<RedirectHop>,
+}
+
+impl RedirectChain {
+    /// Returns the URI the final response came from.
+    pub fn final_uri(&self) -> Option<&Uri> {
+        self.hops.last().map(|hop| &hop.uri)
+    }
+}
+
+/// Returns where a redirect response points, resolved against `base`, or
+/// `None` if the response is not a followable redirect.
+pub(crate) fn redirect_target(
+    base: &Uri,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> Option<Result<Uri>> {
+    if !matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308) {
+        return None;
+    }
+    let location = headers.get(LOCATION)?.to_str().ok()?;
+    Some(
+        Url::parse(&base.to_string())
+            .and_then(|base| base.join(location))
+            .map(Uri::from)
+            .map_err(|e| Error::Redirect(format!("invalid Location `{location}`: {e}"))),
+    )
+}
+
+/// Adjusts the headers of a request being redirected from `from` to `to`.
+///
+/// Credentials, cookies, a caller-supplied `Host` and the `sensitive`
+/// headers only ever go to the origin they were meant for. Redirects from
+/// HTTPS to plain HTTP are refused unless `allow_downgrade` is set.
+pub(crate) fn scope_redirect(
+    headers: &mut HeaderMap,
+    from: &Uri,
+    to: &Uri,
+    sensitive: &[HeaderName],
+    allow_downgrade: bool,
+) -> Result<()> {
+    let downgrade = from.scheme_str() == Some("https") && to.scheme_str() != Some("https");
+    if downgrade && !allow_downgrade {
+        return Err(Error::Redirect(format!(
+            "refusing to follow a redirect from {from} to {to}, which is not HTTPS"
+        )));
+    }
+    if origin_of(from) != origin_of(to) {
+        for name in [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, HOST] {
+            headers.remove(name);
+        }
+        for name in sensitive {
+            headers.remove(name);
+        }
+    }
+    Ok(())
+}
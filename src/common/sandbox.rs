@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+/// The access a [`Client`](crate::Client) needs once it has been built, for
+/// applications that confine themselves with seccomp, landlock or similar.
+///
+/// Files the application hands to the client itself, such as uploads and
+/// download targets, are not included.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SandboxRequirements {
+    /// Files and directories the client opens. Directories are listed for
+    /// everything below them.
+    pub paths: Vec<SandboxPath>,
+    /// Families of the sockets the client opens.
+    pub socket_families: Vec<SocketFamily>,
+    /// Arti keeps its files in directories hypertor does not know, its
+    /// defaults or those of a given `tor_config`, which the application has
+    /// to allow itself. Set `tor_storage` to have them listed.
+    pub unlisted_tor_storage: bool,
+}
+
+/// A file or directory a [`Client`](crate::Client) opens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxPath {
+    /// The file or directory.
+    pub path: PathBuf,
+    /// Whether files are created, written or removed there, rather than
+    /// only read.
+    pub writable: bool,
+}
+
+/// A family of sockets a [`Client`](crate::Client) opens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SocketFamily {
+    /// TCP over IPv4 (`AF_INET`), for connections to Tor relays.
+    Ipv4,
+    /// TCP over IPv6 (`AF_INET6`), for connections to Tor relays.
+    Ipv6,
+}
@@ -0,0 +1,135 @@
+use super::{Cookie, Error, OnionRoute, Result};
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Version of the session snapshot format.
+const SESSION_VERSION: u32 = 2;
+
+/// Serializable snapshot of a client's session state.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct SessionState {
+    version: u32,
+    /// Persisted challenge clearance headers, keyed by origin.
+    #[serde(default)]
+    clearances: BTreeMap<String, Vec<(String, String)>>,
+    /// Cookies of the configured store; `None` if there is no store or it
+    /// cannot list its cookies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cookies: Option<Vec<Cookie>>,
+    /// Learned onion routes.
+    #[serde(default)]
+    onion_routes: Vec<SavedRoute>,
+}
+
+/// A learned onion route, expiring at `expires` seconds since the Unix epoch.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedRoute {
+    origin: String,
+    kind: String,
+    target: String,
+    expires: u64,
+}
+
+impl SessionState {
+    /// Captures the given clearance store, cookies and onion routes.
+    pub(crate) fn capture(
+        clearances: &HashMap<String, HeaderMap>,
+        cookies: Option<Vec<Cookie>>,
+        onion_routes: Vec<(String, OnionRoute, SystemTime)>,
+    ) -> Self {
+        let clearances = clearances
+            .iter()
+            .map(|(origin, headers)| {
+                let headers = headers
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        Some((name.to_string(), value.to_str().ok()?.to_string()))
+                    })
+                    .collect();
+                (origin.clone(), headers)
+            })
+            .collect();
+        let onion_routes = onion_routes
+            .into_iter()
+            .map(|(origin, route, expires)| {
+                let (kind, target) = match route {
+                    OnionRoute::Location(target) => ("location", target),
+                    OnionRoute::AltSvc(target) => ("alt-svc", target),
+                };
+                SavedRoute {
+                    origin,
+                    kind: kind.to_string(),
+                    target,
+                    expires: expires
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                }
+            })
+            .collect();
+
+        SessionState {
+            version: SESSION_VERSION,
+            clearances,
+            cookies,
+            onion_routes,
+        }
+    }
+
+    /// Decodes a snapshot produced by [`SessionState::to_bytes`].
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let state: SessionState =
+            serde_json::from_slice(bytes).map_err(|e| Error::Other(e.into()))?;
+        if state.version > SESSION_VERSION {
+            return Err(Error::Other(
+                format!("unsupported session state version {}", state.version).into(),
+            ));
+        }
+        Ok(state)
+    }
+
+    /// Encodes the snapshot.
+    pub(crate) fn to_bytes(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).map_err(|e| Error::Other(e.into()))
+    }
+
+    /// Returns the snapshot's clearance store.
+    pub(crate) fn clearances(&self) -> Result<HashMap<String, HeaderMap>> {
+        self.clearances
+            .iter()
+            .map(|(origin, pairs)| {
+                let mut headers = HeaderMap::new();
+                for (name, value) in pairs {
+                    let name = HeaderName::from_bytes(name.as_bytes())
+                        .map_err(|e| Error::Other(e.into()))?;
+                    let value = HeaderValue::from_str(value).map_err(|e| Error::Other(e.into()))?;
+                    headers.append(name, value);
+                }
+                Ok((origin.clone(), headers))
+            })
+            .collect()
+    }
+
+    /// Returns the snapshot's cookies, if it holds any.
+    pub(crate) fn cookies(&self) -> Option<Vec<Cookie>> {
+        self.cookies.clone()
+    }
+
+    /// Returns the snapshot's onion routes, skipping those of unknown kinds.
+    pub(crate) fn onion_routes(&self) -> Vec<(String, OnionRoute, SystemTime)> {
+        self.onion_routes
+            .iter()
+            .filter_map(|saved| {
+                let route = match saved.kind.as_str() {
+                    "location" => OnionRoute::Location(saved.target.clone()),
+                    "alt-svc" => OnionRoute::AltSvc(saved.target.clone()),
+                    _ => return None,
+                };
+                let expires = UNIX_EPOCH + Duration::from_secs(saved.expires);
+                Some((saved.origin.clone(), route, expires))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,316 @@
+use crate::common::ConnectionErrorKind;
+use http::header::HeaderValue;
+use http::StatusCode;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::time::Duration;
+
+/// The country clearnet requests leave the Tor network from, as an ISO
+/// 3166-1 alpha-2 code such as `DE`.
+///
+/// Insert it into a request's extensions to pick the exit country of that
+/// request. Onion services are reached without an exit, so it does not
+/// apply to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExitCountry([u8; 2]);
+
+impl ExitCountry {
+    /// Parses a two-letter country code, in either case.
+    pub fn new(code: &str) -> Option<Self> {
+        match code.as_bytes() {
+            &[a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => Some(ExitCountry([
+                a.to_ascii_uppercase(),
+                b.to_ascii_uppercase(),
+            ])),
+            _ => None,
+        }
+    }
+
+    /// Returns the code in uppercase.
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.0).expect("country codes are ASCII")
+    }
+}
+
+impl std::fmt::Display for ExitCountry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Scheduling priority of a request.
+///
+/// Insert it into a request's extensions to have it dispatched ahead of (or
+/// behind) other requests when the client's concurrency limit is reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Prefetching, crawling and other work nobody is waiting on.
+    Background,
+    /// The default priority.
+    #[default]
+    Normal,
+    /// Requests a user is actively waiting on.
+    Interactive,
+}
+
+/// Smallest maximum read buffer hyper accepts.
+pub(crate) const MIN_MAX_READ_BUFFER: usize = 8192;
+
+/// How the read buffer of each connection is sized.
+///
+/// The read buffer bounds how much response data is held per request before
+/// the consumer reads it, which matters on memory-constrained devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadBuffer {
+    /// Grow as needed up to hyper's default limit (about 400 KiB).
+    #[default]
+    Adaptive,
+    /// Grow as needed up to the given number of bytes (at least 8192).
+    Bounded(usize),
+    /// Always use a buffer of exactly the given number of bytes.
+    Exact(usize),
+}
+
+/// How the HTTP/1 connection behind a request is polled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionDriver {
+    /// Poll each connection on its own spawned task.
+    #[default]
+    Spawned,
+    /// Poll the connection on the caller's task, together with the response
+    /// and its body. No task is spawned and connection errors are returned
+    /// from the request or body, but the body must be read for the exchange
+    /// to make progress.
+    Inline,
+}
+
+/// HTTP/1 connection options for servers that do not quite follow the spec.
+///
+/// These map directly onto hyper's client connection builder. hyper's client
+/// has no half-close setting: a connection is always shut down in both
+/// directions once the response has been read.
+#[derive(Debug, Clone, Default)]
+pub struct Http1Options {
+    /// Maximum number of response headers; `None` keeps hyper's default (100).
+    pub max_headers: Option<usize>,
+    /// Force (`true`) or disable (`false`) vectored writes; `None` lets hyper
+    /// choose.
+    pub writev: Option<bool>,
+    /// Write request header names in title case (`Content-Type`) instead of
+    /// lower case.
+    pub title_case_headers: bool,
+    /// Keep header names of forwarded requests in the case they were received
+    /// in.
+    ///
+    /// hyper only records the original case when it parses a message itself,
+    /// so this applies to requests passed to `Client::forward` from a hyper
+    /// server with case preservation enabled. Other headers fall back to
+    /// `title_case_headers`.
+    pub preserve_header_case: bool,
+    /// Accept obsolete line folding in response header values.
+    pub allow_obsolete_multiline_headers: bool,
+    /// Accept whitespace between response header names and the colon.
+    pub allow_spaces_after_header_name: bool,
+    /// Skip malformed response header lines instead of failing the request.
+    pub ignore_invalid_headers: bool,
+    /// Accept HTTP/0.9 responses, which have no status line or headers.
+    pub http09_responses: bool,
+}
+
+/// Settings for adjusting per-host concurrency to observed performance.
+///
+/// Limits grow by roughly one slot per window of fast, successful requests and
+/// shrink multiplicatively on failures or slow responses (AIMD), so a slow
+/// onion service is not buried under more requests than it can answer.
+#[derive(Debug, Clone)]
+pub struct AdaptiveConcurrency {
+    /// Limit each host starts with.
+    pub initial: usize,
+    /// Lower bound for the limit.
+    pub min: usize,
+    /// Upper bound for the limit.
+    pub max: usize,
+    /// Responses slower than this count as congestion.
+    pub latency_target: Duration,
+    /// Factor the limit is multiplied with on congestion.
+    pub backoff: f64,
+}
+
+impl Default for AdaptiveConcurrency {
+    fn default() -> Self {
+        AdaptiveConcurrency {
+            initial: 4,
+            min: 1,
+            max: 32,
+            latency_target: Duration::from_secs(10),
+            backoff: 0.5,
+        }
+    }
+}
+
+/// A budget that caps automatic retries as a share of recent requests.
+///
+/// Every request adds `retry_ratio` retries to the budget and every retry
+/// spends one, so retries across circuits and mirrors cannot multiply load
+/// during an outage. `min_per_sec` keeps a few retries available while
+/// traffic is low.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    /// How long requests and retries count against the budget.
+    pub window: Duration,
+    /// Retries allowed per second regardless of traffic.
+    pub min_per_sec: u32,
+    /// Retries earned by each request, e.g. `0.2` for one retry per five
+    /// requests.
+    pub retry_ratio: f64,
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        RetryBudget {
+            window: Duration::from_secs(10),
+            min_per_sec: 10,
+            retry_ratio: 0.2,
+        }
+    }
+}
+
+/// When and how often requests that failed for a transient reason are
+/// sent again.
+///
+/// Only requests with idempotent methods, such as `GET`, `PUT` and
+/// `DELETE`, are retried. Each retry also spends from the
+/// [`RetryBudget`], if one is set; once it is spent, the last failure is
+/// returned.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further one.
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between attempts.
+    pub max_backoff: Duration,
+    /// Wait a random time between half and all of each delay, so that
+    /// clients failing together do not retry together.
+    pub jitter: bool,
+    /// Response statuses that are retried.
+    pub statuses: Vec<StatusCode>,
+    /// Connection failures that are retried.
+    pub error_kinds: Vec<ConnectionErrorKind>,
+    /// Retry requests that timed out connecting or waiting for response
+    /// headers, or whose upload stalled.
+    pub timeouts: bool,
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait before retry number `retry`, counting from 1.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self
+            .initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff);
+        if !self.jitter {
+            return delay;
+        }
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, half a second apart and then one second, for bad
+    /// gateways, unavailable services, gateway timeouts, collapsed circuits,
+    /// reset streams and timeouts.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+            statuses: vec![
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            error_kinds: vec![
+                ConnectionErrorKind::CircuitClosed,
+                ConnectionErrorKind::ResetByPeer,
+            ],
+            timeouts: true,
+        }
+    }
+}
+
+/// Limits on how much a reused connection carries before it is retired.
+///
+/// The server, and for clearnet the exit relay, can link everything sent
+/// over one connection or circuit; capping their use bounds that linkable
+/// activity.
+#[derive(Debug, Clone, Default)]
+pub struct UsageCaps {
+    /// Requests after which a connection is no longer reused.
+    pub max_requests: Option<u64>,
+    /// Bytes read and written after which a connection is no longer reused.
+    pub max_bytes: Option<u64>,
+    /// Also move requests without their own isolation token to a new
+    /// circuit when a connection is retired.
+    pub retire_circuit: bool,
+}
+
+/// How the `User-Agent` header is set on requests that carry none.
+///
+/// The header links requests as well as a circuit does, so rotating one
+/// without the other gains little. `PerIdentity` ties each value to the
+/// circuit identity (isolation token) a request is sent over.
+#[derive(Debug, Clone)]
+pub enum UserAgent {
+    /// Sends the same value with every request.
+    Fixed(HeaderValue),
+    /// Picks a value per circuit identity: requests sharing an isolation
+    /// token share a value, and requests without one use the first.
+    PerIdentity(Vec<HeaderValue>),
+    /// Picks a random value for every request.
+    PerRequest(Vec<HeaderValue>),
+}
+
+impl UserAgent {
+    /// Chooses the value for a request sent over the circuit identified by
+    /// `identity`.
+    pub(crate) fn choose(&self, identity: Option<u64>) -> Option<&HeaderValue> {
+        match self {
+            UserAgent::Fixed(value) => Some(value),
+            UserAgent::PerIdentity(values) => {
+                let index = identity.unwrap_or(0).checked_rem(values.len() as u64)?;
+                values.get(index as usize)
+            }
+            UserAgent::PerRequest(values) => values.choose(&mut rand::thread_rng()),
+        }
+    }
+}
+
+/// Settings for preferring historically fast exits for clearnet traffic.
+///
+/// arti does not let applications pick exit relays directly, so hypertor keeps
+/// a few isolated circuits, tracks how quickly requests through each exit
+/// relay are answered and sends clearnet requests over the circuit whose exit
+/// has been fastest, periodically rebuilding the slowest. Latencies are kept
+/// per exit, so circuits built after
+/// [`Client::new_identity`](crate::Client::new_identity) are ranked by what
+/// was learned before. Concentrating traffic on fewer, faster paths makes
+/// requests more linkable, which is why this is disabled unless configured.
+#[derive(Debug, Clone)]
+pub struct FastExitSelection {
+    /// Number of candidate circuits to keep.
+    pub circuits: usize,
+    /// Number of requests after which the slowest circuit is replaced.
+    pub rotate_after: u32,
+}
+
+impl Default for FastExitSelection {
+    fn default() -> Self {
+        FastExitSelection {
+            circuits: 3,
+            rotate_after: 50,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::{ConnectionError, ConnectionErrorKind, Error, Result};
+use std::net::IpAddr;
+use zeroize::Zeroizing;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// A SOCKS5 proxy reached through Tor, such as a bastion published as an
+/// onion service.
+///
+/// The password is wiped from memory when the proxy is dropped.
+#[derive(Clone)]
+pub struct Socks5Proxy {
+    /// Host of the proxy.
+    pub host: String,
+    /// Port of the proxy.
+    pub port: u16,
+    /// Username and password for RFC 1929 authentication, if required.
+    pub credentials: Option<(String, Zeroizing<String>)>,
+}
+
+impl Socks5Proxy {
+    /// Creates a proxy that needs no authentication.
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Socks5Proxy {
+            host: host.into(),
+            port,
+            credentials: None,
+        }
+    }
+
+    /// Authenticates with a username and password.
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some((username.into(), Zeroizing::new(password.into())));
+        self
+    }
+}
+
+impl std::fmt::Debug for Socks5Proxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Socks5Proxy")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field(
+                "credentials",
+                &self.credentials.as_ref().map(|_| "[redacted]"),
+            )
+            .finish()
+    }
+}
+
+/// Encodes the method selection message.
+pub(crate) fn socks_greeting(authenticate: bool) -> Vec<u8> {
+    if authenticate {
+        vec![VERSION, 2, NO_AUTH, USERNAME_PASSWORD]
+    } else {
+        vec![VERSION, 1, NO_AUTH]
+    }
+}
+
+/// Checks the server's method choice and returns `true` if it asks for a
+/// username and password.
+pub(crate) fn socks_method(reply: [u8; 2], authenticate: bool) -> Result<bool> {
+    match reply {
+        [VERSION, NO_AUTH] => Ok(false),
+        [VERSION, USERNAME_PASSWORD] if authenticate => Ok(true),
+        [VERSION, NO_ACCEPTABLE_METHODS] => {
+            Err(refused("SOCKS5 proxy accepted no offered method".into()))
+        }
+        [VERSION, method] => Err(protocol(format!(
+            "SOCKS5 proxy chose unexpected method {method:#04x}"
+        ))),
+        [version, _] => Err(protocol(format!("not a SOCKS5 proxy (version {version})"))),
+    }
+}
+
+/// Encodes an RFC 1929 username/password request.
+pub(crate) fn socks_password_auth(username: &str, password: &str) -> Result<Zeroizing<Vec<u8>>> {
+    if username.len() > 255 || password.len() > 255 {
+        return Err(Error::InvalidRequest(
+            "SOCKS5 username and password must be at most 255 bytes".into(),
+        ));
+    }
+    // Sized up front so no reallocation leaves a copy of the password behind.
+    let mut message = Zeroizing::new(Vec::with_capacity(3 + username.len() + password.len()));
+    message.extend_from_slice(&[1, username.len() as u8]);
+    message.extend_from_slice(username.as_bytes());
+    message.push(password.len() as u8);
+    message.extend_from_slice(password.as_bytes());
+    Ok(message)
+}
+
+/// Encodes a CONNECT request for `host:port`.
+///
+/// Host names are sent as names so the proxy resolves them, keeping lookups
+/// off the local network.
+pub(crate) fn socks_connect(host: &str, port: u16) -> Result<Vec<u8>> {
+    let mut message = vec![VERSION, CONNECT, 0];
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(IpAddr::V4(ip)) => {
+            message.push(ATYP_IPV4);
+            message.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            message.push(ATYP_IPV6);
+            message.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.is_empty() || host.len() > 255 {
+                return Err(Error::InvalidRequest(
+                    "SOCKS5 host names must be 1 to 255 bytes".into(),
+                ));
+            }
+            message.push(ATYP_DOMAIN);
+            message.push(host.len() as u8);
+            message.extend_from_slice(host.as_bytes());
+        }
+    }
+    message.extend_from_slice(&port.to_be_bytes());
+    Ok(message)
+}
+
+/// Checks the head of a CONNECT reply and returns the length of the bound
+/// address and port that follow it, or of the domain length byte for
+/// domain addresses.
+pub(crate) fn socks_reply(head: [u8; 4]) -> Result<usize> {
+    let [version, reply, _, atyp] = head;
+    if version != VERSION {
+        return Err(protocol(format!("not a SOCKS5 proxy (version {version})")));
+    }
+    let reason = match reply {
+        0x00 => None,
+        0x01 => Some("general failure"),
+        0x02 => Some("connection not allowed by ruleset"),
+        0x03 => Some("network unreachable"),
+        0x04 => Some("host unreachable"),
+        0x05 => Some("connection refused"),
+        0x06 => Some("TTL expired"),
+        0x07 => Some("command not supported"),
+        0x08 => Some("address type not supported"),
+        _ => Some("unknown error"),
+    };
+    if let Some(reason) = reason {
+        return Err(refused(format!("SOCKS5 proxy failed to connect: {reason}")));
+    }
+    match atyp {
+        ATYP_IPV4 => Ok(4 + 2),
+        ATYP_IPV6 => Ok(16 + 2),
+        ATYP_DOMAIN => Ok(1),
+        _ => Err(protocol(format!(
+            "SOCKS5 reply has unknown address type {atyp}"
+        ))),
+    }
+}
+
+/// An error for a proxy that broke the SOCKS5 protocol.
+fn protocol(reason: String) -> Error {
+    Error::Connect(ConnectionError::with_kind(
+        ConnectionErrorKind::Protocol,
+        reason,
+    ))
+}
+
+/// An error for a proxy that declined the request.
+pub(crate) fn refused(reason: String) -> Error {
+    Error::Connect(ConnectionError::with_kind(
+        ConnectionErrorKind::Other,
+        reason,
+    ))
+}
@@ -0,0 +1,16 @@
+/// Disk space taken by arti's files, as reported by
+/// [`Client::storage_usage`](crate::Client::storage_usage).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StorageUsage {
+    /// Bytes in the state directory: guards, circuit timings and keys.
+    pub state: u64,
+    /// Bytes in the directory cache.
+    pub cache: u64,
+}
+
+impl StorageUsage {
+    /// Returns the bytes taken by both directories together.
+    pub fn total(&self) -> u64 {
+        self.state + self.cache
+    }
+}
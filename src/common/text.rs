@@ -0,0 +1,39 @@
+#[cfg(feature = "text")]
+use encoding_rs::{Encoding, UTF_8};
+use http::header::HeaderMap;
+#[cfg(feature = "text")]
+use http::header::CONTENT_TYPE;
+
+/// Decodes a body to text using the charset named in `Content-Type`.
+///
+/// A byte order mark takes precedence over the charset. Unknown or missing
+/// charsets fall back to UTF-8, and malformed sequences are replaced with
+/// U+FFFD rather than failing.
+#[cfg(feature = "text")]
+pub(crate) fn decode_text(headers: &HeaderMap, body: &[u8]) -> String {
+    let encoding = charset(headers)
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    let (text, _, _) = encoding.decode(body);
+    text.into_owned()
+}
+
+/// Decodes a body as UTF-8, whatever its charset; other charsets need the
+/// `text` feature. Malformed sequences are replaced with U+FFFD.
+#[cfg(not(feature = "text"))]
+pub(crate) fn decode_text(_headers: &HeaderMap, body: &[u8]) -> String {
+    let body = body.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(body);
+    String::from_utf8_lossy(body).into_owned()
+}
+
+/// Returns the `charset` parameter of the `Content-Type` header, if any.
+#[cfg(feature = "text")]
+fn charset(headers: &HeaderMap) -> Option<&str> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
@@ -0,0 +1,15 @@
+/// Details of the TLS session a response was received over.
+///
+/// Inserted into the extensions of responses to HTTPS requests. native-tls
+/// does not report the negotiated protocol version or cipher suite, so those
+/// are not available.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// The protocol negotiated with ALPN, if any.
+    pub alpn_protocol: Option<Vec<u8>>,
+    /// DER-encoded certificates presented by the server, leaf first.
+    ///
+    /// native-tls only exposes the leaf certificate, so this holds at most one
+    /// entry unless a TLS session cache is configured.
+    pub peer_certificates: Vec<Vec<u8>>,
+}
@@ -0,0 +1,243 @@
+use super::{Error, Result};
+use http::header::HeaderValue;
+use http::Uri;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// Returns `true` if `host` is an onion service address.
+pub(crate) fn is_onion(host: &str) -> bool {
+    host.trim_end_matches('.')
+        .rsplit('.')
+        .next()
+        .is_some_and(|tld| tld.eq_ignore_ascii_case("onion"))
+}
+
+/// Returns `true` if `host` names this machine or a private network, the
+/// targets arti refuses unless local addresses are allowed: `localhost`, or
+/// a loopback, private, link-local or unspecified address.
+#[cfg_attr(not(feature = "exclude-exits"), allow(dead_code))]
+pub(crate) fn is_local_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let name = host.trim_end_matches('.');
+    if name.eq_ignore_ascii_case("localhost") || name.to_ascii_lowercase().ends_with(".localhost") {
+        return true;
+    }
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => is_local_v4(ip),
+        Ok(IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_local_v4(ip),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80
+            }
+        },
+        Err(_) => false,
+    }
+}
+
+fn is_local_v4(ip: Ipv4Addr) -> bool {
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+}
+
+/// Returns the default `Host` header for `uri`: its host, plus the port
+/// unless it is the scheme's default. Some origins reject `host:443`.
+pub(crate) fn host_header(uri: &Uri) -> Option<HeaderValue> {
+    let host = uri.host()?;
+    let default_port = match uri.scheme_str() {
+        Some("https") => Some(443),
+        Some("http") => Some(80),
+        _ => None,
+    };
+    let value = match uri.port_u16() {
+        Some(port) if Some(port) != default_port => format!("{host}:{port}"),
+        _ => host.to_string(),
+    };
+    HeaderValue::from_str(&value).ok()
+}
+
+/// Returns `uri` with its host replaced by `host`, keeping any explicit port.
+pub(crate) fn with_host(uri: &Uri, host: &str) -> Option<Uri> {
+    let authority = match uri.port_u16() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.authority = Some(authority.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// Returns `uri` moved to `origin` (`scheme://host[:port]`), keeping its path
+/// and query.
+pub(crate) fn with_origin(uri: &Uri, origin: &str) -> Option<Uri> {
+    let origin = origin.parse::<Uri>().ok()?.into_parts();
+    let mut parts = uri.clone().into_parts();
+    parts.scheme = Some(origin.scheme?);
+    parts.authority = Some(origin.authority?);
+    Uri::from_parts(parts).ok()
+}
+
+/// Returns the `scheme://host:port` origin of `uri`, if it is absolute.
+pub(crate) fn origin_of(uri: &Uri) -> Option<String> {
+    let scheme = uri.scheme_str()?;
+    let host = uri.host()?;
+    let port = uri
+        .port_u16()
+        .unwrap_or(if scheme == "https" { 443 } else { 80 });
+    Some(format!("{scheme}://{host}:{port}"))
+}
+
+/// Returns `true` if `host` ends in a well-formed v3 onion address.
+pub(crate) fn is_valid_onion(host: &str) -> bool {
+    let mut labels = host.trim_end_matches('.').rsplit('.').skip(1);
+    labels.next().is_some_and(|label| {
+        label.len() == 56
+            && label
+                .bytes()
+                .all(|b| matches!(b.to_ascii_lowercase(), b'a'..=b'z' | b'2'..=b'7'))
+    })
+}
+
+/// An absolute `http` or `https` URL with a host.
+///
+/// Onion hosts must be well-formed v3 addresses. A `Url` converts into a
+/// [`Uri`] without failing, so it can be passed to any request method.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Url {
+    url: url::Url,
+    uri: Uri,
+}
+
+impl Url {
+    /// Parses and validates an absolute URL.
+    pub fn parse(input: &str) -> Result<Self> {
+        Self::validate(url::Url::parse(input).map_err(invalid_url)?)
+    }
+
+    /// Resolves a possibly relative reference, such as a link or a
+    /// `Location` header, against this URL.
+    pub fn join(&self, reference: &str) -> Result<Self> {
+        Self::validate(self.url.join(reference).map_err(invalid_url)?)
+    }
+
+    fn validate(mut url: url::Url) -> Result<Self> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(Error::InvalidRequest(format!(
+                "unsupported URL scheme `{}`",
+                url.scheme()
+            )));
+        }
+        let Some(host) = url.host_str() else {
+            return Err(Error::InvalidRequest("URL has no host".into()));
+        };
+        if is_onion(host) && !is_valid_onion(host) {
+            return Err(Error::InvalidRequest(format!(
+                "`{host}` is not a valid v3 onion address"
+            )));
+        }
+
+        // Fragments are never sent to the server.
+        url.set_fragment(None);
+        let uri = url.as_str().parse().map_err(http::Error::from)?;
+        Ok(Url { url, uri })
+    }
+
+    /// Returns the scheme, `http` or `https`.
+    pub fn scheme(&self) -> &str {
+        self.url.scheme()
+    }
+
+    /// Returns the host, without brackets for IPv6 addresses.
+    pub fn host(&self) -> &str {
+        self.url
+            .host_str()
+            .expect("validated URL has a host")
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+    }
+
+    /// Returns the port, or the scheme's default port.
+    pub fn port(&self) -> u16 {
+        self.url
+            .port_or_known_default()
+            .expect("http and https have default ports")
+    }
+
+    /// Returns `true` if the host is an onion service.
+    pub fn is_onion(&self) -> bool {
+        is_onion(self.host())
+    }
+
+    /// Returns the URL as a string.
+    pub fn as_str(&self) -> &str {
+        self.url.as_str()
+    }
+}
+
+impl std::fmt::Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Url {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Url::parse(s)
+    }
+}
+
+fn invalid_url(error: url::ParseError) -> Error {
+    Error::InvalidRequest(format!("invalid URL: {error}"))
+}
+
+impl From<Url> for Uri {
+    fn from(url: Url) -> Self {
+        url.uri
+    }
+}
+
+impl From<&Url> for Uri {
+    fn from(url: &Url) -> Self {
+        url.uri.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_hosts() {
+        for host in [
+            "localhost",
+            "api.localhost.",
+            "127.0.0.1",
+            "10.0.0.8",
+            "172.16.4.1",
+            "192.168.1.1",
+            "169.254.0.1",
+            "0.0.0.0",
+            "[::1]",
+            "[fd00::1]",
+            "[fe80::1]",
+            "[::ffff:192.168.0.1]",
+        ] {
+            assert!(is_local_host(host), "{host}");
+        }
+        for host in [
+            "example.com",
+            "93.184.216.34",
+            "[2001:db8::1]",
+            "localhost.example",
+        ] {
+            assert!(!is_local_host(host), "{host}");
+        }
+    }
+}
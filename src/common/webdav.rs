@@ -0,0 +1,163 @@
+use super::{Error, Result};
+use quick_xml::events::Event;
+use quick_xml::name::{Namespace, ResolveResult};
+use quick_xml::NsReader;
+
+/// Namespace of the WebDAV properties defined by RFC 4918.
+const DAV_NS: &[u8] = b"DAV:";
+
+/// Value of the `Depth` header sent with `PROPFIND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Depth {
+    /// Only the resource itself.
+    Zero,
+    /// The resource and its immediate members.
+    #[default]
+    One,
+    /// The resource and all of its descendants. Many servers refuse this.
+    Infinity,
+}
+
+impl Depth {
+    /// Returns the header value for this depth.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Depth::Zero => "0",
+            Depth::One => "1",
+            Depth::Infinity => "infinity",
+        }
+    }
+}
+
+/// A resource listed in a `207 Multi-Status` response to `PROPFIND`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DavResource {
+    /// The resource's `href`, as sent by the server (usually percent-encoded).
+    pub href: String,
+    /// Whether the resource is a collection (a directory).
+    pub is_collection: bool,
+    /// `getcontentlength`, if reported.
+    pub content_length: Option<u64>,
+    /// `getcontenttype`, if reported.
+    pub content_type: Option<String>,
+    /// `getetag`, if reported.
+    pub etag: Option<String>,
+    /// `getlastmodified`, as an HTTP date.
+    pub last_modified: Option<String>,
+    /// `displayname`, if reported.
+    pub display_name: Option<String>,
+}
+
+/// Properties collected from one `propstat` element.
+#[derive(Default)]
+struct PropStat {
+    ok: bool,
+    resource: DavResource,
+}
+
+/// Parses a `multistatus` document into the resources it describes.
+///
+/// Only properties from `propstat` elements with a 2xx status are kept;
+/// properties the server could not return are left unset.
+pub(crate) fn parse_multistatus(xml: &[u8]) -> Result<Vec<DavResource>> {
+    let mut reader = NsReader::from_reader(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut resources = Vec::new();
+    let mut current = DavResource::default();
+    let mut propstat = PropStat::default();
+    // Local names of the open elements; elements outside the DAV namespace
+    // are recorded as empty strings.
+    let mut path: Vec<String> = Vec::new();
+
+    loop {
+        let (ns, event) = reader
+            .read_resolved_event()
+            .map_err(|e| Error::Decode(e.into()))?;
+        match event {
+            Event::Start(e) => {
+                let name = dav_name(&ns, e.local_name().as_ref());
+                match name.as_str() {
+                    "response" => current = DavResource::default(),
+                    "propstat" => propstat = PropStat::default(),
+                    "collection" if parent(&path, 0) == "resourcetype" => {
+                        propstat.resource.is_collection = true;
+                    }
+                    _ => {}
+                }
+                path.push(name);
+            }
+            Event::Empty(e) => {
+                let name = dav_name(&ns, e.local_name().as_ref());
+                if name == "collection" && parent(&path, 0) == "resourcetype" {
+                    propstat.resource.is_collection = true;
+                }
+            }
+            Event::Text(t) => {
+                let text = t
+                    .unescape()
+                    .map_err(|e| Error::Decode(e.into()))?
+                    .into_owned();
+                match (parent(&path, 1), parent(&path, 0)) {
+                    ("response", "href") => current.href = text,
+                    ("propstat", "status") => propstat.ok = is_success_status(&text),
+                    ("prop", "getcontentlength") => {
+                        propstat.resource.content_length = text.parse().ok();
+                    }
+                    ("prop", "getcontenttype") => propstat.resource.content_type = Some(text),
+                    ("prop", "getetag") => propstat.resource.etag = Some(text),
+                    ("prop", "getlastmodified") => propstat.resource.last_modified = Some(text),
+                    ("prop", "displayname") => propstat.resource.display_name = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(_) => match path.pop().as_deref() {
+                Some("propstat") if propstat.ok => {
+                    merge(&mut current, std::mem::take(&mut propstat.resource));
+                }
+                Some("response") => resources.push(std::mem::take(&mut current)),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(resources)
+}
+
+/// Returns the local name of an element in the DAV namespace, or an empty
+/// string for elements in other namespaces.
+fn dav_name(ns: &ResolveResult, local_name: &[u8]) -> String {
+    match ns {
+        ResolveResult::Bound(Namespace(uri)) if *uri == DAV_NS => {
+            String::from_utf8_lossy(local_name).into_owned()
+        }
+        _ => String::new(),
+    }
+}
+
+/// Returns the name of the open element `depth` levels above the innermost.
+fn parent(path: &[String], depth: usize) -> &str {
+    path.len()
+        .checked_sub(depth + 1)
+        .map_or("", |i| path[i].as_str())
+}
+
+/// Checks an `HTTP/1.1 200 OK` style status line for a 2xx code.
+fn is_success_status(status: &str) -> bool {
+    status
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|code| code.starts_with('2'))
+}
+
+/// Copies the properties set in `from` into `into`.
+fn merge(into: &mut DavResource, from: DavResource) {
+    into.is_collection |= from.is_collection;
+    into.content_length = from.content_length.or(into.content_length);
+    into.content_type = from.content_type.or(into.content_type.take());
+    into.etag = from.etag.or(into.etag.take());
+    into.last_modified = from.last_modified.or(into.last_modified.take());
+    into.display_name = from.display_name.or(into.display_name.take());
+}
@@ -0,0 +1,1535 @@
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+use crate::common::DecodeLimits;
+#[cfg(feature = "chaos")]
+use crate::common::FaultInjection;
+use crate::common::{
+    AdaptiveConcurrency, ChallengeSolver, ConnectionDriver, CookieStore, CredentialsProvider,
+    ExitCountry, FastExitSelection, Http1Options, ReadBuffer, RedirectPolicy, RetryBudget,
+    RetryPolicy, SandboxPath, SandboxRequirements, SocketFamily, TorNetwork, UsageCaps, UserAgent,
+    MIN_MAX_READ_BUFFER,
+};
+use crate::error::{Error, Result};
+use crate::hooks::{ClockSkewHook, ConnectHook, Connector, SandboxHook, WireTap};
+use arti_client::config::dir::{Authority as DirAuthority, FallbackDir};
+use arti_client::config::TorClientConfigBuilder;
+use arti_client::TorClientConfig;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::http::uri::{Authority, PathAndQuery};
+use hyper::Uri;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "native-tls")]
+use tokio_native_tls::native_tls::TlsConnector;
+use tor_llcrypto::pk::ed25519::Ed25519Identity;
+use tor_llcrypto::pk::rsa::RsaIdentity;
+
+/// How long [`ClientConfigBuilder::test_network`] waits for circuits and
+/// servers.
+const TEST_NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Directories OpenSSL's trusted certificates are commonly found in,
+/// including those the certificate links point into.
+#[cfg(all(feature = "native-tls", unix, not(target_os = "macos")))]
+const TRUST_STORE_PATHS: &[&str] = &[
+    "/etc/ssl",
+    "/etc/pki/tls",
+    "/etc/ca-certificates",
+    "/usr/share/ca-certificates",
+    "/usr/lib/ssl",
+];
+
+/// The RAM-backed file system memory-only clients keep Tor's files on.
+const MEMORY_ROOT: &str = "/dev/shm";
+
+/// The `Accept-Language` header sent by Tor Browser.
+const TOR_BROWSER_ACCEPT_LANGUAGE: &str = "en-US,en;q=0.5";
+
+/// Shown by `Debug` in place of settings that may hold keys or credentials.
+const REDACTED: &str = "<redacted>";
+
+/// Configuration for the `Client`.
+///
+/// `Debug` shows the effective settings but redacts the TLS and Tor
+/// configurations, which may hold client keys and onion service
+/// authorization secrets, along with credential providers and cookie
+/// stores.
+///
+/// Secrets held by hypertor types, namely [`Credentials`](crate::Credentials),
+/// SOCKS5 proxy passwords, S3 secret keys and gateway tenant passwords, are
+/// kept in [`Zeroizing`](crate::Zeroizing) buffers and wiped when dropped,
+/// as are the buffers they are encoded in. Headers built from them are
+/// marked sensitive but not wiped. hypertor never copies TLS private keys
+/// out of the `TlsConnector`, nor onion service keys out of arti's
+/// configuration and keystore.
+pub struct ClientConfig {
+    /// TLS configuration for HTTPS connections.
+    #[cfg(feature = "native-tls")]
+    pub tls_config: TlsConnector,
+    /// Connector resuming TLS sessions, used for HTTPS instead of
+    /// `tls_config` if set.
+    #[cfg(feature = "tls-session-cache")]
+    pub tls_session_cache: Option<tokio_rustls::TlsConnector>,
+    /// Tor client configuration for routing through the Tor network.
+    pub tor_config: TorClientConfig,
+    /// Directory arti keeps its state, such as its guards, in, if set with
+    /// `tor_storage`.
+    pub tor_state_dir: Option<PathBuf>,
+    /// Directory arti caches directory documents in, if set with
+    /// `tor_storage`.
+    pub tor_cache_dir: Option<PathBuf>,
+    /// Keep nothing on disk; the Tor directories are on a RAM-backed file
+    /// system and removed with the client.
+    pub memory_only: bool,
+    /// Solvers consulted when a response looks like a challenge.
+    pub challenge_solvers: Vec<Arc<dyn ChallengeSolver>>,
+    /// Maximum number of times a request is retried after solving challenges.
+    pub max_challenge_attempts: usize,
+    /// Maximum number of requests in flight at once; `None` means unlimited.
+    pub max_concurrent_requests: Option<usize>,
+    /// Per-host concurrency control driven by observed latency and failures.
+    pub adaptive_concurrency: Option<AdaptiveConcurrency>,
+    /// Latency-based circuit preference for clearnet requests.
+    pub fast_exit_selection: Option<FastExitSelection>,
+    /// Country clearnet requests exit from, unless they pick their own.
+    pub exit_country: Option<ExitCountry>,
+    /// Fingerprints, in uppercase hex, of relays clearnet requests never
+    /// exit from.
+    pub exclude_exit_nodes: Vec<String>,
+    /// Countries clearnet requests never exit from.
+    pub exclude_countries: Vec<ExitCountry>,
+    /// Hook consulted before every connection is opened.
+    pub connect_hook: Option<Arc<dyn ConnectHook>>,
+    /// Opens streams in place of Tor, for tests.
+    pub connector: Option<Arc<dyn Connector>>,
+    /// Receives the plaintext bytes of every connection, for debugging.
+    pub wire_tap: Option<Arc<dyn WireTap>>,
+    /// Installs a sandbox once the client has been built.
+    pub sandbox_hook: Option<Arc<dyn SandboxHook>>,
+    /// Source of credentials for answering `401 Unauthorized` challenges.
+    pub credentials_provider: Option<Arc<dyn CredentialsProvider>>,
+    /// Sizing of each connection's read buffer.
+    pub read_buffer: ReadBuffer,
+    /// HTTP/1 connection options.
+    pub http1: Http1Options,
+    /// How long to wait for response headers once the request has been sent.
+    pub response_header_timeout: Option<Duration>,
+    /// How long a request may be unable to write before it is abandoned.
+    pub upload_stall_timeout: Option<Duration>,
+    /// How long opening a connection, TLS handshake included, may take.
+    pub connect_timeout: Option<Duration>,
+    /// How long a response body may go without data.
+    pub read_timeout: Option<Duration>,
+    /// How long a request may take from start to the end of its response
+    /// body.
+    pub total_request_timeout: Option<Duration>,
+    /// How connections are polled.
+    pub connection_driver: ConnectionDriver,
+    /// Hosts to contact in place of others, keyed by lowercase host name.
+    pub host_aliases: HashMap<String, String>,
+    /// Cap on automatic retries relative to recent traffic.
+    pub retry_budget: Option<RetryBudget>,
+    /// Which failed requests are sent again.
+    pub retry_policy: Option<RetryPolicy>,
+    /// Hold back requests to origins whose announced rate limit is used up.
+    pub rate_limit_pacing: bool,
+    /// Which redirects are followed.
+    pub redirect_policy: RedirectPolicy,
+    /// Headers removed from requests redirected to another origin, in
+    /// addition to credentials and cookies.
+    pub sensitive_headers: Vec<HeaderName>,
+    /// How the `User-Agent` header is set on requests that carry none.
+    pub user_agent: Option<UserAgent>,
+    /// Maximum number of idle connections kept per host and circuit; `0`
+    /// disables connection reuse.
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle connection is kept for reuse.
+    pub pool_idle_timeout: Duration,
+    /// Origins the client keeps a ready connection to.
+    pub warm_origins: Vec<Uri>,
+    /// `Accept-Language` sent with every request, replacing any set on it.
+    pub accept_language: Option<HeaderValue>,
+    /// Hook told about server clocks off by more than the given duration.
+    pub clock_skew_hook: Option<(Duration, Arc<dyn ClockSkewHook>)>,
+    /// Learn onion routes from `Onion-Location` and `Alt-Svc` headers.
+    pub learn_onion_routes: bool,
+    /// File learned onion routes are persisted to.
+    pub onion_route_store: Option<PathBuf>,
+    /// Limits after which reused connections are retired.
+    pub usage_caps: Option<UsageCaps>,
+    /// Store supplying and receiving cookies.
+    pub cookie_store: Option<Arc<dyn CookieStore>>,
+    /// Ask for compressed responses and decode them.
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    pub decompression: bool,
+    /// Bounds on how far decoded responses may expand.
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    pub decode_limits: DecodeLimits,
+    /// Smallest request body compressed for origins that accept it.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub compress_requests: Option<usize>,
+    /// Faults injected into every connection.
+    #[cfg(feature = "chaos")]
+    pub fault_injection: FaultInjection,
+    // Whether arti was told to allow local addresses, which streams opened
+    // past `connect_with_prefs` must check themselves.
+    #[cfg(feature = "exclude-exits")]
+    pub(crate) allow_local_addrs: bool,
+    // The directory `memory_only` picked, removed with the configuration
+    // rather than whatever `tor_state_dir` points to by then.
+    _memory_dir: Option<MemoryDir>,
+}
+
+/// Builder for creating a `ClientConfig`.
+pub struct ClientConfigBuilder {
+    #[cfg(feature = "native-tls")]
+    tls_config: Option<TlsConnector>,
+    #[cfg(feature = "tls-session-cache")]
+    tls_session_cache: Option<usize>,
+    tor_config: Option<TorClientConfig>,
+    tor_network: Option<TorNetwork>,
+    test_network: bool,
+    tor_storage: Option<(PathBuf, PathBuf)>,
+    memory_only: bool,
+    challenge_solvers: Vec<Arc<dyn ChallengeSolver>>,
+    max_challenge_attempts: usize,
+    max_concurrent_requests: Option<usize>,
+    adaptive_concurrency: Option<AdaptiveConcurrency>,
+    fast_exit_selection: Option<FastExitSelection>,
+    exit_country: Option<String>,
+    exclude_exit_nodes: Vec<String>,
+    exclude_countries: Vec<String>,
+    connect_hook: Option<Arc<dyn ConnectHook>>,
+    connector: Option<Arc<dyn Connector>>,
+    wire_tap: Option<Arc<dyn WireTap>>,
+    sandbox_hook: Option<Arc<dyn SandboxHook>>,
+    credentials_provider: Option<Arc<dyn CredentialsProvider>>,
+    read_buffer: ReadBuffer,
+    http1: Http1Options,
+    response_header_timeout: Option<Duration>,
+    upload_stall_timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    total_request_timeout: Option<Duration>,
+    connection_driver: ConnectionDriver,
+    host_aliases: HashMap<String, String>,
+    retry_budget: Option<RetryBudget>,
+    retry_policy: Option<RetryPolicy>,
+    rate_limit_pacing: bool,
+    redirect_policy: RedirectPolicy,
+    sensitive_headers: Vec<HeaderName>,
+    user_agent: Option<UserAgent>,
+    pool_max_idle_per_host: usize,
+    pool_idle_timeout: Duration,
+    warm_origins: Vec<String>,
+    accept_language: Option<HeaderValue>,
+    clock_skew_hook: Option<(Duration, Arc<dyn ClockSkewHook>)>,
+    learn_onion_routes: bool,
+    onion_route_store: Option<PathBuf>,
+    usage_caps: Option<UsageCaps>,
+    cookie_store: Option<Arc<dyn CookieStore>>,
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    decompression: bool,
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    decode_limits: DecodeLimits,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    compress_requests: Option<usize>,
+    #[cfg(feature = "chaos")]
+    fault_injection: FaultInjection,
+}
+
+impl ClientConfigBuilder {
+    /// Creates a new `ClientConfigBuilder`.
+    pub fn new() -> Self {
+        ClientConfigBuilder {
+            #[cfg(feature = "native-tls")]
+            tls_config: None,
+            #[cfg(feature = "tls-session-cache")]
+            tls_session_cache: None,
+            tor_config: None,
+            tor_network: None,
+            test_network: false,
+            tor_storage: None,
+            memory_only: false,
+            challenge_solvers: Vec::new(),
+            max_challenge_attempts: 3,
+            max_concurrent_requests: None,
+            adaptive_concurrency: None,
+            fast_exit_selection: None,
+            exit_country: None,
+            exclude_exit_nodes: Vec::new(),
+            exclude_countries: Vec::new(),
+            connect_hook: None,
+            connector: None,
+            wire_tap: None,
+            sandbox_hook: None,
+            credentials_provider: None,
+            read_buffer: ReadBuffer::default(),
+            http1: Http1Options::default(),
+            response_header_timeout: None,
+            upload_stall_timeout: None,
+            connect_timeout: None,
+            read_timeout: None,
+            total_request_timeout: None,
+            connection_driver: ConnectionDriver::default(),
+            host_aliases: HashMap::new(),
+            retry_budget: None,
+            retry_policy: None,
+            rate_limit_pacing: false,
+            redirect_policy: RedirectPolicy::None,
+            sensitive_headers: Vec::new(),
+            user_agent: None,
+            pool_max_idle_per_host: 8,
+            pool_idle_timeout: Duration::from_secs(30),
+            warm_origins: Vec::new(),
+            accept_language: None,
+            clock_skew_hook: None,
+            learn_onion_routes: false,
+            onion_route_store: None,
+            usage_caps: None,
+            cookie_store: None,
+            #[cfg(any(
+                feature = "gzip",
+                feature = "deflate",
+                feature = "brotli",
+                feature = "zstd"
+            ))]
+            decompression: true,
+            #[cfg(any(
+                feature = "gzip",
+                feature = "deflate",
+                feature = "brotli",
+                feature = "zstd"
+            ))]
+            decode_limits: DecodeLimits::default(),
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            compress_requests: None,
+            #[cfg(feature = "chaos")]
+            fault_injection: FaultInjection::default(),
+        }
+    }
+
+    /// Starts from settings that limit how much activity can be linked.
+    ///
+    /// Pins `Accept-Language` to Tor Browser's, moves to a new connection
+    /// and circuit every 20 requests, follows up to 10 redirects but never
+    /// from HTTPS to HTTP, and prefers onion services that origins announce.
+    /// No cookie store is set, so cookies are not sent.
+    pub fn privacy_strict() -> Self {
+        Self::new()
+            .tor_browser_accept_language()
+            .usage_caps(UsageCaps {
+                max_requests: Some(20),
+                max_bytes: None,
+                retire_circuit: true,
+            })
+            .follow_redirects(10)
+            .learn_onion_routes(true)
+            .response_header_timeout(Duration::from_secs(60))
+            .upload_stall_timeout(Duration::from_secs(60))
+    }
+
+    /// Starts from settings that favor latency.
+    ///
+    /// Sends clearnet requests over the fastest of several circuits, keeps
+    /// more idle connections, gives up on hung servers and stalled uploads
+    /// after 30 seconds, follows up to 10 redirects and retries transient
+    /// failures with the default [`RetryPolicy`], within the default
+    /// [`RetryBudget`].
+    pub fn fast() -> Self {
+        Self::new()
+            .fast_exit_selection(FastExitSelection::default())
+            .pool_max_idle_per_host(16)
+            .response_header_timeout(Duration::from_secs(30))
+            .upload_stall_timeout(Duration::from_secs(30))
+            .follow_redirects(10)
+            .retry_policy(RetryPolicy::default())
+            .retry_budget(RetryBudget::default())
+    }
+
+    /// Starts from settings for fetching many pages politely.
+    ///
+    /// Adapts per-host concurrency to observed latency, holds back requests
+    /// to origins whose announced rate limit is used up, pins
+    /// `Accept-Language` to Tor Browser's, follows up to 10 redirects, gives
+    /// up on hung servers after 60 seconds and retries transient failures
+    /// with the default [`RetryPolicy`], within the default [`RetryBudget`].
+    pub fn crawler() -> Self {
+        Self::new()
+            .adaptive_concurrency(AdaptiveConcurrency::default())
+            .rate_limit_pacing(true)
+            .tor_browser_accept_language()
+            .follow_redirects(10)
+            .response_header_timeout(Duration::from_secs(60))
+            .upload_stall_timeout(Duration::from_secs(60))
+            .retry_policy(RetryPolicy::default())
+            .retry_budget(RetryBudget::default())
+    }
+
+    /// Starts from settings for running against a private Tor network, such
+    /// as one set up with chutney or shadow for integration tests.
+    ///
+    /// Bootstraps from the directory servers of `network` only, allows relays
+    /// and origins on local addresses, builds circuits through relays that
+    /// share an address, and gives up on circuits, hung servers and stalled
+    /// uploads after 10 seconds so that failing tests fail fast.
+    pub fn test_network(network: TorNetwork) -> Self {
+        let mut builder = Self::new()
+            .tor_network(network)
+            .response_header_timeout(TEST_NETWORK_TIMEOUT)
+            .upload_stall_timeout(TEST_NETWORK_TIMEOUT);
+        builder.test_network = true;
+        builder
+    }
+
+    /// Sets the TLS configuration for the `ClientConfigBuilder`.
+    ///
+    /// native-tls does not expose session tickets, so every HTTPS connection
+    /// made with it performs a full handshake; see `tls_session_cache`.
+    #[cfg(feature = "native-tls")]
+    pub fn tls_config(mut self, tls_config: TlsConnector) -> Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    /// Resumes TLS sessions with origins, keeping up to `sessions` of them,
+    /// so that repeated HTTPS connections to a host save a round trip of the
+    /// handshake.
+    ///
+    /// HTTPS then runs over rustls, trusting the webpki root certificates,
+    /// instead of `tls_config`, which cannot be combined with it. A resumed
+    /// session lets the origin link the connection to the one the session
+    /// came from, across circuits and isolation tokens, so only enable this
+    /// where that is acceptable, such as for internal gateways.
+    ///
+    /// Sessions are kept in memory and lost with the client; they are not
+    /// persisted to disk. rustls offers no way to serialize a client session
+    /// or rebuild one from bytes, and native-tls does not expose sessions at
+    /// all, so a store on disk cannot be built on either.
+    #[cfg(feature = "tls-session-cache")]
+    pub fn tls_session_cache(mut self, sessions: usize) -> Self {
+        self.tls_session_cache = Some(sessions);
+        self
+    }
+
+    /// Sets the Tor configuration for the `ClientConfigBuilder`.
+    pub fn tor_config(mut self, tor_config: TorClientConfig) -> Self {
+        self.tor_config = Some(tor_config);
+        self
+    }
+
+    /// Bootstraps from the directory servers of `network` instead of the
+    /// public Tor network, for private test networks.
+    ///
+    /// Cannot be combined with `tor_config`; set the directory servers on
+    /// that configuration instead.
+    pub fn tor_network(mut self, network: TorNetwork) -> Self {
+        self.tor_network = Some(network);
+        self
+    }
+
+    /// Keeps arti's state in `state_dir` and its directory cache in
+    /// `cache_dir` instead of the default locations in the user's home.
+    ///
+    /// The cache is rewritten with every new consensus, while the state
+    /// changes rarely; on flash storage, the cache can go to a RAM disk at
+    /// the cost of downloading the directory again after a restart. Knowing
+    /// the directories also lets [`Client::storage_usage`](crate::Client::storage_usage)
+    /// report how much space they take.
+    ///
+    /// Cannot be combined with `tor_config`; set the directories on that
+    /// configuration instead.
+    pub fn tor_storage(
+        mut self,
+        state_dir: impl Into<PathBuf>,
+        cache_dir: impl Into<PathBuf>,
+    ) -> Self {
+        self.tor_storage = Some((state_dir.into(), cache_dir.into()));
+        self
+    }
+
+    /// Guarantees that nothing the client learns is written to disk, for
+    /// amnesic systems such as Tails.
+    ///
+    /// Arti's state and directory cache go to a fresh directory on the
+    /// RAM-backed `/dev/shm`, which is removed when the client is dropped;
+    /// building fails on systems without one. Settings that would persist
+    /// data, `tor_config`, `tor_storage`, `onion_route_store` and cookie
+    /// stores that write to disk such as `FileCookieStore`, are refused by
+    /// `build`.
+    /// Cookies and TLS sessions are only ever held in memory otherwise.
+    pub fn memory_only(mut self, enabled: bool) -> Self {
+        self.memory_only = enabled;
+        self
+    }
+
+    /// Adds a solver for proof-of-work style challenges returned by servers.
+    pub fn challenge_solver(mut self, solver: impl ChallengeSolver + 'static) -> Self {
+        self.challenge_solvers.push(Arc::new(solver));
+        self
+    }
+
+    /// Sets how many times a request may be retried after solving challenges.
+    pub fn max_challenge_attempts(mut self, attempts: usize) -> Self {
+        self.max_challenge_attempts = attempts;
+        self
+    }
+
+    /// Limits the number of requests in flight at once.
+    ///
+    /// When the limit is reached, waiting requests are dispatched in
+    /// [`Priority`] order.
+    pub fn max_concurrent_requests(mut self, max: usize) -> Self {
+        self.max_concurrent_requests = Some(max);
+        self
+    }
+
+    /// Enables adaptive per-host concurrency limits.
+    pub fn adaptive_concurrency(mut self, settings: AdaptiveConcurrency) -> Self {
+        self.adaptive_concurrency = Some(settings);
+        self
+    }
+
+    /// Prefers historically fast exit circuits for clearnet requests.
+    ///
+    /// This trades some unlinkability for speed; see [`FastExitSelection`].
+    /// It picks the circuit of every clearnet request itself, so
+    /// [`build`](Self::build) refuses it together with
+    /// [`usage_caps`](Self::usage_caps).
+    pub fn fast_exit_selection(mut self, settings: FastExitSelection) -> Self {
+        self.fast_exit_selection = Some(settings);
+        self
+    }
+
+    /// Exits clearnet requests in the country with the two-letter code
+    /// `country`, such as `"de"`.
+    ///
+    /// Requests can pick another country with
+    /// [`RequestBuilder::exit_country`](crate::RequestBuilder::exit_country).
+    /// Fewer exits to choose from make circuits slower and easier to link.
+    #[cfg(feature = "geoip")]
+    pub fn exit_country(mut self, country: impl Into<String>) -> Self {
+        self.exit_country = Some(country.into());
+        self
+    }
+
+    /// Never exits clearnet requests from the relays with the given
+    /// fingerprints, in hex with or without a leading `$`.
+    ///
+    /// arti cannot leave relays out of its paths, so each circuit is checked
+    /// before a stream is begun on it, and one that exits from an excluded
+    /// relay is retired without having carried anything. Requests fail once
+    /// five circuits in a row were excluded. Targets are checked as arti
+    /// checks them, so local addresses are still refused outside test
+    /// networks. Streams from a custom [`Connector`] are not checked.
+    #[cfg(feature = "exclude-exits")]
+    pub fn exclude_exit_nodes<I, S>(mut self, fingerprints: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude_exit_nodes
+            .extend(fingerprints.into_iter().map(Into::into));
+        self
+    }
+
+    /// Never exits clearnet requests in the countries with the given
+    /// two-letter codes, such as `"ru"`.
+    ///
+    /// Exits are placed by the addresses they listen on, and are checked
+    /// the way [`exclude_exit_nodes`](Self::exclude_exit_nodes) are.
+    #[cfg(all(feature = "geoip", feature = "exclude-exits"))]
+    pub fn exclude_countries<I, S>(mut self, countries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude_countries
+            .extend(countries.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets a hook that can veto connections before they are opened.
+    pub fn connect_hook(mut self, hook: impl ConnectHook + 'static) -> Self {
+        self.connect_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Opens streams with `connector` instead of Tor, for tests.
+    ///
+    /// The client does not bootstrap Tor, so it can be built without network
+    /// access; give it a `tor_config` with temporary directories to keep
+    /// arti's state out of the user's home.
+    pub fn connector(mut self, connector: impl Connector + 'static) -> Self {
+        self.connector = Some(Arc::new(connector));
+        self
+    }
+
+    /// Shows the plaintext bytes of every connection to `tap`, above TLS,
+    /// to debug protocol problems without an intercepting proxy.
+    ///
+    /// See [`WireTap`] for what the tap sees; [`WireTee`](crate::WireTee)
+    /// writes the bytes to files.
+    pub fn wire_tap(mut self, tap: impl WireTap + 'static) -> Self {
+        self.wire_tap = Some(Arc::new(tap));
+        self
+    }
+
+    /// Calls `hook` with the client's
+    /// [`sandbox_requirements`](ClientConfig::sandbox_requirements) once
+    /// the client has been built, to install a seccomp or landlock sandbox.
+    pub fn sandbox_hook(mut self, hook: impl SandboxHook + 'static) -> Self {
+        self.sandbox_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Sets the provider used to answer Basic, Bearer and Digest challenges.
+    ///
+    /// Basic and Bearer credentials are only sent over HTTPS or to onion
+    /// services, where exit relays cannot read them.
+    pub fn credentials_provider(mut self, provider: impl CredentialsProvider + 'static) -> Self {
+        self.credentials_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Sets how each connection's read buffer is sized.
+    pub fn read_buffer(mut self, read_buffer: ReadBuffer) -> Self {
+        self.read_buffer = read_buffer;
+        self
+    }
+
+    /// Sets the HTTP/1 connection options.
+    pub fn http1(mut self, http1: Http1Options) -> Self {
+        self.http1 = http1;
+        self
+    }
+
+    /// Sets how long to wait for response headers after the request body has
+    /// been sent.
+    ///
+    /// This detects hung servers quickly without limiting how long uploads or
+    /// response bodies may take.
+    pub fn response_header_timeout(mut self, timeout: Duration) -> Self {
+        self.response_header_timeout = Some(timeout);
+        self
+    }
+
+    /// Fails requests with [`Error::Stalled`](crate::Error::Stalled) once
+    /// no data could be written to their connection for `timeout`.
+    ///
+    /// A wedged circuit stops accepting data without closing, which would
+    /// otherwise leave an upload hanging forever. Slow but moving uploads and
+    /// servers slow to answer are not affected.
+    pub fn upload_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.upload_stall_timeout = Some(timeout);
+        self
+    }
+
+    /// Fails requests with [`Error::Timeout`](crate::Error::Timeout) when
+    /// opening a connection, from building the circuit to the end of the
+    /// TLS handshake, takes longer than `timeout`.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Fails reading a response body with
+    /// [`Error::Timeout`](crate::Error::Timeout) once no data has arrived
+    /// for `timeout`.
+    ///
+    /// A stalled stream is caught while large bodies that keep arriving may
+    /// take as long as they need.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Fails requests with [`Error::Timeout`](crate::Error::Timeout) unless
+    /// they complete within `timeout`, redirects, retries and reading the
+    /// response body included.
+    pub fn total_request_timeout(mut self, timeout: Duration) -> Self {
+        self.total_request_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how connections are polled.
+    pub fn connection_driver(mut self, connection_driver: ConnectionDriver) -> Self {
+        self.connection_driver = connection_driver;
+        self
+    }
+
+    /// Sends requests for `host` to `alias` instead, such as a service's
+    /// onion address.
+    ///
+    /// The request URI is rewritten before anything else happens, so `Host`,
+    /// TLS verification and connect hooks all see the alias.
+    pub fn host_alias(mut self, host: impl Into<String>, alias: impl Into<String>) -> Self {
+        self.host_aliases
+            .insert(host.into().to_ascii_lowercase(), alias.into());
+        self
+    }
+
+    /// Limits automatic retries, such as those after solved challenges or
+    /// over fresh circuits, to a share of recent requests.
+    pub fn retry_budget(mut self, budget: RetryBudget) -> Self {
+        self.retry_budget = Some(budget);
+        self
+    }
+
+    /// Retries idempotent requests that failed for a transient reason, such
+    /// as a collapsed circuit or a `502` from a flaky onion service, with
+    /// exponential backoff.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Holds back requests to an origin whose [`RateLimit`](crate::RateLimit)
+    /// headers report no remaining requests, until the window resets.
+    pub fn rate_limit_pacing(mut self, enabled: bool) -> Self {
+        self.rate_limit_pacing = enabled;
+        self
+    }
+
+    /// Sets which redirects are followed; by default none are.
+    ///
+    /// `303 See Other`, and `301`/`302` in answer to `POST`, are followed
+    /// with a bodiless `GET`; `307` and `308` repeat the original request
+    /// with its method and body. Responses carry the
+    /// [`RedirectChain`](crate::RedirectChain) that led to them.
+    ///
+    /// `Authorization`, `Cookie`, a custom `Host` and any
+    /// [sensitive headers](Self::sensitive_header) are dropped when a
+    /// redirect leaves the original origin.
+    pub fn redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Follows up to `max_hops` redirects per request, failing on redirects
+    /// from HTTPS to HTTP.
+    ///
+    /// Shorthand for a [`RedirectPolicy::Follow`] without downgrades.
+    pub fn follow_redirects(self, max_hops: usize) -> Self {
+        self.redirect_policy(RedirectPolicy::Follow {
+            max_hops,
+            allow_downgrade: false,
+        })
+    }
+
+    /// Marks a header, such as an API key, to be dropped from requests
+    /// redirected to another origin.
+    pub fn sensitive_header(mut self, name: HeaderName) -> Self {
+        self.sensitive_headers.push(name);
+        self
+    }
+
+    /// Sets the `User-Agent` header of requests that do not set their own.
+    ///
+    /// With [`UserAgent::PerIdentity`] the header changes only when the
+    /// circuit does, so both identity layers rotate together.
+    pub fn user_agent(mut self, user_agent: UserAgent) -> Self {
+        self.user_agent = Some(user_agent);
+        self
+    }
+
+    /// Keeps up to `max` idle keep-alive connections per host and circuit
+    /// for later requests; `0` opens a fresh stream for every request.
+    ///
+    /// Connections are only reused by requests with the same isolation, and
+    /// never with [`ConnectionDriver::Inline`] or a
+    /// [`ProxyHeader`](crate::ProxyHeader). Defaults to 8.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
+
+    /// Sets how long an idle connection is kept for reuse. Defaults to 30
+    /// seconds, which is below the keep-alive timeout of common servers.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Keeps a connection to `origin`, such as `http://example.onion`, open
+    /// and ready, so the first request to it does not wait for a circuit
+    /// and stream to be built.
+    ///
+    /// The client connects when it is created and then sends a `HEAD /`
+    /// request over the idle connection every half
+    /// [`pool_idle_timeout`](Self::pool_idle_timeout), reconnecting if the
+    /// server closed it. The traffic is visible to the server and adds up on
+    /// metered circuits, so only declare hosts that are used interactively.
+    /// Requires connection pooling.
+    pub fn keep_warm(mut self, origin: impl Into<String>) -> Self {
+        self.warm_origins.push(origin.into());
+        self
+    }
+
+    /// Sends `Accept-Language: <value>` with every request, replacing any
+    /// value set on the request, so the header cannot reveal a locale.
+    pub fn accept_language(mut self, value: HeaderValue) -> Self {
+        self.accept_language = Some(value);
+        self
+    }
+
+    /// Pins `Accept-Language` to Tor Browser's `en-US,en;q=0.5`, which the
+    /// largest group of Tor users sends.
+    pub fn tor_browser_accept_language(self) -> Self {
+        self.accept_language(HeaderValue::from_static(TOR_BROWSER_ACCEPT_LANGUAGE))
+    }
+
+    /// Reports responses whose `Date` header is more than `threshold` away
+    /// from the local clock to `hook`.
+    ///
+    /// `Date` has a resolution of one second and is set before the response
+    /// crosses the Tor network, so pick a threshold of a few minutes.
+    pub fn clock_skew_hook(
+        mut self,
+        threshold: Duration,
+        hook: impl ClockSkewHook + 'static,
+    ) -> Self {
+        self.clock_skew_hook = Some((threshold, Arc::new(hook)));
+        self
+    }
+
+    /// Learns onion services announced by HTTPS origins and uses them for
+    /// later requests until they expire.
+    ///
+    /// An `Onion-Location` header moves requests for the origin to the
+    /// announced onion origin, as Tor Browser offers to. An `Alt-Svc` entry
+    /// naming an onion service (`h2` or `http/1.1`) routes connections
+    /// through it, while the URI, `Host` and TLS name stay the origin's.
+    /// Configured [host aliases](Self::host_alias) take precedence.
+    pub fn learn_onion_routes(mut self, enabled: bool) -> Self {
+        self.learn_onion_routes = enabled;
+        self
+    }
+
+    /// Persists learned onion routes to `path`, so they survive restarts,
+    /// and enables [learning](Self::learn_onion_routes) them.
+    pub fn onion_route_store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.learn_onion_routes = true;
+        self.onion_route_store = Some(path.into());
+        self
+    }
+
+    /// Retires connections once they have carried `caps.max_requests`
+    /// requests or `caps.max_bytes` bytes, and with `caps.retire_circuit`
+    /// moves later requests to a new circuit as well.
+    ///
+    /// This bounds how much activity can be linked to one connection or
+    /// path. Connections are only retired between requests, so a large
+    /// response can exceed the byte cap.
+    pub fn usage_caps(mut self, caps: UsageCaps) -> Self {
+        self.usage_caps = Some(caps);
+        self
+    }
+
+    /// Sends and stores cookies with `store`, e.g. a [`CookieJar`](crate::CookieJar) or a
+    /// [`FileCookieStore`](crate::FileCookieStore) that survives restarts.
+    ///
+    /// Cookies from the store are added to any `Cookie` header a request
+    /// sets itself. Pass an `Arc` to keep access to the store.
+    pub fn cookie_store(mut self, store: impl CookieStore + 'static) -> Self {
+        self.cookie_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Sets whether responses are decompressed transparently; enabled by
+    /// default.
+    ///
+    /// Requests without their own `Accept-Encoding` or `Range` header ask
+    /// for the codings enabled by the `gzip`, `deflate`, `brotli` and `zstd`
+    /// features, and compressed responses are decoded as they are read,
+    /// without `Content-Encoding` and `Content-Length`. Requests that set
+    /// `Accept-Encoding` themselves get responses as they were sent.
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    pub fn decompression(mut self, enabled: bool) -> Self {
+        self.decompression = enabled;
+        self
+    }
+
+    /// Sets how far decompressed responses may expand before reading them
+    /// fails with a [`DecompressionBomb`](crate::DecompressionBomb).
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    pub fn decode_limits(mut self, limits: DecodeLimits) -> Self {
+        self.decode_limits = limits;
+        self
+    }
+
+    /// Compresses request bodies of at least `min_size` bytes with zstd or
+    /// gzip once their origin has listed the coding in an `Accept-Encoding`
+    /// response header (RFC 7694), setting `Content-Encoding`.
+    ///
+    /// Only bodies held in memory are compressed, never ones that already
+    /// have a `Content-Encoding`. If the origin answers a compressed request
+    /// with `415 Unsupported Media Type`, it is sent again uncompressed and
+    /// later requests to the origin are not compressed until it lists a
+    /// coding again.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub fn compress_requests(mut self, min_size: usize) -> Self {
+        self.compress_requests = Some(min_size);
+        self
+    }
+
+    /// Injects latency, resets and truncation into every connection.
+    #[cfg(feature = "chaos")]
+    pub fn fault_injection(mut self, faults: FaultInjection) -> Self {
+        self.fault_injection = faults;
+        self
+    }
+
+    /// Builds the `ClientConfig` from the `ClientConfigBuilder`.
+    pub fn build(self) -> Result<ClientConfig> {
+        match self.read_buffer {
+            ReadBuffer::Bounded(max) if max < MIN_MAX_READ_BUFFER => {
+                return Err(Error::Config(format!(
+                    "read buffer limit must be at least {MIN_MAX_READ_BUFFER} bytes"
+                )));
+            }
+            ReadBuffer::Exact(0) => {
+                return Err(Error::Config(
+                    "exact read buffer size must not be zero".into(),
+                ));
+            }
+            _ => {}
+        }
+        for alias in self.host_aliases.values() {
+            let valid = match alias.parse::<Authority>() {
+                Ok(authority) => authority.port().is_none() && !alias.contains('@'),
+                Err(_) => false,
+            };
+            if !valid {
+                return Err(Error::Config(format!("invalid host alias `{alias}`")));
+            }
+        }
+        let exit_country = match &self.exit_country {
+            Some(code) => Some(
+                ExitCountry::new(code)
+                    .ok_or_else(|| Error::Config(format!("invalid exit country `{code}`")))?,
+            ),
+            None => None,
+        };
+        let mut exclude_exit_nodes = Vec::with_capacity(self.exclude_exit_nodes.len());
+        for fingerprint in &self.exclude_exit_nodes {
+            let hex = fingerprint.trim().trim_start_matches('$');
+            if hex.len() != 40 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(Error::Config(format!(
+                    "invalid relay fingerprint `{fingerprint}`"
+                )));
+            }
+            exclude_exit_nodes.push(hex.to_ascii_uppercase());
+        }
+        let mut exclude_countries = Vec::with_capacity(self.exclude_countries.len());
+        for code in &self.exclude_countries {
+            exclude_countries.push(
+                ExitCountry::new(code)
+                    .ok_or_else(|| Error::Config(format!("invalid country `{code}`")))?,
+            );
+        }
+        let mut warm_origins = Vec::with_capacity(self.warm_origins.len());
+        for origin in &self.warm_origins {
+            let uri = origin
+                .parse::<Uri>()
+                .ok()
+                .filter(|uri| {
+                    uri.host().is_some()
+                        && matches!(uri.scheme_str(), Some("http" | "https"))
+                        && uri.path_and_query().is_none_or(|path| path == "/")
+                })
+                .ok_or_else(|| Error::Config(format!("invalid origin to keep warm `{origin}`")))?;
+            let mut parts = uri.into_parts();
+            parts.path_and_query = Some(PathAndQuery::from_static("/"));
+            warm_origins.push(Uri::from_parts(parts).expect("origin with a path is a valid URI"));
+        }
+        if !warm_origins.is_empty()
+            && (self.pool_max_idle_per_host == 0
+                || self.connection_driver == ConnectionDriver::Inline)
+        {
+            return Err(Error::Config(
+                "`keep_warm` requires connection pooling with spawned connection drivers".into(),
+            ));
+        }
+        if self.fast_exit_selection.is_some() && self.usage_caps.is_some() {
+            // The selector would keep requests on its circuits past the caps.
+            return Err(Error::Config(
+                "`fast_exit_selection` cannot be combined with `usage_caps`".into(),
+            ));
+        }
+        if self
+            .retry_policy
+            .as_ref()
+            .is_some_and(|policy| policy.max_attempts == 0)
+        {
+            return Err(Error::Config(
+                "retry policy needs at least one attempt".into(),
+            ));
+        }
+        if let Some(UserAgent::PerIdentity(values) | UserAgent::PerRequest(values)) =
+            &self.user_agent
+        {
+            if values.is_empty() {
+                return Err(Error::Config("user agent list must not be empty".into()));
+            }
+        }
+        #[cfg(feature = "tls-session-cache")]
+        let tls_session_cache = match self.tls_session_cache {
+            Some(_) if self.tls_config.is_some() => {
+                return Err(Error::Config(
+                    "`tls_session_cache` cannot be combined with `tls_config`".into(),
+                ));
+            }
+            Some(0) => {
+                return Err(Error::Config(
+                    "TLS session cache needs room for at least one session".into(),
+                ));
+            }
+            Some(sessions) => Some(tls_session_connector(sessions)?),
+            None => None,
+        };
+        let mut memory_dir = None;
+        let tor_storage = if self.memory_only {
+            if self.tor_config.is_some() || self.tor_storage.is_some() {
+                return Err(Error::Config(
+                    "`memory_only` cannot be combined with `tor_config` or `tor_storage`".into(),
+                ));
+            }
+            if self.onion_route_store.is_some() {
+                return Err(Error::Config(
+                    "`memory_only` cannot be combined with `onion_route_store`".into(),
+                ));
+            }
+            if self
+                .cookie_store
+                .as_ref()
+                .is_some_and(|store| store.is_persistent())
+            {
+                return Err(Error::Config(
+                    "`memory_only` requires a cookie store that keeps cookies in memory".into(),
+                ));
+            }
+            let dir = create_memory_dir()?;
+            let storage = (dir.0.join("state"), dir.0.join("cache"));
+            memory_dir = Some(dir);
+            Some(storage)
+        } else {
+            self.tor_storage
+        };
+        let tor_config = match (self.tor_config, &self.tor_network) {
+            (Some(_), Some(_)) => {
+                return Err(Error::Config(
+                    "`tor_network` cannot be combined with `tor_config`".into(),
+                ));
+            }
+            (Some(_), None) if tor_storage.is_some() => {
+                return Err(Error::Config(
+                    "`tor_storage` cannot be combined with `tor_config`".into(),
+                ));
+            }
+            (Some(tor_config), None) => tor_config,
+            (None, network) => {
+                default_tor_config(network.as_ref(), tor_storage.as_ref(), self.test_network)?
+            }
+        };
+        let (tor_state_dir, tor_cache_dir) = tor_storage.unzip();
+
+        Ok(ClientConfig {
+            #[cfg(feature = "native-tls")]
+            tls_config: self.tls_config.unwrap_or_else(|| {
+                TlsConnector::builder()
+                    .build()
+                    .expect("Failed to create default TlsConnector")
+            }),
+            #[cfg(feature = "tls-session-cache")]
+            tls_session_cache,
+            tor_config,
+            tor_state_dir,
+            tor_cache_dir,
+            memory_only: self.memory_only,
+            challenge_solvers: self.challenge_solvers,
+            max_challenge_attempts: self.max_challenge_attempts,
+            max_concurrent_requests: self.max_concurrent_requests,
+            adaptive_concurrency: self.adaptive_concurrency,
+            fast_exit_selection: self.fast_exit_selection,
+            exit_country,
+            exclude_exit_nodes,
+            exclude_countries,
+            connect_hook: self.connect_hook,
+            connector: self.connector,
+            wire_tap: self.wire_tap,
+            sandbox_hook: self.sandbox_hook,
+            credentials_provider: self.credentials_provider,
+            read_buffer: self.read_buffer,
+            http1: self.http1,
+            response_header_timeout: self.response_header_timeout,
+            upload_stall_timeout: self.upload_stall_timeout,
+            connect_timeout: self.connect_timeout,
+            read_timeout: self.read_timeout,
+            total_request_timeout: self.total_request_timeout,
+            connection_driver: self.connection_driver,
+            host_aliases: self.host_aliases,
+            retry_budget: self.retry_budget,
+            retry_policy: self.retry_policy,
+            rate_limit_pacing: self.rate_limit_pacing,
+            redirect_policy: self.redirect_policy,
+            sensitive_headers: self.sensitive_headers,
+            user_agent: self.user_agent,
+            pool_max_idle_per_host: self.pool_max_idle_per_host,
+            pool_idle_timeout: self.pool_idle_timeout,
+            warm_origins,
+            accept_language: self.accept_language,
+            clock_skew_hook: self.clock_skew_hook,
+            learn_onion_routes: self.learn_onion_routes,
+            onion_route_store: self.onion_route_store,
+            usage_caps: self.usage_caps,
+            cookie_store: self.cookie_store,
+            #[cfg(any(
+                feature = "gzip",
+                feature = "deflate",
+                feature = "brotli",
+                feature = "zstd"
+            ))]
+            decompression: self.decompression,
+            #[cfg(any(
+                feature = "gzip",
+                feature = "deflate",
+                feature = "brotli",
+                feature = "zstd"
+            ))]
+            decode_limits: self.decode_limits,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            compress_requests: self.compress_requests,
+            #[cfg(feature = "chaos")]
+            fault_injection: self.fault_injection,
+            #[cfg(feature = "exclude-exits")]
+            allow_local_addrs: self.test_network,
+            _memory_dir: memory_dir,
+        })
+    }
+}
+
+/// Builds a rustls connector that resumes up to `sessions` TLS sessions.
+#[cfg(feature = "tls-session-cache")]
+fn tls_session_connector(sessions: usize) -> Result<tokio_rustls::TlsConnector> {
+    use tokio_rustls::rustls::{self, client::Resumption, RootCertStore};
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let mut config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::Config(e.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.resumption = Resumption::in_memory_sessions(sessions);
+    Ok(tokio_rustls::TlsConnector::from(Arc::new(config)))
+}
+
+/// A memory-only client's directory on the RAM-backed file system, removed
+/// when dropped.
+struct MemoryDir(PathBuf);
+
+impl Drop for MemoryDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!(error = %e, dir = %self.0.display(), "failed to remove Tor state");
+            }
+        }
+    }
+}
+
+/// Picks a fresh directory on a RAM-backed file system for a memory-only
+/// client's Tor state and cache.
+fn create_memory_dir() -> Result<MemoryDir> {
+    let shm = Path::new(MEMORY_ROOT);
+    if !cfg!(target_os = "linux") || !shm.is_dir() {
+        return Err(Error::Config(format!(
+            "`memory_only` requires a RAM-backed {MEMORY_ROOT}"
+        )));
+    }
+    Ok(MemoryDir(
+        shm.join(format!("hypertor-{:032x}", rand::random::<u128>())),
+    ))
+}
+
+/// The Tor configuration used when none is given: the public network, or
+/// `network` if set, with onion addresses allowed, keeping state in
+/// `storage` if set. `testing` relaxes the rules that only make sense on the
+/// public network.
+fn default_tor_config(
+    network: Option<&TorNetwork>,
+    storage: Option<&(PathBuf, PathBuf)>,
+    testing: bool,
+) -> Result<TorClientConfig> {
+    let mut cfg_builder = match storage {
+        Some((state_dir, cache_dir)) => {
+            TorClientConfigBuilder::from_directories(state_dir, cache_dir)
+        }
+        None => TorClientConfig::builder(),
+    };
+    cfg_builder.address_filter().allow_onion_addrs(true);
+    if testing {
+        cfg_builder.address_filter().allow_local_addrs(true);
+        // Test networks run every relay on one host, often on loopback, so
+        // relays must not count as related for sharing a subnet.
+        cfg_builder
+            .path_rules()
+            .ipv4_subnet_family_prefix(33)
+            .ipv6_subnet_family_prefix(129);
+        cfg_builder
+            .circuit_timing()
+            .request_timeout(TEST_NETWORK_TIMEOUT);
+    }
+    let Some(network) = network else {
+        return Ok(cfg_builder
+            .build()
+            .expect("Failed to create default TorClientConfig"));
+    };
+
+    if network.authorities.is_empty() || network.fallback_directories.is_empty() {
+        return Err(Error::Config(
+            "Tor network needs at least one authority and one fallback directory".into(),
+        ));
+    }
+    let mut authorities = Vec::with_capacity(network.authorities.len());
+    for authority in &network.authorities {
+        let v3ident = RsaIdentity::from_hex(&authority.v3ident).ok_or_else(|| {
+            Error::Config(format!(
+                "invalid v3ident `{}` for authority `{}`",
+                authority.v3ident, authority.name
+            ))
+        })?;
+        let mut builder = DirAuthority::builder();
+        builder.name(authority.name.as_str()).v3ident(v3ident);
+        authorities.push(builder);
+    }
+    let mut fallbacks = Vec::with_capacity(network.fallback_directories.len());
+    for fallback in &network.fallback_directories {
+        let rsa_identity = RsaIdentity::from_hex(&fallback.rsa_identity).ok_or_else(|| {
+            Error::Config(format!(
+                "invalid RSA identity `{}` for fallback directory",
+                fallback.rsa_identity
+            ))
+        })?;
+        let ed_identity = Ed25519Identity::from_base64(&fallback.ed_identity).ok_or_else(|| {
+            Error::Config(format!(
+                "invalid Ed25519 identity `{}` for fallback directory",
+                fallback.ed_identity
+            ))
+        })?;
+        if fallback.orports.is_empty() {
+            return Err(Error::Config(format!(
+                "fallback directory `{}` has no ORPort",
+                fallback.rsa_identity
+            )));
+        }
+        let mut builder = FallbackDir::builder();
+        builder.rsa_identity(rsa_identity).ed_identity(ed_identity);
+        builder.orports().extend(fallback.orports.iter().copied());
+        fallbacks.push(builder);
+    }
+    cfg_builder.tor_network().set_authorities(authorities);
+    cfg_builder.tor_network().set_fallback_caches(fallbacks);
+    cfg_builder
+        .build()
+        .map_err(|e| Error::Config(format!("invalid Tor network: {e}")))
+}
+
+impl ClientConfig {
+    /// Returns the files, directories and socket families a client built
+    /// from this configuration needs once it has been constructed.
+    ///
+    /// Available before the client is built, so that a sandbox can be
+    /// prepared up front; [`ClientConfigBuilder::sandbox_hook`] receives the
+    /// same.
+    pub fn sandbox_requirements(&self) -> SandboxRequirements {
+        let mut paths = Vec::new();
+        for dir in [&self.tor_state_dir, &self.tor_cache_dir]
+            .into_iter()
+            .flatten()
+        {
+            paths.push(SandboxPath {
+                path: dir.clone(),
+                writable: true,
+            });
+        }
+        // Both files are replaced through a temporary file next to them.
+        let stores = [
+            self.onion_route_store.as_deref(),
+            self.cookie_store.as_ref().and_then(|store| store.path()),
+        ];
+        for file in stores.into_iter().flatten() {
+            let dir = match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            paths.push(SandboxPath {
+                path: dir.to_path_buf(),
+                writable: true,
+            });
+        }
+        #[cfg(all(feature = "native-tls", unix, not(target_os = "macos")))]
+        paths.extend(trust_store_paths().into_iter().map(|path| SandboxPath {
+            path,
+            writable: false,
+        }));
+
+        // A custom connector opens the client's streams itself.
+        let socket_families = match self.connector {
+            Some(_) => Vec::new(),
+            None => vec![SocketFamily::Ipv4, SocketFamily::Ipv6],
+        };
+        SandboxRequirements {
+            paths,
+            socket_families,
+            unlisted_tor_storage: self.connector.is_none() && self.tor_state_dir.is_none(),
+        }
+    }
+}
+
+/// Where OpenSSL looks for trusted certificates while verifying servers:
+/// the locations named by `SSL_CERT_FILE` and `SSL_CERT_DIR`, or the usual
+/// ones that exist.
+#[cfg(all(feature = "native-tls", unix, not(target_os = "macos")))]
+fn trust_store_paths() -> Vec<PathBuf> {
+    let configured: Vec<PathBuf> = ["SSL_CERT_FILE", "SSL_CERT_DIR"]
+        .into_iter()
+        .filter_map(std::env::var_os)
+        .map(PathBuf::from)
+        .collect();
+    if !configured.is_empty() {
+        return configured;
+    }
+    TRUST_STORE_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Starts from an existing arti configuration, such as one loaded from
+/// arti's TOML files, leaving the hypertor settings at their defaults.
+impl From<TorClientConfig> for ClientConfigBuilder {
+    fn from(tor_config: TorClientConfig) -> Self {
+        Self::new().tor_config(tor_config)
+    }
+}
+
+/// Builds an arti configuration and starts from it, allowing connections
+/// to onion addresses as the default configuration does.
+impl TryFrom<TorClientConfigBuilder> for ClientConfigBuilder {
+    type Error = Error;
+
+    fn try_from(mut tor_config: TorClientConfigBuilder) -> Result<Self> {
+        tor_config.address_filter().allow_onion_addrs(true);
+        let tor_config = tor_config
+            .build()
+            .map_err(|e| Error::Config(format!("invalid Tor configuration: {e}")))?;
+        Ok(Self::from(tor_config))
+    }
+}
+
+/// Wraps an existing arti configuration with the default hypertor settings.
+impl TryFrom<TorClientConfig> for ClientConfig {
+    type Error = Error;
+
+    fn try_from(tor_config: TorClientConfig) -> Result<Self> {
+        ClientConfigBuilder::from(tor_config).build()
+    }
+}
+
+impl std::fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("ClientConfig");
+        #[cfg(feature = "native-tls")]
+        debug.field("tls_config", &REDACTED);
+        #[cfg(feature = "tls-session-cache")]
+        debug.field("tls_session_cache", &self.tls_session_cache.is_some());
+        debug
+            .field("tor_config", &REDACTED)
+            .field("tor_state_dir", &self.tor_state_dir)
+            .field("tor_cache_dir", &self.tor_cache_dir)
+            .field("memory_only", &self.memory_only)
+            .field("challenge_solvers", &self.challenge_solvers.len())
+            .field("max_challenge_attempts", &self.max_challenge_attempts)
+            .field("max_concurrent_requests", &self.max_concurrent_requests)
+            .field("adaptive_concurrency", &self.adaptive_concurrency)
+            .field("fast_exit_selection", &self.fast_exit_selection)
+            .field("exit_country", &self.exit_country)
+            .field("exclude_exit_nodes", &self.exclude_exit_nodes)
+            .field("exclude_countries", &self.exclude_countries)
+            .field("connect_hook", &self.connect_hook.is_some())
+            .field("connector", &self.connector.is_some())
+            .field("wire_tap", &self.wire_tap.is_some())
+            .field("sandbox_hook", &self.sandbox_hook.is_some())
+            .field(
+                "credentials_provider",
+                &self.credentials_provider.as_ref().map(|_| REDACTED),
+            )
+            .field("read_buffer", &self.read_buffer)
+            .field("http1", &self.http1)
+            .field("response_header_timeout", &self.response_header_timeout)
+            .field("upload_stall_timeout", &self.upload_stall_timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("read_timeout", &self.read_timeout)
+            .field("total_request_timeout", &self.total_request_timeout)
+            .field("connection_driver", &self.connection_driver)
+            .field("host_aliases", &self.host_aliases)
+            .field("retry_budget", &self.retry_budget)
+            .field("retry_policy", &self.retry_policy)
+            .field("rate_limit_pacing", &self.rate_limit_pacing)
+            .field("redirect_policy", &self.redirect_policy)
+            .field("sensitive_headers", &self.sensitive_headers)
+            .field("user_agent", &self.user_agent)
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("pool_idle_timeout", &self.pool_idle_timeout)
+            .field("warm_origins", &self.warm_origins)
+            .field("accept_language", &self.accept_language)
+            .field(
+                "clock_skew_hook",
+                &self
+                    .clock_skew_hook
+                    .as_ref()
+                    .map(|(threshold, _)| threshold),
+            )
+            .field("learn_onion_routes", &self.learn_onion_routes)
+            .field("onion_route_store", &self.onion_route_store)
+            .field("usage_caps", &self.usage_caps)
+            .field(
+                "cookie_store",
+                &self.cookie_store.as_ref().map(|_| REDACTED),
+            );
+        #[cfg(any(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "brotli",
+            feature = "zstd"
+        ))]
+        debug
+            .field("decompression", &self.decompression)
+            .field("decode_limits", &self.decode_limits);
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        debug.field("compress_requests", &self.compress_requests);
+        #[cfg(feature = "chaos")]
+        debug.field("fault_injection", &self.fault_injection);
+        debug.finish()
+    }
+}
+
+impl std::fmt::Debug for ClientConfigBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("ClientConfigBuilder");
+        #[cfg(feature = "native-tls")]
+        debug.field("tls_config", &self.tls_config.as_ref().map(|_| REDACTED));
+        #[cfg(feature = "tls-session-cache")]
+        debug.field("tls_session_cache", &self.tls_session_cache);
+        debug
+            .field("tor_config", &self.tor_config.as_ref().map(|_| REDACTED))
+            .field("tor_network", &self.tor_network)
+            .field("test_network", &self.test_network)
+            .field("tor_storage", &self.tor_storage)
+            .field("memory_only", &self.memory_only)
+            .field("challenge_solvers", &self.challenge_solvers.len())
+            .field("max_challenge_attempts", &self.max_challenge_attempts)
+            .field("max_concurrent_requests", &self.max_concurrent_requests)
+            .field("adaptive_concurrency", &self.adaptive_concurrency)
+            .field("fast_exit_selection", &self.fast_exit_selection)
+            .field("exit_country", &self.exit_country)
+            .field("exclude_exit_nodes", &self.exclude_exit_nodes)
+            .field("exclude_countries", &self.exclude_countries)
+            .field("connect_hook", &self.connect_hook.is_some())
+            .field("connector", &self.connector.is_some())
+            .field("wire_tap", &self.wire_tap.is_some())
+            .field("sandbox_hook", &self.sandbox_hook.is_some())
+            .field(
+                "credentials_provider",
+                &self.credentials_provider.as_ref().map(|_| REDACTED),
+            )
+            .field("read_buffer", &self.read_buffer)
+            .field("http1", &self.http1)
+            .field("response_header_timeout", &self.response_header_timeout)
+            .field("upload_stall_timeout", &self.upload_stall_timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("read_timeout", &self.read_timeout)
+            .field("total_request_timeout", &self.total_request_timeout)
+            .field("connection_driver", &self.connection_driver)
+            .field("host_aliases", &self.host_aliases)
+            .field("retry_budget", &self.retry_budget)
+            .field("retry_policy", &self.retry_policy)
+            .field("rate_limit_pacing", &self.rate_limit_pacing)
+            .field("redirect_policy", &self.redirect_policy)
+            .field("sensitive_headers", &self.sensitive_headers)
+            .field("user_agent", &self.user_agent)
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("pool_idle_timeout", &self.pool_idle_timeout)
+            .field("warm_origins", &self.warm_origins)
+            .field("accept_language", &self.accept_language)
+            .field(
+                "clock_skew_hook",
+                &self
+                    .clock_skew_hook
+                    .as_ref()
+                    .map(|(threshold, _)| threshold),
+            )
+            .field("learn_onion_routes", &self.learn_onion_routes)
+            .field("onion_route_store", &self.onion_route_store)
+            .field("usage_caps", &self.usage_caps)
+            .field(
+                "cookie_store",
+                &self.cookie_store.as_ref().map(|_| REDACTED),
+            );
+        #[cfg(any(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "brotli",
+            feature = "zstd"
+        ))]
+        debug
+            .field("decompression", &self.decompression)
+            .field("decode_limits", &self.decode_limits);
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        debug.field("compress_requests", &self.compress_requests);
+        #[cfg(feature = "chaos")]
+        debug.field("fault_injection", &self.fault_injection);
+        debug.finish()
+    }
+}
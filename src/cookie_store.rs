@@ -0,0 +1,135 @@
+use crate::common::{Cookie, CookieJar, CookieStore};
+use hyper::header::HeaderValue;
+use hyper::Uri;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A [`CookieJar`] persisted to a JSON file, so cookies survive restarts.
+///
+/// The file is rewritten atomically whenever a response changes the jar,
+/// on tokio's blocking thread pool when called from a runtime. It holds
+/// session cookies too, so a restarted process continues the same
+/// sessions; on Unix it is created readable by its owner only.
+#[derive(Debug)]
+pub struct FileCookieStore {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: PathBuf,
+    jar: CookieJar,
+    /// Held while writing, so concurrent saves do not share the temporary file.
+    writing: Mutex<()>,
+    /// Set while a save is queued, so a burst of changes is written once.
+    pending: AtomicBool,
+}
+
+impl FileCookieStore {
+    /// Opens the store at `path`, loading its cookies if the file exists.
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let jar = CookieJar::new();
+        match std::fs::read(&path) {
+            Ok(contents) => {
+                let cookies: Vec<Cookie> = serde_json::from_slice(&contents)?;
+                jar.insert_all(cookies, SystemTime::now());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(FileCookieStore {
+            inner: Arc::new(Inner {
+                path,
+                jar,
+                writing: Mutex::new(()),
+                pending: AtomicBool::new(false),
+            }),
+        })
+    }
+
+    /// Returns the cookies that have not expired.
+    pub fn all(&self) -> Vec<Cookie> {
+        self.inner.jar.all()
+    }
+
+    /// Removes every cookie and saves the empty store.
+    ///
+    /// Unlike saves after responses, this writes the file before returning.
+    pub fn clear(&self) -> std::io::Result<()> {
+        self.inner.jar.clear();
+        self.inner.save()
+    }
+
+    /// Saves the jar in the background, or right away outside a runtime.
+    fn save_later(&self) {
+        if self.inner.pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let inner = Arc::clone(&self.inner);
+        let save = move || {
+            if let Err(e) = inner.save() {
+                tracing::warn!(error = %e, path = %inner.path.display(), "failed to save cookies");
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => drop(handle.spawn_blocking(save)),
+            Err(_) => save(),
+        }
+    }
+}
+
+impl Inner {
+    /// Writes the cookies to the file, replacing it atomically.
+    fn save(&self) -> std::io::Result<()> {
+        let _writing = self.writing.lock().unwrap();
+        // Cleared before taking the snapshot, so changes made while writing
+        // queue another save.
+        self.pending.store(false, Ordering::Release);
+        let contents = serde_json::to_vec(&self.jar.all())?;
+
+        let tmp = self.path.with_extension("tmp");
+        // A file left by an interrupted save may have other permissions.
+        if let Err(e) = std::fs::remove_file(&tmp) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e);
+            }
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+impl CookieStore for FileCookieStore {
+    fn set_cookies(&self, uri: &Uri, set_cookies: &mut dyn Iterator<Item = &HeaderValue>) {
+        if self.inner.jar.update(uri, set_cookies) {
+            self.save_later();
+        }
+    }
+
+    fn cookies(&self, uri: &Uri) -> Option<HeaderValue> {
+        self.inner.jar.cookies(uri)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.inner.path)
+    }
+
+    fn all_cookies(&self) -> Option<Vec<Cookie>> {
+        Some(self.all())
+    }
+
+    fn replace_cookies(&self, cookies: Vec<Cookie>) {
+        self.inner.jar.replace_cookies(cookies);
+        self.save_later();
+    }
+}
@@ -0,0 +1,112 @@
+use crate::common::{Check, CheckStatus, SelfTestReport};
+use crate::Client;
+use hyper::Uri;
+
+/// Origin connected to by name to confirm that names are resolved by Tor.
+const CHECK_ORIGIN: &str = "http://check.torproject.org/";
+
+/// Local origin that must be refused rather than contacted directly.
+const LOCAL_ORIGIN: &str = "http://127.0.0.1:80/";
+
+impl Client {
+    /// Runs a set of leak checks and returns a report describing the results.
+    ///
+    /// The checks confirm that names are resolved through the Tor network,
+    /// that local addresses are refused instead of being reached directly, and
+    /// that the Tor Project's check service sees the request arriving from a
+    /// Tor exit. They connect the way requests do, so configured connectors,
+    /// connect hooks and exit exclusions are part of what is tested.
+    pub async fn self_test(&self) -> SelfTestReport {
+        let mut checks = Vec::new();
+
+        let check_origin = Uri::from_static(CHECK_ORIGIN);
+        let exit_country = self.exit_country(&check_origin, None);
+        checks.push(Check {
+            name: "tor_resolution",
+            description: "Host names are resolved through the Tor network",
+            status: match self
+                .create_stream(&check_origin, None, exit_country, None)
+                .await
+            {
+                Ok(_) => CheckStatus::Passed,
+                Err(e) => CheckStatus::Failed(e.to_string()),
+            },
+        });
+
+        checks.push(Check {
+            name: "no_direct_connections",
+            description: "Local addresses are refused rather than contacted directly",
+            status: match self
+                .create_stream(&Uri::from_static(LOCAL_ORIGIN), None, None, None)
+                .await
+            {
+                Ok(_) => CheckStatus::Failed("a local address was reachable".into()),
+                Err(_) => CheckStatus::Passed,
+            },
+        });
+
+        #[cfg(not(all(feature = "json", feature = "native-tls")))]
+        let exit_ip = None;
+        #[cfg(all(feature = "json", feature = "native-tls"))]
+        let (status, exit_ip) = match self.verify_tor().await {
+            Ok(check) if check.is_tor => (CheckStatus::Passed, Some(check.exit_ip)),
+            Ok(check) => (
+                CheckStatus::Failed(format!("{} is not a Tor exit", check.exit_ip)),
+                Some(check.exit_ip),
+            ),
+            Err(e) => (CheckStatus::Failed(e.to_string()), None),
+        };
+        #[cfg(all(feature = "json", feature = "native-tls"))]
+        checks.push(Check {
+            name: "tor_exit",
+            description: "check.torproject.org sees the request coming from Tor",
+            status,
+        });
+
+        SelfTestReport { checks, exit_ip }
+    }
+}
+
+#[cfg(all(feature = "json", feature = "native-tls"))]
+mod check_api {
+    use crate::common::TorVerification;
+    use crate::error::{Error, Result};
+    use crate::Client;
+    use http_body_util::{BodyExt, Limited};
+    use serde::Deserialize;
+    use std::net::IpAddr;
+
+    /// Endpoint of the Tor Project's check API.
+    const CHECK_URL: &str = "https://check.torproject.org/api/ip";
+
+    #[derive(Deserialize)]
+    struct CheckApiResponse {
+        #[serde(rename = "IsTor")]
+        is_tor: bool,
+        #[serde(rename = "IP")]
+        ip: IpAddr,
+    }
+
+    impl Client {
+        /// Asks the Tor Project's check service whether requests from this client
+        /// arrive through Tor, and from which exit address.
+        pub async fn verify_tor(&self) -> Result<TorVerification> {
+            let resp = self.get(CHECK_URL).await?;
+            if !resp.status().is_success() {
+                return Err(Error::Status(resp.status()));
+            }
+
+            let body = Limited::new(resp.into_body(), 64 * 1024)
+                .collect()
+                .await
+                .map_err(Error::body)?
+                .to_bytes();
+            let check: CheckApiResponse =
+                serde_json::from_slice(&body).map_err(|e| Error::Decode(e.into()))?;
+            Ok(TorVerification {
+                is_tor: check.is_tor,
+                exit_ip: check.ip,
+            })
+        }
+    }
+}
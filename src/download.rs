@@ -0,0 +1,250 @@
+use crate::common::{ContentRange, DownloadProgress};
+use crate::error::{Error, Result};
+use crate::{Client, ResponseBody};
+use http_body_util::BodyExt;
+use hyper::body::Body;
+use hyper::header::{
+    HeaderValue, ACCEPT_ENCODING, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use hyper::{Method, Response, StatusCode, Uri};
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+/// How many interruptions in a row without a single new byte a resumable
+/// download survives before it gives up.
+const RESUME_ATTEMPTS: u32 = 5;
+
+impl Client {
+    /// Downloads `uri` into the file at `path`, returning the number of bytes
+    /// written.
+    ///
+    /// The body is streamed to disk as it arrives rather than held in memory.
+    /// An existing file is replaced. Responses with a status other than
+    /// `2xx` fail with [`Error::Status`] before the file is created.
+    pub async fn download<T>(&self, uri: T, path: impl AsRef<Path>) -> Result<u64>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        self.download_with_progress(uri, path, |_| {}).await
+    }
+
+    /// Like [`download`](Self::download), calling `progress` each time a
+    /// chunk of the body has been written.
+    pub async fn download_with_progress<T, F>(
+        &self,
+        uri: T,
+        path: impl AsRef<Path>,
+        mut progress: F,
+    ) -> Result<u64>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+        F: FnMut(DownloadProgress) + Send,
+    {
+        let resp = self.request(Method::GET, uri).send().await?;
+        if !resp.status().is_success() {
+            return Err(Error::Status(resp.status()));
+        }
+        let mut body = resp.into_body();
+        let mut state = DownloadProgress {
+            received: 0,
+            total: body.size_hint().exact(),
+        };
+
+        let mut file = File::create(path).await.map_err(Error::Write)?;
+        write_body(&mut body, &mut file, &mut state, &mut progress).await?;
+        file.flush().await.map_err(Error::Write)?;
+        Ok(state.received)
+    }
+
+    /// Like [`download`](Self::download), but survives the connection
+    /// dropping mid-transfer.
+    ///
+    /// When the body is cut off, the request is sent again asking only for
+    /// the bytes still missing (`Range: bytes=N-`), guarded by `If-Range`
+    /// with the `ETag` or `Last-Modified` of the first response so that a
+    /// changed resource is never spliced onto the old one. If the server
+    /// sends the whole body instead, the file is started over. The body is
+    /// requested without a content coding, as ranges of a compressed body
+    /// cannot be decoded on their own.
+    pub async fn download_resumable<T>(&self, uri: T, path: impl AsRef<Path>) -> Result<u64>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        self.download_resumable_with_progress(uri, path, |_| {})
+            .await
+    }
+
+    /// Like [`download_resumable`](Self::download_resumable), calling
+    /// `progress` each time a chunk of the body has been written.
+    pub async fn download_resumable_with_progress<T, F>(
+        &self,
+        uri: T,
+        path: impl AsRef<Path>,
+        mut progress: F,
+    ) -> Result<u64>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+        F: FnMut(DownloadProgress) + Send,
+    {
+        let uri = Uri::try_from(uri).map_err(|e| Error::InvalidUri(e.into()))?;
+        let mut download = Resumable {
+            file: None,
+            validator: None,
+            state: DownloadProgress {
+                received: 0,
+                total: None,
+            },
+        };
+
+        let mut failures = 0;
+        loop {
+            let before = download.state.received;
+            let mut req = self
+                .request::<Uri>(Method::GET, uri.clone())
+                .header(ACCEPT_ENCODING, "identity");
+            if let (Some(validator), true) = (&download.validator, before > 0) {
+                req = req
+                    .header(RANGE, format!("bytes={before}-"))
+                    .header(IF_RANGE, validator.clone());
+            }
+
+            let result = match req.send().await {
+                Ok(resp) => download.resume(resp, path.as_ref(), &mut progress).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => break,
+                Err(e) if interrupted(&e) => {
+                    if download.state.received > before {
+                        failures = 0;
+                    }
+                    failures += 1;
+                    if failures > RESUME_ATTEMPTS {
+                        return Err(e);
+                    }
+                    tracing::debug!(
+                        %uri,
+                        received = download.state.received,
+                        error = %e,
+                        "download interrupted, resuming"
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if let Some(file) = &mut download.file {
+            file.flush().await.map_err(Error::Write)?;
+        }
+        Ok(download.state.received)
+    }
+}
+
+/// A resumable download between attempts.
+struct Resumable {
+    // Created once the first usable response arrives.
+    file: Option<File>,
+    // What `If-Range` is sent with, if the resource has a usable validator.
+    validator: Option<HeaderValue>,
+    state: DownloadProgress,
+}
+
+impl Resumable {
+    /// Appends the body of `resp` to the file, or starts the file over if
+    /// the server sent the whole body.
+    async fn resume<F>(
+        &mut self,
+        resp: Response<ResponseBody>,
+        path: &Path,
+        progress: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(DownloadProgress) + Send,
+    {
+        let status = resp.status();
+        let asked_for = self.state.received;
+        let file = if status == StatusCode::PARTIAL_CONTENT {
+            // Only a reply to a range request can be appended, and only if it
+            // continues where the file ends.
+            let range = resp
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(ContentRange::parse);
+            match (range, &mut self.file) {
+                (Some(range), Some(file)) if asked_for > 0 && range.start == asked_for => {
+                    self.state.total = range.complete_length;
+                    file
+                }
+                _ => return Err(Error::Status(status)),
+            }
+        } else if status.is_success() {
+            self.validator = validator(&resp);
+            self.state = DownloadProgress {
+                received: 0,
+                total: resp.body().size_hint().exact(),
+            };
+            match &mut self.file {
+                Some(file) => {
+                    file.set_len(0).await.map_err(Error::Write)?;
+                    file.rewind().await.map_err(Error::Write)?;
+                    file
+                }
+                None => self
+                    .file
+                    .insert(File::create(path).await.map_err(Error::Write)?),
+            }
+        } else {
+            return Err(Error::Status(status));
+        };
+
+        let mut body = resp.into_body();
+        write_body(&mut body, file, &mut self.state, progress).await
+    }
+}
+
+/// Writes `body` to `file` chunk by chunk, counting it into `state`.
+async fn write_body<F>(
+    body: &mut ResponseBody,
+    file: &mut File,
+    state: &mut DownloadProgress,
+    progress: &mut F,
+) -> Result<()>
+where
+    F: FnMut(DownloadProgress) + Send,
+{
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(Error::body)?;
+        if let Ok(data) = frame.into_data() {
+            file.write_all(&data).await.map_err(Error::Write)?;
+            state.received += data.len() as u64;
+            progress(*state);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the validator to send in `If-Range`: a strong `ETag`, or else
+/// `Last-Modified`. Weak entity tags must not be used for ranges.
+fn validator(resp: &Response<ResponseBody>) -> Option<HeaderValue> {
+    let headers = resp.headers();
+    headers
+        .get(ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+        .or_else(|| headers.get(LAST_MODIFIED))
+        .cloned()
+}
+
+/// Tells whether a download failed because its connection gave out, as
+/// opposed to the server refusing it or the file being unwritable.
+fn interrupted(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::Http(_) | Error::Connect(_) | Error::Timeout(_) | Error::Stalled(_)
+    )
+}
@@ -0,0 +1,148 @@
+use crate::body::{BodyTimeout, BoxError};
+use crate::stall::Stalled;
+use arti_client::{ErrorKind, HasKind};
+use std::error::Error as StdError;
+
+pub use crate::common::{ConnectionError, ConnectionErrorKind, Error, Result};
+
+impl Error {
+    /// Wraps an error reading a response body, reporting a body that took
+    /// too long as [`Error::Timeout`].
+    pub(crate) fn body(error: BoxError) -> Self {
+        match error.downcast_ref::<BodyTimeout>() {
+            Some(timeout) => Error::Timeout(timeout.duration()),
+            None => Error::Http(error),
+        }
+    }
+}
+
+impl From<ConnectionError> for Error {
+    fn from(error: ConnectionError) -> Self {
+        match find::<Stalled>(error.source.as_ref()) {
+            Some(Stalled(timeout)) => Error::Stalled(*timeout),
+            None => Error::Connect(error),
+        }
+    }
+}
+
+impl ConnectionError {
+    /// Classifies `error` by walking its chain of sources.
+    pub fn new(error: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        let source = error.into();
+        let kind = classify(source.as_ref()).unwrap_or(ConnectionErrorKind::Other);
+        ConnectionError { kind, source }
+    }
+
+    /// Returns `true` if Tor failed to open the stream for a reason another
+    /// circuit may avoid. Nothing of the request was sent then.
+    pub(crate) fn is_circuit_refusal(&self) -> bool {
+        tor_kind(self.source.as_ref()).is_some_and(|kind| {
+            matches!(
+                kind,
+                ErrorKind::CircuitCollapse
+                    | ErrorKind::ExitTimeout
+                    | ErrorKind::TorNetworkTimeout
+                    | ErrorKind::RemoteStreamClosed
+                    | ErrorKind::RemoteStreamReset
+                    | ErrorKind::RemoteStreamError
+            )
+        })
+    }
+}
+
+/// Returns the kind of the first error from arti in an error chain,
+/// whether it came through `TorClient` or straight from a circuit.
+fn tor_kind(error: &(dyn StdError + 'static)) -> Option<ErrorKind> {
+    find::<arti_client::Error>(error)
+        .map(HasKind::kind)
+        .or_else(|| find::<tor_proto::Error>(error).map(HasKind::kind))
+        .or_else(|| circmgr_kind(error))
+}
+
+/// Returns the kind of the first circuit manager error in an error chain.
+/// Only streams opened around excluded exits come straight from it.
+#[cfg(feature = "exclude-exits")]
+fn circmgr_kind(error: &(dyn StdError + 'static)) -> Option<ErrorKind> {
+    find::<tor_circmgr::Error>(error).map(HasKind::kind)
+}
+
+#[cfg(not(feature = "exclude-exits"))]
+fn circmgr_kind(_error: &(dyn StdError + 'static)) -> Option<ErrorKind> {
+    None
+}
+
+/// Finds an error of type `T` in an error chain.
+fn find<'a, T: StdError + 'static>(error: &'a (dyn StdError + 'static)) -> Option<&'a T> {
+    if let Some(e) = error.downcast_ref::<T>() {
+        return Some(e);
+    }
+    if let Some(e) = error.downcast_ref::<std::io::Error>() {
+        // `io::Error::source` skips the wrapped error, so look at it directly.
+        if let Some(found) = e.get_ref().and_then(|inner| find(inner)) {
+            return Some(found);
+        }
+    }
+    error.source().and_then(find)
+}
+
+/// Finds the most specific cause in an error chain.
+fn classify(error: &(dyn StdError + 'static)) -> Option<ConnectionErrorKind> {
+    if let Some(e) = error.downcast_ref::<tor_proto::Error>() {
+        if let Some(kind) = from_tor_kind(e.kind()) {
+            return Some(kind);
+        }
+    }
+    if let Some(e) = error.downcast_ref::<arti_client::Error>() {
+        if let Some(kind) = from_tor_kind(e.kind()) {
+            return Some(kind);
+        }
+    }
+    #[cfg(feature = "exclude-exits")]
+    if let Some(e) = error.downcast_ref::<tor_circmgr::Error>() {
+        if let Some(kind) = from_tor_kind(e.kind()) {
+            return Some(kind);
+        }
+    }
+    if let Some(e) = error.downcast_ref::<std::io::Error>() {
+        // `io::Error::source` skips the wrapped error, so look at it directly.
+        let inner = e.get_ref().map(|inner| inner as &(dyn StdError + 'static));
+        if let Some(kind) = inner.and_then(classify) {
+            return Some(kind);
+        }
+        match e.kind() {
+            std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::UnexpectedEof => return Some(ConnectionErrorKind::ResetByPeer),
+            std::io::ErrorKind::InvalidData => return Some(ConnectionErrorKind::Protocol),
+            _ => {}
+        }
+    }
+    if let Some(e) = error.downcast_ref::<hyper::Error>() {
+        if e.is_parse() || e.is_parse_status() {
+            return Some(ConnectionErrorKind::Protocol);
+        }
+        if let Some(kind) = error.source().and_then(classify) {
+            return Some(kind);
+        }
+        if e.is_incomplete_message() {
+            return Some(ConnectionErrorKind::ResetByPeer);
+        }
+        return None;
+    }
+
+    error.source().and_then(classify)
+}
+
+fn from_tor_kind(kind: ErrorKind) -> Option<ConnectionErrorKind> {
+    match kind {
+        ErrorKind::CircuitCollapse => Some(ConnectionErrorKind::CircuitClosed),
+        ErrorKind::RemoteStreamClosed | ErrorKind::RemoteStreamReset => {
+            Some(ConnectionErrorKind::ResetByPeer)
+        }
+        ErrorKind::TorProtocolViolation | ErrorKind::RemoteProtocolViolation => {
+            Some(ConnectionErrorKind::Protocol)
+        }
+        _ => None,
+    }
+}
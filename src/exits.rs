@@ -0,0 +1,199 @@
+use crate::common::FastExitSelection;
+use arti_client::IsolationToken;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Weight of the newest sample in an exit's latency average.
+const EWMA_ALPHA: f64 = 0.3;
+/// Exit relays whose latency is remembered; the least recently used are
+/// forgotten first.
+const MAX_TRACKED_EXITS: usize = 256;
+
+/// Tracks latency per exit relay and picks the candidate circuit whose exit
+/// has been fastest.
+pub(crate) struct ExitSelector {
+    settings: FastExitSelection,
+    state: Mutex<SelectorState>,
+}
+
+struct SelectorState {
+    slots: Vec<Slot>,
+    /// Latency by exit fingerprint. Kept when the circuits are renewed, so
+    /// a new circuit through a known exit is ranked right away.
+    exits: HashMap<String, ExitStats>,
+    requests: u32,
+    /// Counts recorded requests, to find the least recently used exit.
+    clock: u64,
+}
+
+struct Slot {
+    token: IsolationToken,
+    /// Fingerprint of the circuit's exit, once a response has told it.
+    exit: Option<String>,
+}
+
+struct ExitStats {
+    latency: f64,
+    last_used: u64,
+}
+
+impl Slot {
+    fn fresh() -> Self {
+        Slot {
+            token: IsolationToken::new(),
+            exit: None,
+        }
+    }
+}
+
+impl SelectorState {
+    /// Returns the average latency of the exit behind `slot`, if known.
+    fn latency(&self, slot: &Slot) -> Option<f64> {
+        let exit = slot.exit.as_ref()?;
+        self.exits.get(exit).map(|stats| stats.latency)
+    }
+}
+
+impl ExitSelector {
+    /// Creates a selector with the given settings.
+    pub(crate) fn new(settings: FastExitSelection) -> Self {
+        let slots = (0..settings.circuits.max(1))
+            .map(|_| Slot::fresh())
+            .collect();
+        ExitSelector {
+            settings,
+            state: Mutex::new(SelectorState {
+                slots,
+                exits: HashMap::new(),
+                requests: 0,
+                clock: 0,
+            }),
+        }
+    }
+
+    /// Picks the isolation token of the circuit to use next.
+    ///
+    /// Circuits whose exit has no measurements are tried first.
+    pub(crate) fn choose(&self) -> IsolationToken {
+        let state = self.state.lock().unwrap();
+        state
+            .slots
+            .iter()
+            .min_by(|a, b| {
+                let a = state.latency(a).unwrap_or(f64::NEG_INFINITY);
+                let b = state.latency(b).unwrap_or(f64::NEG_INFINITY);
+                a.total_cmp(&b)
+            })
+            .map(|slot| slot.token)
+            .expect("selector always has at least one slot")
+    }
+
+    /// Replaces every circuit with a fresh one. What was learned about the
+    /// exits is kept.
+    pub(crate) fn renew(&self) {
+        let mut state = self.state.lock().unwrap();
+        for slot in &mut state.slots {
+            *slot = Slot::fresh();
+        }
+        state.requests = 0;
+    }
+
+    /// Records how a request over the circuit identified by `token` went.
+    ///
+    /// `exit` is the fingerprint of the circuit's exit relay, unknown for
+    /// streams from a custom connector.
+    pub(crate) fn record(
+        &self,
+        token: IsolationToken,
+        exit: Option<&str>,
+        latency: Duration,
+        success: bool,
+    ) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        state.clock += 1;
+
+        if let Some(slot) = state.slots.iter_mut().find(|slot| slot.token == token) {
+            if !success {
+                // A failing circuit is not worth keeping around.
+                *slot = Slot::fresh();
+            } else if let Some(exit) = exit {
+                slot.exit = Some(exit.to_owned());
+                let sample = latency.as_secs_f64();
+                let stats = state.exits.entry(exit.to_owned()).or_insert(ExitStats {
+                    latency: sample,
+                    last_used: state.clock,
+                });
+                stats.latency += EWMA_ALPHA * (sample - stats.latency);
+                stats.last_used = state.clock;
+                if state.exits.len() > MAX_TRACKED_EXITS {
+                    let oldest = state
+                        .exits
+                        .iter()
+                        .min_by_key(|(_, stats)| stats.last_used)
+                        .map(|(exit, _)| exit.clone());
+                    if let Some(oldest) = oldest {
+                        state.exits.remove(&oldest);
+                    }
+                }
+            }
+        }
+
+        state.requests += 1;
+        if state.requests >= self.settings.rotate_after {
+            state.requests = 0;
+            let slowest = (0..state.slots.len())
+                .filter_map(|i| Some((i, state.latency(&state.slots[i])?)))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((i, _)) = slowest {
+                state.slots[i] = Slot::fresh();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_fast_exits_across_renewals() {
+        let selector = ExitSelector::new(FastExitSelection {
+            circuits: 2,
+            rotate_after: 100,
+        });
+        let first = selector.choose();
+        selector.record(first, Some("SLOW"), Duration::from_secs(3), true);
+        let second = selector.choose();
+        assert_ne!(first, second);
+        selector.record(second, Some("FAST"), Duration::from_secs(1), true);
+        assert_eq!(selector.choose(), second);
+
+        // New circuits are tried first, then ranked by their exit's history
+        // rather than by their own first request.
+        selector.renew();
+        let renewed = selector.choose();
+        selector.record(renewed, Some("FAST"), Duration::from_secs(2), true);
+        let other = selector.choose();
+        assert_ne!(renewed, other);
+        selector.record(other, Some("SLOW"), Duration::from_secs(1), true);
+        assert_eq!(selector.choose(), renewed);
+    }
+
+    #[test]
+    fn rotates_the_slowest_circuit() {
+        let selector = ExitSelector::new(FastExitSelection {
+            circuits: 2,
+            rotate_after: 2,
+        });
+        let slow = selector.choose();
+        selector.record(slow, Some("SLOW"), Duration::from_secs(3), true);
+        let fast = selector.choose();
+        selector.record(fast, Some("FAST"), Duration::from_secs(1), true);
+
+        let fresh = selector.choose();
+        assert_ne!(fresh, slow);
+        assert_ne!(fresh, fast);
+    }
+}
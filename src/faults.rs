@@ -0,0 +1,96 @@
+use crate::common::FaultInjection;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io::{Error as IoError, ErrorKind};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf, Take};
+
+/// Applies [`FaultInjection`] settings to new connections.
+pub(crate) struct FaultInjector {
+    settings: FaultInjection,
+    rng: Mutex<StdRng>,
+}
+
+impl FaultInjector {
+    /// Creates an injector with the given settings.
+    pub(crate) fn new(settings: FaultInjection) -> Self {
+        let rng = Mutex::new(StdRng::seed_from_u64(settings.seed));
+        FaultInjector { settings, rng }
+    }
+
+    /// Delays or fails a new connection, or wraps it to fail later.
+    pub(crate) async fn inject<S>(&self, stream: S) -> Result<FaultyStream<S>, IoError>
+    where
+        S: AsyncRead + Unpin,
+    {
+        if !self.settings.connect_delay.is_zero() {
+            tokio::time::sleep(self.settings.connect_delay).await;
+        }
+        let reset = self
+            .rng
+            .lock()
+            .unwrap()
+            .gen_bool(self.settings.reset_rate.clamp(0.0, 1.0));
+        if reset {
+            return Err(IoError::new(
+                ErrorKind::ConnectionReset,
+                "connection reset by fault injection",
+            ));
+        }
+
+        let (limit, reset_at_limit) =
+            match (self.settings.truncate_after, self.settings.reset_after) {
+                (Some(truncate), Some(reset)) if reset < truncate => (reset, true),
+                (Some(truncate), _) => (truncate, false),
+                (None, Some(reset)) => (reset, true),
+                (None, None) => (u64::MAX, false),
+            };
+        Ok(FaultyStream {
+            inner: stream.take(limit),
+            reset_at_limit,
+        })
+    }
+}
+
+/// A stream that ends or fails after a number of bytes have been read.
+pub(crate) struct FaultyStream<S> {
+    inner: Take<S>,
+    reset_at_limit: bool,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FaultyStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.reset_at_limit && this.inner.limit() == 0 {
+            return Poll::Ready(Err(IoError::new(
+                ErrorKind::ConnectionReset,
+                "connection reset by fault injection",
+            )));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for FaultyStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(self.get_mut().inner.get_mut()).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(self.get_mut().inner.get_mut()).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(self.get_mut().inner.get_mut()).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,92 @@
+use crate::common::GraphQlResponse;
+use crate::error::{Error, Result};
+use crate::Client;
+use http_body_util::{BodyExt, Limited};
+use hyper::body::Bytes;
+use hyper::Uri;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Upper bound on the size of a GraphQL response.
+const MAX_GRAPHQL_RESPONSE: usize = 32 * 1024 * 1024;
+
+/// A GraphQL operation to be posted to an endpoint.
+pub struct GraphQlRequest<'a> {
+    client: &'a Client,
+    endpoint: Uri,
+    query: String,
+    variables: Option<Value>,
+    operation_name: Option<String>,
+}
+
+impl Client {
+    /// Starts a GraphQL operation against `endpoint`.
+    pub fn graphql(&self, endpoint: Uri, query: impl Into<String>) -> GraphQlRequest<'_> {
+        GraphQlRequest {
+            client: self,
+            endpoint,
+            query: query.into(),
+            variables: None,
+            operation_name: None,
+        }
+    }
+}
+
+impl GraphQlRequest<'_> {
+    /// Sets the operation's variables; they must serialize to an object.
+    pub fn variables(mut self, variables: impl Serialize) -> Result<Self> {
+        let variables = serde_json::to_value(variables)
+            .map_err(|e| Error::InvalidRequest(format!("invalid GraphQL variables: {e}")))?;
+        if !variables.is_object() {
+            return Err(Error::InvalidRequest(
+                "GraphQL variables must serialize to an object".into(),
+            ));
+        }
+        self.variables = Some(variables);
+        Ok(self)
+    }
+
+    /// Selects the operation to run when the query defines several.
+    pub fn operation_name(mut self, name: impl Into<String>) -> Self {
+        self.operation_name = Some(name.into());
+        self
+    }
+
+    /// Posts the operation and deserializes the response envelope.
+    ///
+    /// Use [`GraphQlResponse::into_data`] to treat any reported error as a
+    /// failure.
+    pub async fn send<T: DeserializeOwned>(self) -> Result<GraphQlResponse<T>> {
+        let mut document = json!({ "query": self.query });
+        if let Some(variables) = self.variables {
+            document["variables"] = variables;
+        }
+        if let Some(name) = self.operation_name {
+            document["operationName"] = name.into();
+        }
+
+        let resp = self
+            .client
+            .post(
+                self.endpoint,
+                "application/json",
+                Bytes::from(document.to_string()),
+            )
+            .await?;
+        let status = resp.status();
+
+        let body = Limited::new(resp.into_body(), MAX_GRAPHQL_RESPONSE)
+            .collect()
+            .await
+            .map_err(Error::body)?
+            .to_bytes();
+        // Validation errors commonly arrive with a 4xx status but a regular
+        // envelope, so the status only matters if the body is not one.
+        match serde_json::from_slice(&body) {
+            Ok(response) => Ok(response),
+            Err(_) if !status.is_success() => Err(Error::Status(status)),
+            Err(e) => Err(Error::Decode(e.into())),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{AsyncReadWrite, BoxFuture, ClockSkew, ExitCountry, SandboxRequirements};
+use arti_client::IsolationToken;
+use hyper::http::uri::Scheme;
+use std::future::Future;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// A connection about to be opened through Tor.
+#[derive(Debug, Clone)]
+pub struct ConnectTarget {
+    /// The host to connect to.
+    pub host: String,
+    /// The port to connect to.
+    pub port: u16,
+    /// The scheme of the request that needs the connection.
+    pub scheme: Scheme,
+    /// The isolation token the stream will use, if any.
+    pub isolation: Option<IsolationToken>,
+    /// The country the stream has to exit from, if one was chosen.
+    pub exit_country: Option<ExitCountry>,
+}
+
+/// Decides whether a connection may be opened.
+///
+/// The hook runs before any traffic leaves the process and may take as long as
+/// it needs, e.g. to ask the user for confirmation.
+pub trait ConnectHook: Send + Sync {
+    /// Returns `true` to allow the connection or `false` to veto it.
+    fn allow<'a>(&'a self, target: &'a ConnectTarget) -> BoxFuture<'a, bool>;
+}
+
+impl<F, Fut> ConnectHook for F
+where
+    F: Fn(ConnectTarget) -> Fut + Send + Sync,
+    Fut: Future<Output = bool> + Send + 'static,
+{
+    fn allow<'a>(&'a self, target: &'a ConnectTarget) -> BoxFuture<'a, bool> {
+        Box::pin(self(target.clone()))
+    }
+}
+
+/// A stream opened by a [`Connector`].
+pub type ConnectedStream = Box<dyn AsyncReadWrite + Unpin + Send>;
+
+/// Opens streams in place of Tor.
+///
+/// Installing one with
+/// [`ClientConfigBuilder::connector`](crate::ClientConfigBuilder::connector)
+/// lets a `Client` run against a simulated network in tests, e.g. one end of
+/// a `tokio::io::duplex` pipe served by a hyper server, without bootstrapping
+/// Tor. Connect hooks, TLS and fault injection still apply.
+pub trait Connector: Send + Sync {
+    /// Opens a stream to `target`.
+    fn connect<'a>(
+        &'a self,
+        target: &'a ConnectTarget,
+    ) -> BoxFuture<'a, std::io::Result<ConnectedStream>>;
+}
+
+impl<F, Fut> Connector for F
+where
+    F: Fn(ConnectTarget) -> Fut + Send + Sync,
+    Fut: Future<Output = std::io::Result<ConnectedStream>> + Send + 'static,
+{
+    fn connect<'a>(
+        &'a self,
+        target: &'a ConnectTarget,
+    ) -> BoxFuture<'a, std::io::Result<ConnectedStream>> {
+        Box::pin(self(target.clone()))
+    }
+}
+
+/// Receives reports of servers whose clock disagrees with the local one.
+///
+/// A wrong local clock is a common cause of failing onion service
+/// authentication and TLS certificate checks. arti keeps its own skew
+/// estimate from relay handshakes and accepts no outside input, so the hook
+/// is the place to warn the user or correct the system clock.
+pub trait ClockSkewHook: Send + Sync {
+    /// Called with the skew observed in a response from `origin`.
+    fn report(&self, origin: &str, skew: ClockSkew);
+}
+
+impl<F> ClockSkewHook for F
+where
+    F: Fn(&str, ClockSkew) + Send + Sync,
+{
+    fn report(&self, origin: &str, skew: ClockSkew) {
+        self(origin, skew)
+    }
+}
+
+/// Receives a copy of the bytes exchanged on every connection, for debugging.
+///
+/// The bytes are the plaintext HTTP of the exchange: what is handed to TLS
+/// before encryption and what comes out of it, or the stream itself for
+/// plain HTTP. They include cookies, credentials and bodies, so a tap must
+/// only be installed while debugging. Calls happen on the connection's task
+/// and hold it up, so a tap should be quick.
+pub trait WireTap: Send + Sync {
+    /// Called with bytes written to the connection to `target`.
+    fn sent(&self, target: &ConnectTarget, bytes: &[u8]);
+
+    /// Called with bytes read from the connection to `target`.
+    fn received(&self, target: &ConnectTarget, bytes: &[u8]);
+}
+
+/// Installs a sandbox once the client has been built.
+///
+/// Called at the end of [`Client::with_config`](crate::Client::with_config),
+/// after Tor has bootstrapped, with everything the client still needs, so
+/// that the process can restrict itself to that from then on. An error
+/// fails the construction of the client.
+pub trait SandboxHook: Send + Sync {
+    /// Restricts the process to `requirements`.
+    fn install(&self, requirements: &SandboxRequirements) -> std::io::Result<()>;
+}
+
+impl<F> SandboxHook for F
+where
+    F: Fn(&SandboxRequirements) -> std::io::Result<()> + Send + Sync,
+{
+    fn install(&self, requirements: &SandboxRequirements) -> std::io::Result<()> {
+        self(requirements)
+    }
+}
+
+/// A [`WireTap`] that copies sent and received bytes into two writers, such
+/// as files or `stderr`.
+///
+/// Bytes of concurrent connections are interleaved. Write errors are
+/// ignored, so a full disk does not fail requests.
+pub struct WireTee {
+    sent: Mutex<Box<dyn Write + Send>>,
+    received: Mutex<Box<dyn Write + Send>>,
+}
+
+impl WireTee {
+    /// Copies request bytes into `sent` and response bytes into `received`.
+    pub fn new(sent: impl Write + Send + 'static, received: impl Write + Send + 'static) -> Self {
+        WireTee {
+            sent: Mutex::new(Box::new(sent)),
+            received: Mutex::new(Box::new(received)),
+        }
+    }
+}
+
+impl WireTap for WireTee {
+    fn sent(&self, _target: &ConnectTarget, bytes: &[u8]) {
+        let mut sink = self.sent.lock().unwrap();
+        let _ = sink.write_all(bytes).and_then(|()| sink.flush());
+    }
+
+    fn received(&self, _target: &ConnectTarget, bytes: &[u8]) {
+        let mut sink = self.received.lock().unwrap();
+        let _ = sink.write_all(bytes).and_then(|()| sink.flush());
+    }
+}
+
+impl std::fmt::Debug for WireTee {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WireTee").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,140 @@
+use crate::common::{rpc_request, RpcError, RpcResponse};
+use crate::error::{Error, Result};
+use crate::Client;
+use http_body_util::{BodyExt, Limited};
+use hyper::body::Bytes;
+use hyper::Uri;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Upper bound on the size of a JSON-RPC response.
+const MAX_RPC_RESPONSE: usize = 32 * 1024 * 1024;
+
+/// A JSON-RPC 2.0 client for one endpoint, such as a Bitcoin or Monero node.
+///
+/// Errors reported by the server are returned as [`Error::Other`] holding an
+/// [`RpcError`], which can be recovered with `downcast_ref`.
+pub struct JsonRpc<'a> {
+    client: &'a Client,
+    endpoint: Uri,
+    next_id: AtomicU64,
+}
+
+impl Client {
+    /// Returns a JSON-RPC client that posts calls to `endpoint`.
+    pub fn json_rpc(&self, endpoint: Uri) -> JsonRpc<'_> {
+        JsonRpc {
+            client: self,
+            endpoint,
+            next_id: AtomicU64::new(1),
+        }
+    }
+}
+
+impl JsonRpc<'_> {
+    /// Calls `method` and deserializes its result.
+    ///
+    /// `params` must serialize to an array, an object or `null` (for `()`),
+    /// which omits the parameters.
+    pub async fn call<P, R>(&self, method: &str, params: P) -> Result<R>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = rpc_request(Some(id), method, to_params(params)?);
+
+        let response: RpcResponse = serde_json::from_slice(&self.post(&request).await?)
+            .map_err(|e| Error::Decode(e.into()))?;
+        let result = response
+            .into_result()
+            .map_err(|e| Error::Other(Box::new(e)))?;
+        serde_json::from_value(result).map_err(|e| Error::Decode(e.into()))
+    }
+
+    /// Sends a notification, a call the server does not answer.
+    pub async fn notify<P: Serialize>(&self, method: &str, params: P) -> Result<()> {
+        let request = rpc_request(None, method, to_params(params)?);
+        self.post(&request).await?;
+        Ok(())
+    }
+
+    /// Sends several calls in one request and returns their outcomes in the
+    /// order the calls were given.
+    pub async fn batch(&self, calls: Vec<(&str, Value)>) -> Result<Vec<Result<Value, RpcError>>> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let first = self
+            .next_id
+            .fetch_add(calls.len() as u64, Ordering::Relaxed);
+        let requests: Vec<Value> = calls
+            .into_iter()
+            .zip(first..)
+            .map(|((method, params), id)| rpc_request(Some(id), method, params))
+            .collect();
+        let count = requests.len();
+
+        let body = self.post(&Value::Array(requests)).await?;
+        let responses: Vec<RpcResponse> = match serde_json::from_slice(&body) {
+            Ok(responses) => responses,
+            // A server rejecting the whole batch answers with a single object.
+            Err(_) => {
+                let response: RpcResponse =
+                    serde_json::from_slice(&body).map_err(|e| Error::Decode(e.into()))?;
+                return Err(match response.into_result() {
+                    Ok(_) => Error::Decode("JSON-RPC batch answered with a single result".into()),
+                    Err(e) => Error::Other(Box::new(e)),
+                });
+            }
+        };
+
+        let mut by_id: HashMap<u64, Result<Value, RpcError>> = responses
+            .into_iter()
+            .filter_map(|response| Some((response.id.as_u64()?, response.into_result())))
+            .collect();
+        (first..first + count as u64)
+            .map(|id| {
+                by_id.remove(&id).ok_or_else(|| {
+                    Error::Decode(format!("JSON-RPC batch response is missing call {id}").into())
+                })
+            })
+            .collect()
+    }
+
+    /// Posts a JSON document and returns the response body.
+    async fn post(&self, document: &Value) -> Result<Bytes> {
+        let resp = self
+            .client
+            .post(
+                self.endpoint.clone(),
+                "application/json",
+                Bytes::from(document.to_string()),
+            )
+            .await?;
+        let status = resp.status();
+
+        let body = Limited::new(resp.into_body(), MAX_RPC_RESPONSE)
+            .collect()
+            .await
+            .map_err(Error::body)?
+            .to_bytes();
+        // Servers such as bitcoind report call errors with a 500 status and a
+        // regular JSON-RPC error body, so only fail here if the body is not JSON.
+        let is_json = matches!(body.trim_ascii_start().first(), Some(b'{' | b'['));
+        if !status.is_success() && !is_json {
+            return Err(Error::Status(status));
+        }
+        Ok(body)
+    }
+}
+
+/// Converts call parameters to JSON.
+fn to_params(params: impl Serialize) -> Result<Value> {
+    serde_json::to_value(params)
+        .map_err(|e| Error::InvalidRequest(format!("invalid JSON-RPC parameters: {e}")))
+}
@@ -0,0 +1,124 @@
+use crate::{BoxFuture, Client, Error, RedirectChain, ResponseBody, Result, Url};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::header::{LOCATION, RANGE};
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use std::future::poll_fn;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+/// Maximum number of redirects followed for one link.
+const MAX_LINK_REDIRECTS: usize = 10;
+
+/// The outcome of checking one link with [`Client::check_links`].
+#[derive(Debug)]
+pub struct LinkCheck {
+    /// The URL that was checked.
+    pub url: Uri,
+    /// The URL the last request went to, after following redirects.
+    pub final_url: Uri,
+    /// The status of the last response, or why no response arrived.
+    pub status: Result<StatusCode>,
+    /// Time taken to check the link, including redirects.
+    pub elapsed: Duration,
+}
+
+impl Client {
+    /// Checks a batch of links, at most `concurrency` at a time, and returns
+    /// the outcomes in the order the links were given.
+    ///
+    /// Each link is requested with `HEAD`, falling back to a single-byte
+    /// ranged `GET` for servers that reject `HEAD`, and redirects are
+    /// followed even if the client does not follow them otherwise.
+    pub async fn check_links(
+        &self,
+        urls: impl IntoIterator<Item = Uri>,
+        concurrency: usize,
+    ) -> Vec<LinkCheck> {
+        let mut waiting = urls.into_iter().enumerate();
+        let mut running: Vec<BoxFuture<'_, (usize, LinkCheck)>> = Vec::new();
+        let mut results = Vec::new();
+
+        poll_fn(|cx| loop {
+            while running.len() < concurrency.max(1) {
+                let Some((index, url)) = waiting.next() else {
+                    break;
+                };
+                running.push(Box::pin(async move { (index, self.check_link(url).await) }));
+            }
+
+            if running.is_empty() {
+                return Poll::Ready(());
+            }
+
+            let before = results.len();
+            running.retain_mut(|check| match check.as_mut().poll(cx) {
+                Poll::Ready(result) => {
+                    results.push(result);
+                    false
+                }
+                Poll::Pending => true,
+            });
+            if results.len() == before {
+                return Poll::Pending;
+            }
+        })
+        .await;
+
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, check)| check).collect()
+    }
+
+    async fn check_link(&self, url: Uri) -> LinkCheck {
+        let started = Instant::now();
+        let mut final_url = url.clone();
+        let status = async {
+            for _ in 0..=MAX_LINK_REDIRECTS {
+                let mut resp = self.probe(Method::HEAD, &final_url).await?;
+                if matches!(
+                    resp.status(),
+                    StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+                ) {
+                    resp = self.probe(Method::GET, &final_url).await?;
+                }
+
+                if let Some(chain) = resp.extensions().get::<RedirectChain>() {
+                    final_url = chain.final_uri().unwrap_or(&final_url).clone();
+                }
+
+                let location = resp.headers().get(LOCATION);
+                match location.and_then(|value| value.to_str().ok()) {
+                    Some(location) if resp.status().is_redirection() => {
+                        final_url = Url::parse(&final_url.to_string())
+                            .and_then(|base| base.join(location))
+                            .map_err(|e| {
+                                Error::Redirect(format!("invalid Location `{location}`: {e}"))
+                            })?
+                            .into();
+                    }
+                    _ => return Ok(resp.status()),
+                }
+            }
+            Err(Error::Redirect(format!(
+                "more than {MAX_LINK_REDIRECTS} redirects"
+            )))
+        }
+        .await;
+
+        LinkCheck {
+            url,
+            final_url,
+            status,
+            elapsed: started.elapsed(),
+        }
+    }
+
+    /// Sends a `HEAD`, or a `GET` for the first byte, without a body.
+    async fn probe(&self, method: Method, url: &Uri) -> Result<Response<ResponseBody>> {
+        let mut req = Request::builder().method(method.clone()).uri(url.clone());
+        if method == Method::GET {
+            req = req.header(RANGE, "bytes=0-0");
+        }
+        self.send_request(req.body(Full::<Bytes>::default())?).await
+    }
+}
@@ -0,0 +1,300 @@
+//! `multipart/form-data` request bodies, as sent by HTML forms that upload
+//! files.
+//!
+//! A [`Form`] is attached to a request with
+//! [`RequestBuilder::multipart`](crate::RequestBuilder::multipart). File
+//! parts are streamed from disk rather than read into memory, and reopened
+//! whenever the request is sent again.
+
+use crate::ReplayableBody;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Size of the chunks file parts are read in.
+const FILE_CHUNK: usize = 64 * 1024;
+
+/// A `multipart/form-data` body under construction.
+#[derive(Debug, Clone)]
+pub struct Form {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+
+/// One field of a [`Form`].
+#[derive(Debug, Clone)]
+pub struct Part {
+    content: Content,
+    file_name: Option<String>,
+    content_type: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Content {
+    Bytes(Bytes),
+    File { path: PathBuf, len: u64 },
+}
+
+impl Form {
+    /// Creates an empty form with a random boundary.
+    pub fn new() -> Self {
+        Form {
+            boundary: format!("hypertor-{:032x}", rand::random::<u128>()),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Returns the boundary between the parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Returns the `Content-Type` of the body, including the boundary.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Adds a text field.
+    pub fn text(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.part(name, Part::text(value))
+    }
+
+    /// Adds a field holding `value` as is.
+    pub fn bytes(self, name: impl Into<String>, value: impl Into<Bytes>) -> Self {
+        self.part(name, Part::bytes(value))
+    }
+
+    /// Adds a field with the contents of the file at `path`, named after the
+    /// file.
+    ///
+    /// Fails if the file cannot be read now; it is opened again each time
+    /// the request is sent.
+    pub async fn file(
+        self,
+        name: impl Into<String>,
+        path: impl AsRef<Path>,
+    ) -> std::io::Result<Self> {
+        Ok(self.part(name, Part::file(path).await?))
+    }
+
+    /// Adds a field.
+    pub fn part(mut self, name: impl Into<String>, part: Part) -> Self {
+        self.parts.push((name.into(), part));
+        self
+    }
+
+    /// Turns the form into a request body that can be sent more than once.
+    pub(crate) fn into_body(self) -> ReplayableBody {
+        let mut segments = Vec::with_capacity(self.parts.len() * 3 + 1);
+        for (name, part) in self.parts {
+            let mut head = format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+                self.boundary,
+                escape(&name)
+            );
+            if let Some(file_name) = &part.file_name {
+                head.push_str(&format!("; filename=\"{}\"", escape(file_name)));
+            }
+            head.push_str("\r\n");
+            if let Some(content_type) = &part.content_type {
+                head.push_str(&format!("Content-Type: {content_type}\r\n"));
+            }
+            head.push_str("\r\n");
+            segments.push(Content::Bytes(Bytes::from(head)));
+            segments.push(part.content);
+            segments.push(Content::Bytes(Bytes::from_static(b"\r\n")));
+        }
+        segments.push(Content::Bytes(Bytes::from(format!(
+            "--{}--\r\n",
+            self.boundary
+        ))));
+
+        let segments: Arc<[Content]> = segments.into();
+        let len = segments.iter().map(Content::len).sum();
+        ReplayableBody::from_fn_with_len(move || FormBody::new(segments.clone()), len)
+    }
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Part {
+    /// Creates a text part.
+    pub fn text(value: impl Into<String>) -> Self {
+        Self::bytes(value.into())
+    }
+
+    /// Creates a part holding `value` as is.
+    pub fn bytes(value: impl Into<Bytes>) -> Self {
+        Part {
+            content: Content::Bytes(value.into()),
+            file_name: None,
+            content_type: None,
+        }
+    }
+
+    /// Creates a part streamed from the file at `path`, named after the
+    /// file and sent as `application/octet-stream`.
+    ///
+    /// Fails if the file cannot be read now; it is opened again each time
+    /// the request is sent.
+    pub async fn file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let metadata = tokio::fs::metadata(path).await?;
+        if !metadata.is_file() {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                format!("{} is not a file", path.display()),
+            ));
+        }
+        Ok(Part {
+            content: Content::File {
+                path: path.to_path_buf(),
+                len: metadata.len(),
+            },
+            file_name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            content_type: Some("application/octet-stream".into()),
+        })
+    }
+
+    /// Sets the file name reported for the part.
+    pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Sets the `Content-Type` of the part.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+}
+
+/// Escapes a name for a quoted `Content-Disposition` parameter the way
+/// browsers do.
+fn escape(name: &str) -> String {
+    name.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// One pass over the segments of a form.
+struct FormBody {
+    segments: Arc<[Content]>,
+    next: usize,
+    remaining: u64,
+    file: Option<OpenFile>,
+}
+
+/// The file part being read.
+struct OpenFile {
+    file: tokio::fs::File,
+    path: PathBuf,
+    left: u64,
+    buf: Box<[u8]>,
+}
+
+impl FormBody {
+    fn new(segments: Arc<[Content]>) -> Self {
+        let remaining = segments.iter().map(Content::len).sum();
+        FormBody {
+            segments,
+            next: 0,
+            remaining,
+            file: None,
+        }
+    }
+}
+
+impl Content {
+    fn len(&self) -> u64 {
+        match self {
+            Content::Bytes(bytes) => bytes.len() as u64,
+            Content::File { len, .. } => *len,
+        }
+    }
+}
+
+impl Body for FormBody {
+    type Data = Bytes;
+    type Error = IoError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, IoError>>> {
+        let this = &mut *self;
+        loop {
+            if let Some(open) = &mut this.file {
+                if open.left == 0 {
+                    this.file = None;
+                    continue;
+                }
+                let want = open
+                    .buf
+                    .len()
+                    .min(usize::try_from(open.left).unwrap_or(usize::MAX));
+                let mut buf = ReadBuf::new(&mut open.buf[..want]);
+                match Pin::new(&mut open.file).poll_read(cx, &mut buf) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                    Poll::Ready(Ok(())) if buf.filled().is_empty() => {
+                        return Poll::Ready(Some(Err(IoError::new(
+                            ErrorKind::UnexpectedEof,
+                            format!("{} shrank while it was uploaded", open.path.display()),
+                        ))));
+                    }
+                    Poll::Ready(Ok(())) => {
+                        let chunk = Bytes::copy_from_slice(buf.filled());
+                        open.left -= chunk.len() as u64;
+                        this.remaining -= chunk.len() as u64;
+                        return Poll::Ready(Some(Ok(Frame::data(chunk))));
+                    }
+                }
+            }
+
+            let Some(segment) = this.segments.get(this.next) else {
+                return Poll::Ready(None);
+            };
+            this.next += 1;
+            match segment {
+                Content::Bytes(bytes) if bytes.is_empty() => {}
+                Content::Bytes(bytes) => {
+                    this.remaining -= bytes.len() as u64;
+                    return Poll::Ready(Some(Ok(Frame::data(bytes.clone()))));
+                }
+                Content::File { path, len } => {
+                    // Opening a file does not block for long; reads go
+                    // through tokio's blocking pool.
+                    let file = match std::fs::File::open(path) {
+                        Ok(file) => file,
+                        Err(e) => return Poll::Ready(Some(Err(e))),
+                    };
+                    this.file = Some(OpenFile {
+                        file: tokio::fs::File::from_std(file),
+                        path: path.clone(),
+                        left: *len,
+                        buf: vec![0; FILE_CHUNK].into_boxed_slice(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0 && self.file.is_none() && self.next >= self.segments.len()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
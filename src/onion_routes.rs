@@ -0,0 +1,225 @@
+use crate::common::{Announcement, OnionRoute};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How much later a refreshed route must expire before the store is
+/// rewritten, so that every response does not cause a write.
+const SAVE_GRANULARITY: Duration = Duration::from_secs(60 * 60);
+
+struct Entry {
+    route: OnionRoute,
+    expires: SystemTime,
+}
+
+/// Onion routes learned per origin, optionally persisted to a file.
+///
+/// The file holds one route per line: origin, kind, target and expiry in
+/// seconds since the Unix epoch, separated by spaces.
+pub(crate) struct OnionRoutes {
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<String, Vec<Entry>>>,
+}
+
+impl OnionRoutes {
+    /// Creates a store, loading the routes saved at `path` if it exists.
+    pub(crate) fn open(path: Option<PathBuf>) -> std::io::Result<Self> {
+        let mut entries: HashMap<String, Vec<Entry>> = HashMap::new();
+        if let Some(path) = &path {
+            let contents = match std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(e),
+            };
+            let now = SystemTime::now();
+            for line in contents.lines() {
+                let Some((origin, entry)) = parse_line(line) else {
+                    continue;
+                };
+                if entry.expires > now {
+                    entries.entry(origin).or_default().push(entry);
+                }
+            }
+        }
+        Ok(OnionRoutes {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Returns the onion origin announced with `Onion-Location` by `origin`.
+    pub(crate) fn location(&self, origin: &str) -> Option<String> {
+        self.find(origin, |route| match route {
+            OnionRoute::Location(target) => Some(target.clone()),
+            OnionRoute::AltSvc(_) => None,
+        })
+    }
+
+    /// Returns the onion `host:port` announced with `Alt-Svc` by `origin`.
+    pub(crate) fn alt_svc(&self, origin: &str) -> Option<String> {
+        self.find(origin, |route| match route {
+            OnionRoute::AltSvc(target) => Some(target.clone()),
+            OnionRoute::Location(_) => None,
+        })
+    }
+
+    /// Returns every unexpired route, by origin.
+    pub(crate) fn routes(&self) -> Vec<(String, OnionRoute)> {
+        let now = SystemTime::now();
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .flat_map(|(origin, routes)| routes.iter().map(move |entry| (origin, entry)))
+            .filter(|(_, entry)| entry.expires > now)
+            .map(|(origin, entry)| (origin.clone(), entry.route.clone()))
+            .collect()
+    }
+
+    /// Returns every unexpired route with its expiry, by origin.
+    #[cfg(feature = "json")]
+    pub(crate) fn expiring_routes(&self) -> Vec<(String, OnionRoute, SystemTime)> {
+        let now = SystemTime::now();
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .flat_map(|(origin, routes)| routes.iter().map(move |entry| (origin, entry)))
+            .filter(|(_, entry)| entry.expires > now)
+            .map(|(origin, entry)| (origin.clone(), entry.route.clone(), entry.expires))
+            .collect()
+    }
+
+    /// Replaces every route with `routes`, dropping those already expired.
+    #[cfg(feature = "json")]
+    pub(crate) fn replace(&self, routes: Vec<(String, OnionRoute, SystemTime)>) {
+        let now = SystemTime::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        for (origin, route, expires) in routes {
+            if expires > now {
+                entries
+                    .entry(origin)
+                    .or_default()
+                    .push(Entry { route, expires });
+            }
+        }
+        if let Err(e) = self.save(&entries) {
+            tracing::warn!(error = %e, "failed to save onion routes");
+        }
+    }
+
+    /// Forgets every route.
+    pub(crate) fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        if let Err(e) = self.save(&entries) {
+            tracing::warn!(error = %e, "failed to save onion routes");
+        }
+    }
+
+    fn find<T>(&self, origin: &str, pick: impl Fn(&OnionRoute) -> Option<T>) -> Option<T> {
+        let now = SystemTime::now();
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(origin)?
+            .iter()
+            .filter(|entry| entry.expires > now)
+            .find_map(|entry| pick(&entry.route))
+    }
+
+    /// Records what a response from `origin` announced.
+    pub(crate) fn learn(&self, origin: &str, announcements: Vec<Announcement>) {
+        if announcements.is_empty() {
+            return;
+        }
+        let now = SystemTime::now();
+        let mut entries = self.entries.lock().unwrap();
+        let routes = entries.entry(origin.to_string()).or_default();
+        let mut changed = false;
+        for announcement in announcements {
+            match announcement {
+                Announcement::Clear => {
+                    let before = routes.len();
+                    routes.retain(|entry| !matches!(entry.route, OnionRoute::AltSvc(_)));
+                    changed |= routes.len() != before;
+                }
+                Announcement::Route(route, ttl) => {
+                    let expires = now + ttl;
+                    let same_kind = |entry: &&mut Entry| {
+                        std::mem::discriminant(&entry.route) == std::mem::discriminant(&route)
+                    };
+                    match routes.iter_mut().find(same_kind) {
+                        Some(entry) if entry.route == route => {
+                            let refreshed = expires
+                                .duration_since(entry.expires)
+                                .is_ok_and(|later| later > SAVE_GRANULARITY);
+                            changed |= refreshed || expires < entry.expires;
+                            entry.expires = expires;
+                        }
+                        Some(entry) => {
+                            *entry = Entry { route, expires };
+                            changed = true;
+                        }
+                        None => {
+                            routes.push(Entry { route, expires });
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+        entries.retain(|_, routes| {
+            routes.retain(|entry| entry.expires > now);
+            !routes.is_empty()
+        });
+
+        if changed {
+            if let Err(e) = self.save(&entries) {
+                tracing::warn!(error = %e, "failed to save onion routes");
+            }
+        }
+    }
+
+    /// Writes `entries` to the store file, replacing it atomically.
+    fn save(&self, entries: &HashMap<String, Vec<Entry>>) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut contents = String::new();
+        for (origin, routes) in entries {
+            for entry in routes {
+                let (kind, target) = match &entry.route {
+                    OnionRoute::Location(target) => ("location", target),
+                    OnionRoute::AltSvc(target) => ("alt-svc", target),
+                };
+                let expires = entry
+                    .expires
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                contents.push_str(&format!("{origin} {kind} {target} {expires}\n"));
+            }
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, contents)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(String, Entry)> {
+    let mut fields = line.split_whitespace();
+    let (origin, kind, target, expires) = (
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+    );
+    let route = match kind {
+        "location" => OnionRoute::Location(target.to_string()),
+        "alt-svc" => OnionRoute::AltSvc(target.to_string()),
+        _ => return None,
+    };
+    let expires = UNIX_EPOCH + Duration::from_secs(expires.parse().ok()?);
+    Some((origin.to_string(), Entry { route, expires }))
+}
@@ -0,0 +1,222 @@
+use crate::common::{CircuitInfo, ExitCountry, TlsInfo};
+use crate::usage::{Usage, UsageLimiter};
+use arti_client::IsolationToken;
+use http_body_util::combinators::UnsyncBoxBody;
+use hyper::body::Bytes;
+use hyper::client::conn::{http1::SendRequest, http2};
+use hyper::http::uri::Scheme;
+use hyper::Response;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The request body type of pooled connections.
+pub(crate) type PoolBody = UnsyncBoxBody<Bytes, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Identifies the connections a request may be sent over.
+///
+/// The isolation token and exit country are part of the key, so a
+/// connection never carries requests meant for different circuits.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PoolKey {
+    pub(crate) scheme: Scheme,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) isolation: Option<IsolationToken>,
+    pub(crate) exit_country: Option<ExitCountry>,
+}
+
+/// What is known about the stream a connection runs over.
+#[derive(Clone, Default)]
+pub(crate) struct StreamInfo {
+    pub(crate) tls: Option<TlsInfo>,
+    pub(crate) circuit: Option<CircuitInfo>,
+}
+
+impl StreamInfo {
+    /// Inserts the details into the extensions of `resp`.
+    pub(crate) fn annotate<B>(&self, resp: &mut Response<B>) {
+        if let Some(info) = &self.tls {
+            resp.extensions_mut().insert(info.clone());
+        }
+        if let Some(info) = &self.circuit {
+            resp.extensions_mut().insert(info.clone());
+        }
+    }
+}
+
+/// An HTTP/1 connection that can carry further requests.
+pub(crate) struct PooledConnection {
+    pub(crate) sender: SendRequest<PoolBody>,
+    pub(crate) info: StreamInfo,
+    /// Traffic counted against [`UsageCaps`](crate::UsageCaps), if set.
+    pub(crate) usage: Option<Arc<Usage>>,
+}
+
+struct Idle {
+    conn: PooledConnection,
+    since: Instant,
+}
+
+struct Shared {
+    sender: http2::SendRequest<PoolBody>,
+    usage: Option<Arc<Usage>>,
+    info: StreamInfo,
+    last_used: Instant,
+}
+
+/// Keeps idle keep-alive connections for reuse.
+///
+/// HTTP/2 connections are shared instead: one per key carries every
+/// request, and is dropped once unused for the idle timeout.
+pub(crate) struct Pool {
+    max_idle_per_host: usize,
+    idle_timeout: Duration,
+    idle: Mutex<HashMap<PoolKey, Vec<Idle>>>,
+    shared: Mutex<HashMap<PoolKey, Shared>>,
+    limiter: Option<Arc<UsageLimiter>>,
+}
+
+impl Pool {
+    /// Creates a pool keeping up to `max_idle_per_host` connections per key
+    /// for at most `idle_timeout` each, and retiring those `limiter` finds
+    /// spent.
+    pub(crate) fn new(
+        max_idle_per_host: usize,
+        idle_timeout: Duration,
+        limiter: Option<Arc<UsageLimiter>>,
+    ) -> Self {
+        Pool {
+            max_idle_per_host,
+            idle_timeout,
+            idle: Mutex::new(HashMap::new()),
+            shared: Mutex::new(HashMap::new()),
+            limiter,
+        }
+    }
+
+    /// Returns `true` if connections are kept at all.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_idle_per_host > 0
+    }
+
+    /// Takes the most recently used open connection for `key`.
+    ///
+    /// A connection is checked in as soon as its response has been read,
+    /// which can be before hyper has polled it again; this waits for that.
+    pub(crate) async fn checkout(&self, key: &PoolKey) -> Option<PooledConnection> {
+        loop {
+            let mut conn = self.take(key)?;
+            if conn.sender.ready().await.is_ok() {
+                return Some(conn);
+            }
+        }
+    }
+
+    /// Returns the open HTTP/2 connection for `key`, its usage and what is
+    /// known about its stream, if there is one.
+    pub(crate) fn shared(
+        &self,
+        key: &PoolKey,
+    ) -> Option<(http2::SendRequest<PoolBody>, Option<Arc<Usage>>, StreamInfo)> {
+        let mut shared = self.shared.lock().unwrap();
+        let now = Instant::now();
+        let conn = shared.get_mut(key)?;
+        let expired = now.duration_since(conn.last_used) >= self.idle_timeout;
+        if expired || conn.sender.is_closed() || self.is_spent(conn.usage.as_deref(), key) {
+            shared.remove(key);
+            return None;
+        }
+        conn.last_used = now;
+        Some((conn.sender.clone(), conn.usage.clone(), conn.info.clone()))
+    }
+
+    /// Makes an HTTP/2 connection available to later requests for `key`.
+    pub(crate) fn share(
+        &self,
+        key: PoolKey,
+        sender: http2::SendRequest<PoolBody>,
+        usage: Option<Arc<Usage>>,
+        info: StreamInfo,
+    ) {
+        let now = Instant::now();
+        let mut shared = self.shared.lock().unwrap();
+        shared.retain(|_, conn| {
+            now.duration_since(conn.last_used) < self.idle_timeout && !conn.sender.is_closed()
+        });
+        let last_used = now;
+        shared.insert(
+            key,
+            Shared {
+                sender,
+                usage,
+                info,
+                last_used,
+            },
+        );
+    }
+
+    /// Drops every idle and shared connection.
+    pub(crate) fn clear(&self) {
+        self.idle.lock().unwrap().clear();
+        self.shared.lock().unwrap().clear();
+    }
+
+    /// Returns `true` if a connection with `usage` must not be reused.
+    fn is_spent(&self, usage: Option<&Usage>, key: &PoolKey) -> bool {
+        match (&self.limiter, usage) {
+            (Some(limiter), Some(usage)) => limiter.is_spent(usage, key.isolation),
+            _ => false,
+        }
+    }
+
+    fn take(&self, key: &PoolKey) -> Option<PooledConnection> {
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.get_mut(key)?;
+        let now = Instant::now();
+        conns.retain(|idle| self.is_usable(idle, now));
+        let conn = conns.pop().map(|idle| idle.conn);
+        if conns.is_empty() {
+            idle.remove(key);
+        }
+        conn
+    }
+
+    fn is_usable(&self, idle: &Idle, now: Instant) -> bool {
+        now.duration_since(idle.since) < self.idle_timeout && !idle.conn.sender.is_closed()
+    }
+}
+
+/// A connection to return to its pool once the response body has been read.
+pub(crate) struct Checkin {
+    pool: Arc<Pool>,
+    key: PoolKey,
+    conn: PooledConnection,
+}
+
+impl Checkin {
+    /// Prepares `conn` to be returned to `pool` under `key`.
+    pub(crate) fn new(pool: Arc<Pool>, key: PoolKey, conn: PooledConnection) -> Self {
+        Checkin { pool, key, conn }
+    }
+
+    /// Returns the connection to the pool.
+    pub(crate) fn run(self) {
+        let Checkin { pool, key, conn } = self;
+        if pool.is_spent(conn.usage.as_deref(), &key) {
+            return;
+        }
+        let now = Instant::now();
+        let mut idle = pool.idle.lock().unwrap();
+        // Sweep every key, so connections to hosts that are not visited
+        // again are closed as well.
+        idle.retain(|_, conns| {
+            conns.retain(|idle| pool.is_usable(idle, now));
+            !conns.is_empty()
+        });
+        let conns = idle.entry(key).or_default();
+        if conns.len() < pool.max_idle_per_host {
+            conns.push(Idle { conn, since: now });
+        }
+    }
+}
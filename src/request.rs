@@ -0,0 +1,250 @@
+use crate::error::{Error, Result};
+use crate::multipart::Form;
+use crate::{Client, ReplayableBody, ResponseBody};
+use arti_client::IsolationToken;
+#[cfg(any(feature = "json", feature = "form"))]
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue, CONNECTION, CONTENT_TYPE};
+use hyper::http::request::Builder;
+use hyper::{Method, Request, Response, Uri, Version};
+#[cfg(any(feature = "json", feature = "form"))]
+use serde::Serialize;
+use std::time::Duration;
+use url::form_urlencoded;
+
+/// A request being put together before it is sent with [`send`](Self::send).
+///
+/// Invalid URIs, headers and bodies are reported by `send`.
+pub struct RequestBuilder<'a> {
+    client: &'a Client,
+    builder: Builder,
+    body: ReplayableBody,
+    timeout: Option<Duration>,
+    // A body that could not be built, reported by `send`.
+    error: Option<Error>,
+}
+
+impl Client {
+    /// Starts a request with the given method to `uri`.
+    pub fn request<T>(&self, method: Method, uri: T) -> RequestBuilder<'_>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        RequestBuilder {
+            client: self,
+            builder: Request::builder().method(method).uri(uri),
+            body: ReplayableBody::empty(),
+            timeout: None,
+            error: None,
+        }
+    }
+
+    /// Returns a new isolation token for [`RequestBuilder::isolation`].
+    ///
+    /// Requests carrying the same token may share a circuit; requests
+    /// carrying different tokens, or none, never do.
+    pub fn isolation_token(&self) -> IsolationToken {
+        IsolationToken::new()
+    }
+
+    /// Sends an HTTP POST request to `uri` with `value` serialized as its
+    /// JSON body.
+    #[cfg(feature = "json")]
+    pub async fn post_json<T, U>(&self, uri: T, value: &U) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+        U: Serialize + ?Sized,
+    {
+        self.request(Method::POST, uri).json(value).send().await
+    }
+
+    /// Sends an HTTP POST request to `uri` with `value` serialized as its
+    /// `application/x-www-form-urlencoded` body, as an HTML form would.
+    #[cfg(feature = "form")]
+    pub async fn post_form<T, U>(&self, uri: T, value: &U) -> Result<Response<ResponseBody>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+        U: Serialize + ?Sized,
+    {
+        self.request(Method::POST, uri).form(value).send().await
+    }
+}
+
+impl RequestBuilder<'_> {
+    /// Adds a header, keeping any previous values of the same name.
+    pub fn header<K, V>(mut self, name: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<hyper::http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<hyper::http::Error>,
+    {
+        self.builder = self.builder.header(name, value);
+        self
+    }
+
+    /// Appends form-encoded query parameters to the URI.
+    pub fn query<K, V>(mut self, params: &[(K, V)]) -> Self
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let Some(uri) = self.builder.uri_ref() else {
+            return self;
+        };
+        if params.is_empty() {
+            return self;
+        }
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        for (name, value) in params {
+            query.append_pair(name.as_ref(), value.as_ref());
+        }
+        let separator = if uri.query().is_some() { '&' } else { '?' };
+        let uri = format!("{uri}{separator}{}", query.finish());
+        self.builder = self.builder.uri(uri);
+        self
+    }
+
+    /// Sends the request on a connection of its own, closed afterwards with
+    /// `Connection: close` instead of being kept for later requests.
+    ///
+    /// Useful for servers that mishandle keep-alive, and so that requests
+    /// which must not be linked do not share a connection.
+    pub fn close_connection(mut self) -> Self {
+        self.builder = self
+            .builder
+            .header(CONNECTION, HeaderValue::from_static("close"));
+        self
+    }
+
+    /// Sends the request, and any redirects and retries of it, over circuits
+    /// isolated to `token`.
+    ///
+    /// Requests with different tokens are never linked by a shared circuit
+    /// or connection, whatever the client's exit selection or usage caps.
+    pub fn isolation(mut self, token: IsolationToken) -> Self {
+        self.builder = self.builder.extension(token);
+        self
+    }
+
+    /// Exits the request in the country with the two-letter code `country`
+    /// instead of the client's
+    /// [`exit_country`](crate::ClientConfigBuilder::exit_country).
+    ///
+    /// Onion services are reached without an exit, so requests to them
+    /// ignore it.
+    #[cfg(feature = "geoip")]
+    pub fn exit_country(mut self, country: &str) -> Self {
+        match crate::ExitCountry::new(country) {
+            Some(country) => self.builder = self.builder.extension(country),
+            None => {
+                self.error = Some(Error::InvalidRequest(format!(
+                    "invalid exit country `{country}`"
+                )));
+            }
+        }
+        self
+    }
+
+    /// Sets the HTTP version the request is sent with.
+    pub fn version(mut self, version: Version) -> Self {
+        self.builder = self.builder.version(version);
+        self
+    }
+
+    /// Sets the request body; without one, the request has an empty body.
+    pub fn body(mut self, body: impl Into<ReplayableBody>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Sets the request body to `value` serialized as JSON, along with
+    /// `Content-Type: application/json` unless a content type is already set.
+    #[cfg(feature = "json")]
+    pub fn json<T: Serialize + ?Sized>(mut self, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(json) => self.body = ReplayableBody::from(Bytes::from(json)),
+            Err(e) => {
+                self.error = Some(Error::InvalidRequest(format!(
+                    "failed to serialize JSON body: {e}"
+                )));
+                return self;
+            }
+        }
+        self.default_content_type("application/json")
+    }
+
+    /// Sets the request body to `value` serialized as
+    /// `application/x-www-form-urlencoded`, along with that content type
+    /// unless one is already set.
+    ///
+    /// `value` must serialize to a flat sequence of name/value pairs, such as
+    /// a struct of scalar fields, a map or a slice of tuples.
+    #[cfg(feature = "form")]
+    pub fn form<T: Serialize + ?Sized>(mut self, value: &T) -> Self {
+        match serde_urlencoded::to_string(value) {
+            Ok(form) => self.body = ReplayableBody::from(Bytes::from(form)),
+            Err(e) => {
+                self.error = Some(Error::InvalidRequest(format!(
+                    "failed to serialize form body: {e}"
+                )));
+                return self;
+            }
+        }
+        self.default_content_type("application/x-www-form-urlencoded")
+    }
+
+    /// Sets the request body to `form`, encoded as `multipart/form-data`.
+    ///
+    /// Replaces any `Content-Type` already set, since it has to name the
+    /// form's boundary. File parts are streamed, so the body is read from
+    /// disk again if the request has to be resent.
+    pub fn multipart(mut self, form: Form) -> Self {
+        let content_type = HeaderValue::from_str(&form.content_type())
+            .expect("multipart boundaries are valid header values");
+        if let Some(headers) = self.builder.headers_mut() {
+            headers.insert(CONTENT_TYPE, content_type);
+        }
+        self.body = form.into_body();
+        self
+    }
+
+    /// Sets `Content-Type` to `value` unless a content type is already set.
+    #[cfg(any(feature = "json", feature = "form"))]
+    fn default_content_type(mut self, value: &'static str) -> Self {
+        let has_content_type = self
+            .builder
+            .headers_ref()
+            .is_some_and(|headers| headers.contains_key(CONTENT_TYPE));
+        if !has_content_type {
+            self.builder = self
+                .builder
+                .header(CONTENT_TYPE, HeaderValue::from_static(value));
+        }
+        self
+    }
+
+    /// Fails the request with [`Error::Timeout`] unless the response
+    /// headers arrive within `timeout`, redirects and retries included.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sends the request with [`Client::send_request`].
+    pub async fn send(self) -> Result<Response<ResponseBody>> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let req = self.builder.body(self.body)?;
+        let Some(timeout) = self.timeout else {
+            return self.client.send_request(req).await;
+        };
+        tokio::time::timeout(timeout, self.client.send_request(req))
+            .await
+            .map_err(|_| Error::Timeout(timeout))?
+    }
+}
@@ -0,0 +1,118 @@
+use crate::common::decode_text;
+use crate::error::{Error, Result};
+use crate::ResponseBody;
+use http_body_util::{BodyExt, Limited};
+use hyper::body::Bytes;
+use hyper::Response;
+#[cfg(feature = "json")]
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Largest body the helpers on [`ResponseExt`] read unless told otherwise.
+pub const DEFAULT_BODY_LIMIT: usize = 10 * 1024 * 1024;
+
+/// Helpers for reading the body of a response returned by
+/// [`Client`](crate::Client).
+///
+/// The body is read into memory, so each helper fails with
+/// [`Error::Http`] once it grows past a limit, [`DEFAULT_BODY_LIMIT`]
+/// unless one is given.
+pub trait ResponseExt {
+    /// Reads the body into a single buffer.
+    fn bytes(self) -> impl Future<Output = Result<Bytes>> + Send;
+
+    /// Reads a body of up to `limit` bytes into a single buffer.
+    fn bytes_with_limit(self, limit: usize) -> impl Future<Output = Result<Bytes>> + Send;
+
+    /// Reads the body and decodes it to a `String` with the charset from
+    /// `Content-Type`.
+    ///
+    /// A byte order mark takes precedence over the charset. Without a known
+    /// charset the body is read as UTF-8, and malformed sequences are
+    /// replaced with U+FFFD.
+    fn text(self) -> impl Future<Output = Result<String>> + Send;
+
+    /// Reads a body of up to `limit` bytes and decodes it like
+    /// [`text`](Self::text).
+    fn text_with_limit(self, limit: usize) -> impl Future<Output = Result<String>> + Send;
+
+    /// Streams the body into `writer` as it arrives, returning the number of
+    /// bytes written.
+    ///
+    /// Unlike the other helpers, this does not buffer the body, so it has no
+    /// size limit; each chunk is written before the next is read. The writer
+    /// is flushed at the end. Failed writes are reported as
+    /// [`Error::Write`].
+    fn write_to<W>(self, writer: &mut W) -> impl Future<Output = Result<u64>> + Send
+    where
+        W: AsyncWrite + Unpin + Send + ?Sized;
+
+    /// Reads the body and deserializes it from JSON.
+    #[cfg(feature = "json")]
+    fn json<T: DeserializeOwned>(self) -> impl Future<Output = Result<T>> + Send;
+
+    /// Reads a body of up to `limit` bytes and deserializes it from JSON.
+    #[cfg(feature = "json")]
+    fn json_with_limit<T: DeserializeOwned>(
+        self,
+        limit: usize,
+    ) -> impl Future<Output = Result<T>> + Send;
+}
+
+impl ResponseExt for Response<ResponseBody> {
+    fn bytes(self) -> impl Future<Output = Result<Bytes>> + Send {
+        self.bytes_with_limit(DEFAULT_BODY_LIMIT)
+    }
+
+    fn bytes_with_limit(self, limit: usize) -> impl Future<Output = Result<Bytes>> + Send {
+        read(self.into_body(), limit)
+    }
+
+    fn text(self) -> impl Future<Output = Result<String>> + Send {
+        self.text_with_limit(DEFAULT_BODY_LIMIT)
+    }
+
+    async fn text_with_limit(self, limit: usize) -> Result<String> {
+        let (parts, body) = self.into_parts();
+        let body = read(body, limit).await?;
+        Ok(decode_text(&parts.headers, &body))
+    }
+
+    async fn write_to<W>(self, writer: &mut W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send + ?Sized,
+    {
+        let mut body = self.into_body();
+        let mut written = 0;
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(Error::body)?;
+            if let Ok(data) = frame.into_data() {
+                writer.write_all(&data).await.map_err(Error::Write)?;
+                written += data.len() as u64;
+            }
+        }
+        writer.flush().await.map_err(Error::Write)?;
+        Ok(written)
+    }
+
+    #[cfg(feature = "json")]
+    fn json<T: DeserializeOwned>(self) -> impl Future<Output = Result<T>> + Send {
+        self.json_with_limit(DEFAULT_BODY_LIMIT)
+    }
+
+    #[cfg(feature = "json")]
+    async fn json_with_limit<T: DeserializeOwned>(self, limit: usize) -> Result<T> {
+        let body = read(self.into_body(), limit).await?;
+        serde_json::from_slice(&body).map_err(|e| Error::Decode(e.into()))
+    }
+}
+
+/// Reads `body` into memory, failing once it grows past `limit` bytes.
+async fn read(body: ResponseBody, limit: usize) -> Result<Bytes> {
+    Ok(Limited::new(body, limit)
+        .collect()
+        .await
+        .map_err(Error::body)?
+        .to_bytes())
+}
@@ -0,0 +1,89 @@
+use crate::common::RetryBudget;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of buckets the budget's window is divided into.
+const BUCKETS: u32 = 10;
+
+/// Tracks requests and retries over a sliding window and decides whether
+/// another retry fits the budget.
+pub(crate) struct RetryLimiter {
+    settings: RetryBudget,
+    slot: Duration,
+    started: Instant,
+    buckets: Mutex<Vec<Bucket>>,
+    exhausted: AtomicU64,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    epoch: u64,
+    requests: u64,
+    retries: u64,
+}
+
+impl RetryLimiter {
+    /// Creates a limiter with the given settings.
+    pub(crate) fn new(settings: RetryBudget) -> Self {
+        let slot = (settings.window / BUCKETS).max(Duration::from_millis(1));
+        RetryLimiter {
+            settings,
+            slot,
+            started: Instant::now(),
+            buckets: Mutex::new(vec![Bucket::default(); BUCKETS as usize]),
+            exhausted: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a new request, which earns retries for the budget.
+    pub(crate) fn deposit(&self) {
+        let epoch = self.epoch();
+        let mut buckets = self.buckets.lock().unwrap();
+        current(&mut buckets, epoch).requests += 1;
+    }
+
+    /// Takes a retry from the budget, returning `false` if it is spent.
+    pub(crate) fn withdraw(&self) -> bool {
+        let epoch = self.epoch();
+        let mut buckets = self.buckets.lock().unwrap();
+        let (requests, retries) = buckets
+            .iter()
+            .filter(|bucket| bucket.epoch + u64::from(BUCKETS) > epoch)
+            .fold((0, 0), |(requests, retries), bucket| {
+                (requests + bucket.requests, retries + bucket.retries)
+            });
+
+        let reserve = self.settings.min_per_sec as f64 * self.settings.window.as_secs_f64();
+        let allowed = reserve + requests as f64 * self.settings.retry_ratio;
+        if retries as f64 + 1.0 > allowed {
+            drop(buckets);
+            self.exhausted.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!("retry budget exhausted");
+            return false;
+        }
+        current(&mut buckets, epoch).retries += 1;
+        true
+    }
+
+    /// Number of retries refused so far.
+    pub(crate) fn exhausted(&self) -> u64 {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    fn epoch(&self) -> u64 {
+        (self.started.elapsed().as_nanos() / self.slot.as_nanos()) as u64
+    }
+}
+
+/// Returns the bucket for `epoch`, clearing it if it holds an older epoch.
+fn current(buckets: &mut [Bucket], epoch: u64) -> &mut Bucket {
+    let bucket = &mut buckets[(epoch % u64::from(BUCKETS)) as usize];
+    if bucket.epoch != epoch {
+        *bucket = Bucket {
+            epoch,
+            ..Bucket::default()
+        };
+    }
+    bucket
+}
@@ -0,0 +1,343 @@
+use crate::common::{sign_v4, uri_encode, AwsCredentials};
+use crate::error::{Error, Result};
+use crate::Client;
+use arti_client::IsolationToken;
+use bytes::BytesMut;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MATCH, RANGE};
+use hyper::{Method, Request, StatusCode, Uri};
+use std::time::SystemTime;
+
+/// Smallest part size S3 accepts for all but the last part of an upload.
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+/// Upper bound on the size of responses that carry no object data.
+const MAX_XML_BODY: usize = 1024 * 1024;
+
+/// Connection settings for an S3-compatible object store.
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// Endpoint of the store, e.g. `https://s3.eu-central-1.amazonaws.com`.
+    pub endpoint: Uri,
+    /// Region used when signing requests.
+    pub region: String,
+    /// Access keys used to sign requests.
+    pub credentials: AwsCredentials,
+    /// Address buckets as `endpoint/bucket` instead of `bucket.endpoint`.
+    /// Most self-hosted stores need this.
+    pub path_style: bool,
+    /// Size of the parts objects are uploaded and downloaded in (at least 5 MiB).
+    pub part_size: usize,
+    /// Number of times a failed request is retried, each time over a fresh
+    /// circuit.
+    pub max_retries: u32,
+}
+
+impl S3Config {
+    /// Creates settings with path-style addressing, 8 MiB parts and three
+    /// retries.
+    pub fn new(endpoint: Uri, region: impl Into<String>, credentials: AwsCredentials) -> Self {
+        S3Config {
+            endpoint,
+            region: region.into(),
+            credentials,
+            path_style: true,
+            part_size: 8 * 1024 * 1024,
+            max_retries: 3,
+        }
+    }
+}
+
+/// A bucket in an S3-compatible object store, reached through a [`Client`].
+///
+/// Objects larger than one part are transferred part by part, and every part
+/// is retried on its own, so a circuit failing halfway through a large
+/// transfer only costs the part in flight.
+pub struct S3Bucket<'a> {
+    client: &'a Client,
+    config: S3Config,
+    bucket: String,
+}
+
+/// A fully read response.
+struct Reply {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Client {
+    /// Returns a handle for the given bucket.
+    pub fn s3_bucket(&self, bucket: impl Into<String>, config: S3Config) -> Result<S3Bucket<'_>> {
+        if config.part_size < MIN_PART_SIZE {
+            return Err(Error::Config(format!(
+                "S3 part size must be at least {MIN_PART_SIZE} bytes"
+            )));
+        }
+        Ok(S3Bucket {
+            client: self,
+            config,
+            bucket: bucket.into(),
+        })
+    }
+}
+
+impl S3Bucket<'_> {
+    /// Uploads an object, using a multipart upload when it is larger than one
+    /// part.
+    pub async fn put_object(&self, key: &str, content_type: &str, body: Bytes) -> Result<()> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(content_type).map_err(hyper::http::Error::from)?,
+        );
+
+        if body.len() <= self.config.part_size {
+            let reply = self.call(Method::PUT, key, "", headers, body).await?;
+            return check(reply, "PutObject").map(drop);
+        }
+
+        let reply = self
+            .call(Method::POST, key, "uploads", headers, Bytes::new())
+            .await
+            .and_then(|reply| check(reply, "CreateMultipartUpload"))?;
+        let upload_id = xml_text(&reply.body, "UploadId")
+            .ok_or_else(|| malformed("CreateMultipartUpload returned no upload ID"))?;
+        let upload_id = uri_encode(&upload_id, false);
+
+        let result = self.upload_parts(key, &upload_id, body).await;
+        if result.is_err() {
+            // Best effort: let the store discard the uploaded parts.
+            let query = format!("uploadId={upload_id}");
+            let _ = self
+                .call(Method::DELETE, key, &query, HeaderMap::new(), Bytes::new())
+                .await;
+        }
+        result
+    }
+
+    /// Downloads an object, in ranged parts when it is larger than one part.
+    pub async fn get_object(&self, key: &str) -> Result<Bytes> {
+        let head = self
+            .call(Method::HEAD, key, "", HeaderMap::new(), Bytes::new())
+            .await
+            .and_then(|reply| check(reply, "HeadObject"))?;
+        let length: u64 = head
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| malformed("HeadObject returned no content length"))?;
+
+        // Pin the version seen by HEAD so parts of different versions are
+        // never stitched together.
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = head.headers.get(ETAG) {
+            headers.insert(IF_MATCH, etag.clone());
+        }
+
+        if length <= self.config.part_size as u64 {
+            let reply = self
+                .call(Method::GET, key, "", headers, Bytes::new())
+                .await?;
+            return check(reply, "GetObject").map(|reply| reply.body);
+        }
+
+        let mut object = BytesMut::with_capacity(length as usize);
+        while (object.len() as u64) < length {
+            let start = object.len() as u64;
+            let end = (start + self.config.part_size as u64).min(length) - 1;
+            let range = format!("bytes={start}-{end}");
+            headers.insert(RANGE, HeaderValue::from_str(&range).expect("valid range"));
+
+            let reply = self
+                .call(Method::GET, key, "", headers.clone(), Bytes::new())
+                .await
+                .and_then(|reply| check(reply, "GetObject"))?;
+            if reply.status != StatusCode::PARTIAL_CONTENT
+                || reply.body.len() as u64 != end - start + 1
+            {
+                return Err(malformed(&format!(
+                    "GetObject returned an unexpected range for bytes {start}-{end}"
+                )));
+            }
+            object.extend_from_slice(&reply.body);
+        }
+
+        Ok(object.freeze())
+    }
+
+    /// Deletes an object.
+    pub async fn delete_object(&self, key: &str) -> Result<()> {
+        let reply = self
+            .call(Method::DELETE, key, "", HeaderMap::new(), Bytes::new())
+            .await?;
+        check(reply, "DeleteObject").map(drop)
+    }
+
+    /// Uploads `body` part by part and completes the multipart upload.
+    async fn upload_parts(&self, key: &str, upload_id: &str, body: Bytes) -> Result<()> {
+        let mut completion = String::from("<CompleteMultipartUpload>");
+        let mut start = 0;
+        let mut number = 1;
+        while start < body.len() {
+            let end = (start + self.config.part_size).min(body.len());
+            let query = format!("partNumber={number}&uploadId={upload_id}");
+            let reply = self
+                .call(
+                    Method::PUT,
+                    key,
+                    &query,
+                    HeaderMap::new(),
+                    body.slice(start..end),
+                )
+                .await
+                .and_then(|reply| check(reply, "UploadPart"))?;
+            let etag = reply
+                .headers
+                .get(ETAG)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| malformed("UploadPart returned no ETag"))?;
+            completion.push_str(&format!(
+                "<Part><PartNumber>{number}</PartNumber><ETag>{}</ETag></Part>",
+                xml_escape(etag)
+            ));
+
+            start = end;
+            number += 1;
+        }
+        completion.push_str("</CompleteMultipartUpload>");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/xml"));
+        let query = format!("uploadId={upload_id}");
+        let reply = self
+            .call(Method::POST, key, &query, headers, Bytes::from(completion))
+            .await
+            .and_then(|reply| check(reply, "CompleteMultipartUpload"))?;
+        // A completion can fail after the 200 status has been sent.
+        if let Some(code) = xml_text(&reply.body, "Code") {
+            return Err(Error::Other(
+                format!("CompleteMultipartUpload failed: {code}").into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Signs and sends a request, retrying over fresh circuits on transport
+    /// errors and server errors.
+    async fn call(
+        &self,
+        method: Method,
+        key: &str,
+        query: &str,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<Reply> {
+        let uri = self.object_uri(key, query)?;
+        let limit = self.config.part_size.max(MAX_XML_BODY);
+
+        let mut attempt = 0;
+        loop {
+            let (mut parts, ()) = Request::builder()
+                .method(method.clone())
+                .uri(uri.clone())
+                .body(())?
+                .into_parts();
+            parts.headers.extend(headers.clone());
+            sign_v4(
+                &mut parts,
+                Some(&body),
+                &self.config.credentials,
+                &self.config.region,
+                "s3",
+                SystemTime::now(),
+            )?;
+            if attempt > 0 {
+                parts.extensions.insert(IsolationToken::new());
+            }
+
+            let req = Request::from_parts(parts, Full::new(body.clone()));
+            let result = async {
+                let (parts, body) = self.client.send_request(req).await?.into_parts();
+                let body = Limited::new(body, limit)
+                    .collect()
+                    .await
+                    .map_err(Error::body)?
+                    .to_bytes();
+                Ok::<_, Error>(Reply {
+                    status: parts.status,
+                    headers: parts.headers,
+                    body,
+                })
+            }
+            .await;
+
+            match result {
+                Ok(reply) if !reply.status.is_server_error() => return Ok(reply),
+                result if attempt >= self.config.max_retries => return result,
+                result if !self.client.may_retry() => return result,
+                _ => attempt += 1,
+            }
+        }
+    }
+
+    /// Builds the URI of `key` in this bucket.
+    fn object_uri(&self, key: &str, query: &str) -> Result<Uri> {
+        let endpoint = &self.config.endpoint;
+        let scheme = endpoint.scheme_str().unwrap_or("https");
+        let authority = endpoint
+            .authority()
+            .ok_or_else(|| Error::Config("S3 endpoint has no host".into()))?;
+        let key = uri_encode(key.trim_start_matches('/'), true);
+
+        let mut uri = if self.config.path_style {
+            format!("{scheme}://{authority}/{}/{key}", self.bucket)
+        } else {
+            format!("{scheme}://{}.{authority}/{key}", self.bucket)
+        };
+        if !query.is_empty() {
+            uri.push('?');
+            uri.push_str(query);
+        }
+        Ok(uri.parse().map_err(hyper::http::Error::from)?)
+    }
+}
+
+/// Fails with [`Error::Status`] unless the reply has a 2xx status; the
+/// store's error code is logged.
+fn check(reply: Reply, operation: &str) -> Result<Reply> {
+    if reply.status.is_success() {
+        return Ok(reply);
+    }
+    if let Some(code) = xml_text(&reply.body, "Code") {
+        tracing::debug!("{operation} returned {}: {code}", reply.status);
+    }
+    Err(Error::Status(reply.status))
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::Decode(reason.into())
+}
+
+/// Returns the text of the first `<tag>` element in an XML body.
+fn xml_text(body: &[u8], tag: &str) -> Option<String> {
+    let body = std::str::from_utf8(body).ok()?;
+    let start = body.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = start + body[start..].find(&format!("</{tag}>"))?;
+    Some(
+        body[start..end]
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&"),
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,235 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+use crate::common::{AdaptiveConcurrency, Priority};
+
+/// How long an idle host keeps a limit below its initial one before it is
+/// forgotten.
+const BACKOFF_MEMORY: Duration = Duration::from_secs(10 * 60);
+
+/// Hands out a bounded number of request slots, highest priority first.
+pub(crate) struct Scheduler {
+    state: Mutex<State>,
+}
+
+struct State {
+    limit: usize,
+    active: usize,
+    next_seq: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+struct Waiter {
+    priority: Priority,
+    seq: u64,
+    tx: oneshot::Sender<Permit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Higher priority first, then first come, first served.
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl Scheduler {
+    /// Creates a scheduler allowing `limit` concurrent requests.
+    pub(crate) fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Scheduler {
+            state: Mutex::new(State {
+                limit: limit.max(1),
+                active: 0,
+                next_seq: 0,
+                waiters: BinaryHeap::new(),
+            }),
+        })
+    }
+
+    /// Waits for a free slot.
+    pub(crate) async fn acquire(self: &Arc<Self>, priority: Priority) -> Permit {
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.active < state.limit {
+                state.active += 1;
+                return Permit {
+                    scheduler: self.clone(),
+                    released: false,
+                };
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiters.push(Waiter { priority, seq, tx });
+            rx
+        };
+
+        // The scheduler outlives its waiters, so the sender is never dropped unsent.
+        rx.await.expect("scheduler dropped a waiter")
+    }
+
+    /// Changes the number of concurrent slots, waking waiters if it grew.
+    pub(crate) fn set_limit(self: &Arc<Self>, limit: usize) {
+        self.state.lock().unwrap().limit = limit.max(1);
+        self.grant_waiters();
+    }
+
+    /// Returns `true` if no slot is taken and nobody is waiting for one.
+    fn is_idle(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.active == 0 && state.waiters.is_empty()
+    }
+
+    /// Hands free slots to waiters in priority order.
+    fn grant_waiters(self: &Arc<Self>) {
+        loop {
+            let waiter = {
+                let mut state = self.state.lock().unwrap();
+                if state.active >= state.limit {
+                    return;
+                }
+                match state.waiters.pop() {
+                    Some(waiter) => {
+                        state.active += 1;
+                        waiter
+                    }
+                    None => return,
+                }
+            };
+
+            let permit = Permit {
+                scheduler: self.clone(),
+                released: false,
+            };
+            if let Err(unused) = waiter.tx.send(permit) {
+                // The waiter gave up; take the slot back and try the next one.
+                unused.release();
+            }
+        }
+    }
+}
+
+/// A request slot, released (or handed to the next waiter) on drop.
+pub(crate) struct Permit {
+    scheduler: Arc<Scheduler>,
+    /// Set once the slot has been given back, so that drop leaves it alone.
+    released: bool,
+}
+
+impl Permit {
+    /// Gives the slot back without handing it to a waiter.
+    fn release(mut self) {
+        self.released = true;
+        self.scheduler.state.lock().unwrap().active -= 1;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        self.scheduler.state.lock().unwrap().active -= 1;
+        self.scheduler.grant_waiters();
+    }
+}
+
+/// Per-host AIMD limiters.
+pub(crate) struct AdaptiveLimiter {
+    settings: AdaptiveConcurrency,
+    hosts: Mutex<HashMap<String, Arc<HostLimit>>>,
+}
+
+/// The adaptive limit for a single host.
+pub(crate) struct HostLimit {
+    scheduler: Arc<Scheduler>,
+    limit: Mutex<f64>,
+    /// When the last request to the host finished.
+    last_used: Mutex<Instant>,
+}
+
+impl HostLimit {
+    /// Returns `true` if nothing is in flight to the host and its limit no
+    /// longer holds anything worth remembering: it is back at `initial`, or
+    /// the host has been idle long enough to be tried afresh.
+    fn is_stale(self: &Arc<Self>, initial: usize) -> bool {
+        Arc::strong_count(self) == 1
+            && self.scheduler.is_idle()
+            && (*self.limit.lock().unwrap() >= initial as f64
+                || self.last_used.lock().unwrap().elapsed() >= BACKOFF_MEMORY)
+    }
+}
+
+impl AdaptiveLimiter {
+    /// Creates a limiter with the given settings.
+    pub(crate) fn new(settings: AdaptiveConcurrency) -> Self {
+        AdaptiveLimiter {
+            settings,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits for a slot for `host`.
+    ///
+    /// Hosts that went idle are forgotten on the way, so the limiter does
+    /// not grow with every host ever contacted.
+    pub(crate) async fn acquire(&self, host: &str, priority: Priority) -> (Permit, Arc<HostLimit>) {
+        let initial = self
+            .settings
+            .initial
+            .clamp(self.settings.min, self.settings.max);
+        let host_limit = {
+            let mut hosts = self.hosts.lock().unwrap();
+            hosts.retain(|_, host_limit| !host_limit.is_stale(initial));
+            hosts
+                .entry(host.to_string())
+                .or_insert_with(|| {
+                    Arc::new(HostLimit {
+                        scheduler: Scheduler::new(initial),
+                        limit: Mutex::new(initial as f64),
+                        last_used: Mutex::new(Instant::now()),
+                    })
+                })
+                .clone()
+        };
+
+        let permit = host_limit.scheduler.acquire(priority).await;
+        (permit, host_limit)
+    }
+
+    /// Feeds the outcome of a request back into the host's limit.
+    pub(crate) fn record(&self, host_limit: &HostLimit, latency: Duration, success: bool) {
+        let settings = &self.settings;
+        let new_limit = {
+            let mut limit = host_limit.limit.lock().unwrap();
+            if success && latency <= settings.latency_target {
+                *limit += 1.0 / *limit;
+            } else {
+                *limit *= settings.backoff;
+            }
+            *limit = limit.clamp(settings.min.max(1) as f64, settings.max as f64);
+            *limit as usize
+        };
+        *host_limit.last_used.lock().unwrap() = Instant::now();
+        host_limit.scheduler.set_limit(new_limit);
+    }
+}
@@ -0,0 +1,232 @@
+use hyper::body::Body;
+use hyper::header::CONTENT_LENGTH;
+use hyper::{Method, Request, Response, StatusCode, Version};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Controls how much of the request target ends up in the access log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathLogging {
+    /// Log the path but drop the query string.
+    #[default]
+    StripQuery,
+    /// Log the full path and query string.
+    Full,
+    /// Do not log the request target at all.
+    Redacted,
+}
+
+impl PathLogging {
+    fn apply(&self, uri: &hyper::Uri) -> String {
+        match self {
+            PathLogging::StripQuery => uri.path().to_string(),
+            PathLogging::Full => uri
+                .path_and_query()
+                .map(|pq| pq.as_str().to_string())
+                .unwrap_or_else(|| uri.path().to_string()),
+            PathLogging::Redacted => "-".to_string(),
+        }
+    }
+}
+
+/// A single access log record.
+///
+/// Onion service clients have no address, so no client identifier is part of
+/// the record.
+#[derive(Debug, Clone)]
+pub struct AccessLogEntry {
+    /// When the request was received.
+    pub timestamp: SystemTime,
+    /// The request method.
+    pub method: Method,
+    /// The request target, sanitized according to [`PathLogging`].
+    pub target: String,
+    /// The request HTTP version.
+    pub version: Version,
+    /// The response status.
+    pub status: StatusCode,
+    /// The response body size, if known up front.
+    pub bytes: Option<u64>,
+    /// Time between receiving the request and producing the response.
+    pub duration: Duration,
+}
+
+impl AccessLogEntry {
+    /// Formats the entry as a Common Log Format line (without trailing newline).
+    pub fn to_common_log_format(&self) -> String {
+        let bytes = self
+            .bytes
+            .map(|b| b.to_string())
+            .unwrap_or_else(|| "-".to_string());
+
+        format!(
+            "- - - [{}] \"{} {} {:?}\" {} {}",
+            clf_timestamp(self.timestamp),
+            self.method,
+            self.target,
+            self.version,
+            self.status.as_u16(),
+            bytes
+        )
+    }
+}
+
+/// A destination for access log entries.
+pub trait AccessLog: Send + Sync {
+    /// Records a completed request.
+    fn log(&self, entry: &AccessLogEntry);
+}
+
+impl<F> AccessLog for F
+where
+    F: Fn(&AccessLogEntry) + Send + Sync,
+{
+    fn log(&self, entry: &AccessLogEntry) {
+        self(entry)
+    }
+}
+
+/// Writes entries in Common Log Format, one per line.
+pub struct CommonLogWriter<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> CommonLogWriter<W> {
+    /// Creates a new `CommonLogWriter` writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        CommonLogWriter {
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl<W: Write + Send> AccessLog for CommonLogWriter<W> {
+    fn log(&self, entry: &AccessLogEntry) {
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "{}", entry.to_common_log_format());
+        }
+    }
+}
+
+/// Emits entries as structured `tracing` events under the `hypertor::access` target.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingAccessLog;
+
+impl AccessLog for TracingAccessLog {
+    fn log(&self, entry: &AccessLogEntry) {
+        tracing::info!(
+            target: "hypertor::access",
+            method = %entry.method,
+            target_path = %entry.target,
+            version = ?entry.version,
+            status = entry.status.as_u16(),
+            bytes = entry.bytes,
+            duration_ms = entry.duration.as_millis() as u64,
+        );
+    }
+}
+
+/// Produces access log entries for requests handled by a service.
+#[derive(Clone)]
+pub struct AccessLogger {
+    sink: Arc<dyn AccessLog>,
+    path_logging: PathLogging,
+}
+
+impl AccessLogger {
+    /// Creates a new `AccessLogger` sending entries to `sink`.
+    pub fn new(sink: impl AccessLog + 'static) -> Self {
+        AccessLogger {
+            sink: Arc::new(sink),
+            path_logging: PathLogging::default(),
+        }
+    }
+
+    /// Sets how request targets are sanitized before logging.
+    pub fn path_logging(mut self, path_logging: PathLogging) -> Self {
+        self.path_logging = path_logging;
+        self
+    }
+
+    /// Captures the request side of an entry; call [`PendingAccess::finish`]
+    /// once the response is ready.
+    pub fn start<B>(&self, request: &Request<B>) -> PendingAccess {
+        PendingAccess {
+            sink: self.sink.clone(),
+            timestamp: SystemTime::now(),
+            started: Instant::now(),
+            method: request.method().clone(),
+            target: self.path_logging.apply(request.uri()),
+            version: request.version(),
+        }
+    }
+}
+
+/// The request half of an access log entry.
+pub struct PendingAccess {
+    sink: Arc<dyn AccessLog>,
+    timestamp: SystemTime,
+    started: Instant,
+    method: Method,
+    target: String,
+    version: Version,
+}
+
+impl PendingAccess {
+    /// Completes the entry with `response` and hands it to the log sink.
+    pub fn finish<B: Body>(self, response: &Response<B>) {
+        let bytes = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .or_else(|| response.body().size_hint().exact());
+
+        let entry = AccessLogEntry {
+            timestamp: self.timestamp,
+            method: self.method,
+            target: self.target,
+            version: self.version,
+            status: response.status(),
+            bytes,
+            duration: self.started.elapsed(),
+        };
+
+        self.sink.log(&entry);
+    }
+}
+
+/// Formats a timestamp as `10/Oct/2000:13:55:36 +0000`.
+fn clf_timestamp(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil-from-days conversion, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        rem / 3_600,
+        (rem % 3_600) / 60,
+        rem % 60
+    )
+}
@@ -0,0 +1,223 @@
+use crate::common::ContentEncoding;
+use crate::error::{Error, Result};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Body, Bytes};
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG,
+    VARY,
+};
+use hyper::{Response, StatusCode};
+use std::io::Write;
+
+/// Response compression keyed off the request's `Accept-Encoding` header.
+///
+/// Bandwidth is scarce on Tor circuits, so compressing text responses served
+/// from an onion service pays off more than it does on the open web.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    /// Encodings offered to clients, in order of server preference.
+    pub encodings: Vec<ContentEncoding>,
+    /// Bodies smaller than this many bytes are sent uncompressed.
+    pub min_size: usize,
+    /// gzip and deflate compression level (0-9).
+    pub gzip_level: u32,
+    /// Brotli quality (0-11).
+    pub brotli_quality: u32,
+    /// zstd compression level (1-19).
+    pub zstd_level: i32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            encodings: vec![
+                #[cfg(feature = "brotli")]
+                ContentEncoding::Brotli,
+                #[cfg(feature = "gzip")]
+                ContentEncoding::Gzip,
+            ],
+            min_size: 860,
+            gzip_level: 6,
+            brotli_quality: 5,
+            zstd_level: 3,
+        }
+    }
+}
+
+impl Compression {
+    /// Picks the encoding to use for a request with the given headers, if any.
+    ///
+    /// Codings are ranked by their `q` value; ties are broken by the order of
+    /// [`Compression::encodings`].
+    pub fn negotiate(&self, request_headers: &HeaderMap) -> Option<ContentEncoding> {
+        let mut best: Option<(ContentEncoding, f32)> = None;
+
+        for encoding in &self.encodings {
+            let q = request_headers
+                .get_all(ACCEPT_ENCODING)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .filter_map(|value| quality_of(value, encoding.as_str()))
+                .max_by(f32::total_cmp);
+
+            if let Some(q) = q.filter(|q| *q > 0.0) {
+                if best.is_none_or(|(_, best_q)| q > best_q) {
+                    best = Some((*encoding, q));
+                }
+            }
+        }
+
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Compresses `response` if the client accepts one of the configured
+    /// encodings and the content type is worth compressing.
+    ///
+    /// The body is buffered in memory. Responses that already carry a
+    /// `Content-Encoding`, or that would not shrink, are passed through as-is.
+    pub async fn compress<B>(
+        &self,
+        request_headers: &HeaderMap,
+        response: Response<B>,
+    ) -> Result<Response<Full<Bytes>>>
+    where
+        B: Body,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (mut parts, body) = response.into_parts();
+        let body = body
+            .collect()
+            .await
+            .map_err(|e| Error::Http(e.into()))?
+            .to_bytes();
+
+        if !is_compressible(parts.status, &parts.headers) {
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+
+        parts
+            .headers
+            .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+        let encoding = match self.negotiate(request_headers) {
+            Some(encoding) if body.len() >= self.min_size => encoding,
+            _ => return Ok(Response::from_parts(parts, Full::new(body))),
+        };
+
+        let compressed = encode(encoding, &body, self).map_err(|e| Error::Other(e.into()))?;
+        if compressed.len() >= body.len() {
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+
+        // The representation changed, so a strong validator no longer applies.
+        if let Some(etag) = parts.headers.get(ETAG).and_then(|v| v.to_str().ok()) {
+            if !etag.starts_with("W/") {
+                let weak = HeaderValue::from_str(&format!("W/{etag}"))
+                    .map_err(hyper::http::Error::from)?;
+                parts.headers.insert(ETAG, weak);
+            }
+        }
+
+        Ok(Response::from_parts(
+            parts,
+            Full::new(Bytes::from(compressed)),
+        ))
+    }
+}
+
+fn encode(
+    encoding: ContentEncoding,
+    data: &[u8],
+    compression: &Compression,
+) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        #[cfg(feature = "gzip")]
+        ContentEncoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(compression.gzip_level),
+            );
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "deflate")]
+        ContentEncoding::Deflate => {
+            let mut encoder = flate2::write::ZlibEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(compression.gzip_level),
+            );
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "brotli")]
+        ContentEncoding::Brotli => {
+            let mut encoder =
+                brotli::CompressorWriter::new(Vec::new(), 4096, compression.brotli_quality, 22);
+            encoder.write_all(data)?;
+            Ok(encoder.into_inner())
+        }
+        #[cfg(feature = "zstd")]
+        ContentEncoding::Zstd => zstd::stream::encode_all(data, compression.zstd_level),
+    }
+}
+
+/// Returns the `q` value given to `coding` in an `Accept-Encoding` value.
+fn quality_of(accept_encoding: &str, coding: &str) -> Option<f32> {
+    let mut wildcard = None;
+
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let token = params.next().unwrap_or("").trim();
+        let q = params
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if token.eq_ignore_ascii_case(coding) {
+            return Some(q);
+        }
+        if token == "*" {
+            wildcard = Some(q);
+        }
+    }
+
+    wildcard
+}
+
+fn is_compressible(status: StatusCode, headers: &HeaderMap) -> bool {
+    if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || headers.contains_key(CONTENT_ENCODING)
+    {
+        return false;
+    }
+
+    let Some(content_type) = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+        )
+}
@@ -0,0 +1,85 @@
+use crate::{Client, ResponseBody, Result};
+use hyper::body::{Body, Bytes};
+use hyper::header::{HeaderMap, HeaderName, CONNECTION, HOST};
+use hyper::http::uri::PathAndQuery;
+use hyper::{Request, Response, Uri, Version};
+
+/// Hop-by-hop headers that must not be forwarded (RFC 9110, section 7.6.1).
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+impl Client {
+    /// Forwards a request received by a hosted service to `upstream` over Tor.
+    ///
+    /// Request and response bodies are passed through frame by frame, so a
+    /// gateway's memory use stays flat regardless of transfer size. The
+    /// returned response can be handed straight back to `hyper`'s server.
+    /// Forwarded requests are sent once: challenges, authentication and
+    /// retries are left to the original client.
+    ///
+    /// Insert a [`ProxyHeader`](crate::ProxyHeader) into the request's
+    /// extensions to announce the connection with PROXY protocol v2.
+    pub async fn forward<B>(
+        &self,
+        upstream: &Uri,
+        req: Request<B>,
+    ) -> Result<Response<ResponseBody>>
+    where
+        B: Body<Data = Bytes> + Send + 'static,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (mut parts, body) = req.into_parts();
+
+        let path = parts
+            .uri
+            .path_and_query()
+            .cloned()
+            .unwrap_or_else(|| PathAndQuery::from_static("/"));
+        let mut uri = Uri::builder().path_and_query(path);
+        if let Some(scheme) = upstream.scheme() {
+            uri = uri.scheme(scheme.clone());
+        }
+        if let Some(authority) = upstream.authority() {
+            uri = uri.authority(authority.clone());
+        }
+        parts.uri = uri.build()?;
+        // The upstream hop is independent of the one the request came in on.
+        if parts.version != Version::HTTP_10 {
+            parts.version = Version::HTTP_11;
+        }
+
+        strip_hop_by_hop(&mut parts.headers);
+        parts.headers.remove(HOST);
+
+        let mut resp = self.dispatch(Request::from_parts(parts, body)).await?;
+        strip_hop_by_hop(resp.headers_mut());
+        Ok(resp)
+    }
+}
+
+/// Removes hop-by-hop headers, including those named in `Connection`.
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{Client, IsolationToken, Result};
+use base64::Engine;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Empty};
+use hyper::body::{Body, Buf, Bytes, Frame, SizeHint};
+use hyper::header::{HeaderValue, PROXY_AUTHENTICATE, PROXY_AUTHORIZATION, RETRY_AFTER};
+use hyper::{Request, Response, StatusCode, Uri};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+/// The body of responses returned by [`Gateway::forward`].
+pub type GatewayBody = UnsyncBoxBody<Bytes, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Limits on what one tenant of a [`Gateway`] sends through it.
+#[derive(Debug, Clone)]
+pub struct TenantQuota {
+    /// Requests accepted per window.
+    pub max_requests: Option<u64>,
+    /// Request and response body bytes per window.
+    ///
+    /// This is a soft limit: it is checked as requests arrive, so a request
+    /// admitted while budget is left runs to completion even if its bodies
+    /// go past it. Bytes count towards the window the request was admitted
+    /// in; what a transfer moves after that window has ended is not charged
+    /// to the next one.
+    pub max_bytes: Option<u64>,
+    /// The period the limits apply to, starting with a tenant's first request.
+    pub window: Duration,
+}
+
+impl Default for TenantQuota {
+    fn default() -> Self {
+        TenantQuota {
+            max_requests: None,
+            max_bytes: None,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Shares one [`Client`] between several local applications, each a
+/// tenant identified by the Basic credentials in its `Proxy-Authorization`
+/// header.
+///
+/// Every tenant gets circuits of its own and is held to its own
+/// [`TenantQuota`], so tenants can be neither linked nor starved by each
+/// other. Only digests of tenant passwords are kept, compared in constant
+/// time and wiped from memory when the gateway is dropped.
+pub struct Gateway {
+    client: Client,
+    tenants: HashMap<String, Tenant>,
+}
+
+struct Tenant {
+    /// SHA-256 digest of the password.
+    password: Zeroizing<[u8; 32]>,
+    isolation: IsolationToken,
+    quota: TenantQuota,
+    usage: Arc<Mutex<Usage>>,
+}
+
+/// What a tenant has used in the current window.
+struct Usage {
+    /// Counts the windows so far, telling transfers of past windows apart.
+    window: u64,
+    started: Instant,
+    requests: u64,
+    bytes: u64,
+}
+
+impl Gateway {
+    /// Creates a gateway without tenants, which refuses every request.
+    pub fn new(client: Client) -> Self {
+        Gateway {
+            client,
+            tenants: HashMap::new(),
+        }
+    }
+
+    /// Adds a tenant authenticating with `username` and `password`,
+    /// replacing any previous tenant of that name.
+    pub fn tenant(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+        quota: TenantQuota,
+    ) -> Self {
+        let tenant = Tenant {
+            password: digest(&Zeroizing::new(password.into())),
+            isolation: IsolationToken::new(),
+            quota,
+            usage: Arc::new(Mutex::new(Usage {
+                window: 0,
+                started: Instant::now(),
+                requests: 0,
+                bytes: 0,
+            })),
+        };
+        self.tenants.insert(username.into(), tenant);
+        self
+    }
+
+    /// Forwards a request to `upstream` with [`Client::forward`] on behalf
+    /// of the tenant it authenticates as.
+    ///
+    /// Unauthenticated requests are answered with `407 Proxy Authentication
+    /// Required`, and those of tenants over their quota with `429 Too Many
+    /// Requests` and a `Retry-After` for the end of the window. The
+    /// credentials are not forwarded.
+    pub async fn forward<B>(
+        &self,
+        upstream: &Uri,
+        mut req: Request<B>,
+    ) -> Result<Response<GatewayBody>>
+    where
+        B: Body<Data = Bytes> + Send + 'static,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let Some(tenant) = self.authenticate(&req) else {
+            let mut resp = empty(StatusCode::PROXY_AUTHENTICATION_REQUIRED);
+            resp.headers_mut().insert(
+                PROXY_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"hypertor\""),
+            );
+            return Ok(resp);
+        };
+        let window = match tenant.admit() {
+            Ok(window) => window,
+            Err(wait) => {
+                let mut resp = empty(StatusCode::TOO_MANY_REQUESTS);
+                resp.headers_mut().insert(
+                    RETRY_AFTER,
+                    HeaderValue::from(wait.as_secs_f64().ceil() as u64),
+                );
+                return Ok(resp);
+            }
+        };
+
+        req.extensions_mut().insert(tenant.isolation);
+        let req = req.map(|body| Billed::new(body, Arc::clone(&tenant.usage), window));
+        let resp = self.client.forward(upstream, req).await?;
+        Ok(resp.map(|body| Billed::new(body, Arc::clone(&tenant.usage), window).boxed_unsync()))
+    }
+
+    /// Returns the tenant whose credentials `req` carries.
+    fn authenticate<B>(&self, req: &Request<B>) -> Option<&Tenant> {
+        let value = req.headers().get(PROXY_AUTHORIZATION)?.to_str().ok()?;
+        let (scheme, token) = value.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let decoded = Zeroizing::new(
+            base64::engine::general_purpose::STANDARD
+                .decode(token.trim())
+                .ok()?,
+        );
+        let (username, password) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+        let password = digest(password);
+        match self.tenants.get(username) {
+            Some(tenant) => bool::from(tenant.password.ct_eq(&*password)).then_some(tenant),
+            None => {
+                // Compare anyway, so unknown usernames take as long as known ones.
+                std::hint::black_box([0u8; 32].ct_eq(&*password));
+                None
+            }
+        }
+    }
+}
+
+/// Returns the SHA-256 digest of a tenant password.
+fn digest(password: &str) -> Zeroizing<[u8; 32]> {
+    Zeroizing::new(Sha256::digest(password.as_bytes()).into())
+}
+
+impl std::fmt::Debug for Gateway {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut tenants: Vec<&String> = self.tenants.keys().collect();
+        tenants.sort();
+        f.debug_struct("Gateway")
+            .field("tenants", &tenants)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tenant {
+    /// Counts a request against the quota and returns the window it is
+    /// charged to, or returns how long until the window ends if the quota
+    /// is used up.
+    fn admit(&self) -> std::result::Result<u64, Duration> {
+        let mut usage = self.usage.lock().unwrap();
+        let elapsed = usage.started.elapsed();
+        if elapsed >= self.quota.window {
+            *usage = Usage {
+                window: usage.window + 1,
+                started: Instant::now(),
+                requests: 0,
+                bytes: 0,
+            };
+        }
+        let spent = |cap: Option<u64>, used: u64| cap.is_some_and(|cap| used >= cap);
+        if spent(self.quota.max_requests, usage.requests)
+            || spent(self.quota.max_bytes, usage.bytes)
+        {
+            return Err(self.quota.window.saturating_sub(usage.started.elapsed()));
+        }
+        usage.requests += 1;
+        Ok(usage.window)
+    }
+}
+
+fn empty(status: StatusCode) -> Response<GatewayBody> {
+    let mut resp = Response::new(Empty::new().map_err(|never| match never {}).boxed_unsync());
+    *resp.status_mut() = status;
+    resp
+}
+
+/// A body whose data is counted against a tenant's quota for the window
+/// its request was admitted in.
+struct Billed<B> {
+    inner: Pin<Box<B>>,
+    usage: Arc<Mutex<Usage>>,
+    window: u64,
+}
+
+impl<B> Billed<B> {
+    fn new(body: B, usage: Arc<Mutex<Usage>>, window: u64) -> Self {
+        Billed {
+            inner: Box::pin(body),
+            usage,
+            window,
+        }
+    }
+}
+
+impl<B: Body> Body for Billed<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = self.inner.as_mut().poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let Some(data) = frame.data_ref() {
+                let mut usage = self.usage.lock().unwrap();
+                if usage.window == self.window {
+                    usage.bytes += data.remaining() as u64;
+                }
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{client, ONION};
+    use crate::ClientConfigBuilder;
+    use http_body_util::Full;
+
+    /// A gateway whose upstream answers every request with ten bytes.
+    async fn gateway(quota: TenantQuota) -> (Gateway, crate::testing::Log) {
+        let builder = ClientConfigBuilder::new().pool_max_idle_per_host(0);
+        let (client, log) = client(builder, |_| {
+            Response::new(Full::new(Bytes::from_static(b"0123456789")))
+        })
+        .await;
+        let gateway = Gateway::new(client)
+            .tenant("alice", "secret-a", quota)
+            .tenant("bob", "secret-b", TenantQuota::default());
+        (gateway, log)
+    }
+
+    fn request(credentials: Option<&str>) -> Request<Full<Bytes>> {
+        let mut req = Request::get("/page");
+        if let Some(credentials) = credentials {
+            let token = base64::engine::general_purpose::STANDARD.encode(credentials);
+            req = req.header(PROXY_AUTHORIZATION, format!("Basic {token}"));
+        }
+        req.body(Full::default()).unwrap()
+    }
+
+    fn upstream() -> Uri {
+        format!("http://{ONION}").parse().unwrap()
+    }
+
+    async fn forward(gateway: &Gateway, credentials: Option<&str>) -> Response<GatewayBody> {
+        let resp = gateway
+            .forward(&upstream(), request(credentials))
+            .await
+            .unwrap();
+        let (parts, body) = resp.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        Response::from_parts(
+            parts,
+            Full::new(body)
+                .map_err(|never| match never {})
+                .boxed_unsync(),
+        )
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_requests_are_refused() {
+        let (gateway, log) = gateway(TenantQuota::default()).await;
+        for credentials in [
+            None,
+            Some("alice:wrong"),
+            Some("carol:secret-a"),
+            Some("alice"),
+        ] {
+            let resp = forward(&gateway, credentials).await;
+            assert_eq!(resp.status(), StatusCode::PROXY_AUTHENTICATION_REQUIRED);
+            assert_eq!(
+                resp.headers()[PROXY_AUTHENTICATE],
+                "Basic realm=\"hypertor\""
+            );
+        }
+        assert!(log.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn tenants_get_circuits_of_their_own() {
+        let (gateway, log) = gateway(TenantQuota::default()).await;
+        for credentials in ["alice:secret-a", "alice:secret-a", "bob:secret-b"] {
+            let resp = forward(&gateway, Some(credentials)).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        let requests = log.requests();
+        assert!(requests
+            .iter()
+            .all(|req| !req.headers.contains_key(PROXY_AUTHORIZATION)));
+        let isolation: Vec<_> = requests.iter().map(|req| req.target.isolation).collect();
+        assert!(isolation[0].is_some());
+        assert_eq!(isolation[0], isolation[1]);
+        assert_ne!(isolation[0], isolation[2]);
+    }
+
+    #[tokio::test]
+    async fn request_quota() {
+        let quota = TenantQuota {
+            max_requests: Some(2),
+            ..TenantQuota::default()
+        };
+        let (gateway, log) = gateway(quota).await;
+        for _ in 0..2 {
+            let resp = forward(&gateway, Some("alice:secret-a")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        let resp = forward(&gateway, Some("alice:secret-a")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = resp.headers()[RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after), "{retry_after}");
+        assert_eq!(log.requests().len(), 2);
+
+        let resp = forward(&gateway, Some("bob:secret-b")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn byte_quota() {
+        let quota = TenantQuota {
+            max_bytes: Some(15),
+            ..TenantQuota::default()
+        };
+        let (gateway, _log) = gateway(quota).await;
+        for _ in 0..2 {
+            let resp = forward(&gateway, Some("alice:secret-a")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let resp = forward(&gateway, Some("alice:secret-a")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
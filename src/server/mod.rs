@@ -0,0 +1,46 @@
+//! Helpers for serving HTTP from onion services.
+//!
+//! hypertor does not accept onion service connections itself; these utilities
+//! are meant to be used from a `hyper` service that handles streams accepted
+//! through arti.
+//!
+//! Arti also holds the service's keys and signs its descriptors with them,
+//! so they cannot be kept in a hardware token behind an external signer;
+//! with the `sealed-keys` feature, `SealedKeys` encrypts them at rest.
+
+mod access_log;
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+mod compression;
+mod forward;
+mod gateway;
+#[cfg(feature = "sealed-keys")]
+mod sealed_keys;
+mod tunnel;
+
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+pub use crate::common::ContentEncoding;
+pub use access_log::{
+    AccessLog, AccessLogEntry, AccessLogger, CommonLogWriter, PathLogging, PendingAccess,
+    TracingAccessLog,
+};
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+pub use compression::Compression;
+pub use gateway::{Gateway, GatewayBody, TenantQuota};
+#[cfg(feature = "sealed-keys")]
+pub use sealed_keys::{SealedKeys, UnlockedKeys};
+pub use tunnel::forward_to_local;
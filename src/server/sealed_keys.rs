@@ -0,0 +1,448 @@
+use crate::common::{Error, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Identifies a sealed keystore file and the version of its format.
+const MAGIC: &[u8; 8] = b"HTKEYS1\0";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Magic, the three Argon2 costs, salt and nonce.
+const HEADER_LEN: usize = MAGIC.len() + 12 + SALT_LEN + NONCE_LEN;
+
+/// Arti's onion service keys, encrypted at rest with a passphrase.
+///
+/// Arti keeps the identity key of an onion service unencrypted in the
+/// `keystore` directory of its state directory, so a copy of that directory
+/// is enough to impersonate the service. [`seal`](Self::seal) encrypts the
+/// keystore into a single file, with XChaCha20-Poly1305 under a key derived
+/// from the passphrase by Argon2id, and removes the plaintext keys.
+/// [`unlock`](Self::unlock) restores them before arti launches the service.
+///
+/// Both derive a key with Argon2id, which takes a noticeable fraction of a
+/// second; call them from `spawn_blocking` in async code.
+#[derive(Debug, Clone)]
+pub struct SealedKeys {
+    path: PathBuf,
+}
+
+/// A keystore restored by [`SealedKeys::unlock`].
+///
+/// [`lock`](Self::lock), or dropping it, seals the keystore again, with the
+/// keys arti created while it was unlocked, and removes the plaintext keys.
+/// They are plain files until then; keeping arti's state directory on a
+/// RAM-backed file system such as `/dev/shm` keeps them off the disk even
+/// if the process dies first.
+#[derive(Debug)]
+pub struct UnlockedKeys {
+    sealed: PathBuf,
+    keystore: PathBuf,
+    key: SealingKey,
+    locked: bool,
+}
+
+/// A key derived from a passphrase, with the parameters that derived it.
+struct SealingKey {
+    key: Zeroizing<[u8; 32]>,
+    params: Params,
+    salt: [u8; SALT_LEN],
+}
+
+impl SealedKeys {
+    /// Creates a handle to the sealed keystore at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        SealedKeys { path: path.into() }
+    }
+
+    /// Returns the path of the sealed keystore.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Encrypts the keys in `keystore` with `passphrase`, replacing the
+    /// sealed keystore, and removes the `keystore` directory.
+    ///
+    /// Fails without touching the sealed keystore if `keystore` does not
+    /// exist or holds no keys.
+    pub fn seal(&self, keystore: impl AsRef<Path>, passphrase: &str) -> Result<()> {
+        let keystore = keystore.as_ref();
+        if is_empty(keystore).map_err(|e| Error::Other(e.into()))? {
+            return Err(Error::Other(
+                format!("no keys to seal in {}", keystore.display()).into(),
+            ));
+        }
+        let key = SealingKey::derive(passphrase, Params::DEFAULT, random())?;
+        write_sealed(&self.path, keystore, &key)
+    }
+
+    /// Decrypts the sealed keys with `passphrase` into `keystore`, the
+    /// `keystore` directory of arti's state directory.
+    ///
+    /// If nothing has been sealed yet, `keystore` is created empty and the
+    /// keys arti creates in it are sealed with `passphrase` when it is
+    /// locked. Fails if `keystore` already holds files, as when keys were
+    /// left unlocked by a process that died; seal them first.
+    pub fn unlock(&self, keystore: impl AsRef<Path>, passphrase: &str) -> Result<UnlockedKeys> {
+        let keystore = keystore.as_ref();
+        if !is_empty(keystore).map_err(|e| Error::Other(e.into()))? {
+            return Err(Error::Other(
+                format!("{} already holds keys", keystore.display()).into(),
+            ));
+        }
+        let (key, payload) = match std::fs::read(&self.path) {
+            Ok(sealed) => open(&sealed, passphrase)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = SealingKey::derive(passphrase, Params::DEFAULT, random())?;
+                (key, Zeroizing::new(Vec::new()))
+            }
+            Err(e) => return Err(Error::Other(e.into())),
+        };
+        if let Err(e) = restore(keystore, &payload) {
+            let _ = std::fs::remove_dir_all(keystore);
+            return Err(Error::Other(e.into()));
+        }
+        Ok(UnlockedKeys {
+            sealed: self.path.clone(),
+            keystore: keystore.to_path_buf(),
+            key,
+            locked: false,
+        })
+    }
+}
+
+impl UnlockedKeys {
+    /// Returns the directory the keys were restored to.
+    pub fn keystore(&self) -> &Path {
+        &self.keystore
+    }
+
+    /// Seals the keystore again and removes the plaintext keys.
+    ///
+    /// Fails without touching the sealed keystore if the keystore directory
+    /// was removed while unlocked.
+    pub fn lock(mut self) -> Result<()> {
+        self.locked = true;
+        write_sealed(&self.sealed, &self.keystore, &self.key)
+    }
+}
+
+impl Drop for UnlockedKeys {
+    fn drop(&mut self) {
+        if self.locked {
+            return;
+        }
+        if let Err(e) = write_sealed(&self.sealed, &self.keystore, &self.key) {
+            tracing::warn!(error = %e, keystore = %self.keystore.display(), "failed to seal keys");
+        }
+    }
+}
+
+impl SealingKey {
+    fn derive(passphrase: &str, params: Params, salt: [u8; SALT_LEN]) -> Result<Self> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| Error::Other(e.to_string().into()))?;
+        Ok(SealingKey { key, params, salt })
+    }
+
+    /// Returns the header of a sealed keystore encrypted with `nonce`.
+    fn header(&self, nonce: &[u8; NONCE_LEN]) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&self.params.m_cost().to_le_bytes());
+        header.extend_from_slice(&self.params.t_cost().to_le_bytes());
+        header.extend_from_slice(&self.params.p_cost().to_le_bytes());
+        header.extend_from_slice(&self.salt);
+        header.extend_from_slice(nonce);
+        header
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(self.key.as_ref().into())
+    }
+}
+
+impl std::fmt::Debug for SealingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SealingKey").finish_non_exhaustive()
+    }
+}
+
+/// Encrypts the files in `keystore` to `sealed` with `key` and removes
+/// `keystore`.
+fn write_sealed(sealed: &Path, keystore: &Path, key: &SealingKey) -> Result<()> {
+    if !keystore.is_dir() {
+        return Err(Error::Other(
+            format!("keystore {} is missing", keystore.display()).into(),
+        ));
+    }
+    let mut payload = Zeroizing::new(Vec::new());
+    collect(keystore, keystore, &mut payload).map_err(|e| Error::Other(e.into()))?;
+
+    let nonce = random::<NONCE_LEN>();
+    let mut contents = key.header(&nonce);
+    let ciphertext = key
+        .cipher()
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &payload,
+                aad: &contents,
+            },
+        )
+        .map_err(|_| Error::Other("failed to encrypt keys".into()))?;
+    contents.extend_from_slice(&ciphertext);
+
+    write_atomically(sealed, &contents).map_err(|e| Error::Other(e.into()))?;
+    std::fs::remove_dir_all(keystore).map_err(|e| Error::Other(e.into()))
+}
+
+/// Decrypts a sealed keystore, returning its key and the encoded files.
+fn open(sealed: &[u8], passphrase: &str) -> Result<(SealingKey, Zeroizing<Vec<u8>>)> {
+    if sealed.len() < HEADER_LEN || !sealed.starts_with(MAGIC) {
+        return Err(Error::Other("not a sealed keystore".into()));
+    }
+    let (header, ciphertext) = sealed.split_at(HEADER_LEN);
+    let field = |i: usize| {
+        let at = MAGIC.len() + 4 * i;
+        u32::from_le_bytes(header[at..at + 4].try_into().unwrap())
+    };
+    let params = Params::new(field(0), field(1), field(2), Some(32))
+        .map_err(|e| Error::Other(e.to_string().into()))?;
+    let salt = header[HEADER_LEN - NONCE_LEN - SALT_LEN..HEADER_LEN - NONCE_LEN]
+        .try_into()
+        .unwrap();
+    let nonce = &header[HEADER_LEN - NONCE_LEN..];
+
+    let key = SealingKey::derive(passphrase, params, salt)?;
+    let payload = key
+        .cipher()
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| Error::Other("wrong passphrase or damaged sealed keystore".into()))?;
+    Ok((key, Zeroizing::new(payload)))
+}
+
+/// Appends the files below `dir` to `payload`, each as its path relative to
+/// `root` and its contents, both prefixed with their length.
+fn collect(root: &Path, dir: &Path, payload: &mut Zeroizing<Vec<u8>>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            collect(root, &entry.path(), payload)?;
+        } else if kind.is_file() {
+            let path = entry.path();
+            let name = path
+                .strip_prefix(root)
+                .ok()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| std::io::Error::other("key file name is not UTF-8"))?
+                .replace(std::path::MAIN_SEPARATOR, "/");
+            let contents = Zeroizing::new(std::fs::read(&path)?);
+            let (Ok(name_len), Ok(contents_len)) =
+                (u16::try_from(name.len()), u32::try_from(contents.len()))
+            else {
+                return Err(std::io::Error::other("key file too large"));
+            };
+            payload.extend_from_slice(&name_len.to_be_bytes());
+            payload.extend_from_slice(name.as_bytes());
+            payload.extend_from_slice(&contents_len.to_be_bytes());
+            payload.extend_from_slice(&contents);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the files encoded by [`collect`] below `keystore`, with the
+/// owner-only permissions arti requires of its keystore.
+fn restore(keystore: &Path, mut payload: &[u8]) -> std::io::Result<()> {
+    let damaged = || std::io::Error::other("damaged sealed keystore");
+    create_dir(keystore)?;
+    while !payload.is_empty() {
+        let (name, rest) = take(payload, 2).ok_or_else(damaged)?;
+        let (contents, rest) = take(rest, 4).ok_or_else(damaged)?;
+        payload = rest;
+
+        let name = std::str::from_utf8(name).map_err(|_| damaged())?;
+        let relative = Path::new(name);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(damaged());
+        }
+        let path = keystore.join(relative);
+        if let Some(parent) = path.parent() {
+            create_dir(parent)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&path)?.write_all(contents)?;
+    }
+    Ok(())
+}
+
+/// Splits a field prefixed with its big-endian length, `width` bytes long,
+/// off the front of `data`.
+fn take(data: &[u8], width: usize) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = data.split_at_checked(width)?;
+    let len = len.iter().fold(0usize, |n, b| n << 8 | usize::from(*b));
+    rest.split_at_checked(len)
+}
+
+/// Creates `dir` and its missing parents, readable by their owner only.
+fn create_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Returns whether `dir` is missing or holds no entries.
+fn is_empty(dir: &Path) -> std::io::Result<bool> {
+    match std::fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes `contents` to `path` through a temporary file, so an interrupted
+/// write leaves the previous sealed keystore in place.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    if let Err(e) = std::fs::remove_file(&tmp) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(e);
+        }
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a fresh directory for one test, removed when it ends.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "hypertor-sealed-keys-{}",
+                u64::from_le_bytes(random())
+            ));
+            std::fs::create_dir(&dir).unwrap();
+            Scratch(dir)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const IDENTITY: &str = "hss/service/ks_hs_id.ed25519_expanded_private";
+
+    fn write_key(keystore: &Path, name: &str, contents: &[u8]) {
+        let path = keystore.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn seals_and_unlocks_keys() {
+        let scratch = Scratch::new();
+        let keystore = scratch.0.join("keystore");
+        let sealed = SealedKeys::new(scratch.0.join("keys.sealed"));
+        write_key(&keystore, IDENTITY, b"identity");
+
+        sealed.seal(&keystore, "correct horse").unwrap();
+        assert!(!keystore.exists());
+        let contents = std::fs::read(sealed.path()).unwrap();
+        assert!(!contents.windows(8).any(|w| w == b"identity"));
+
+        assert!(sealed.unlock(&keystore, "wrong horse").is_err());
+        assert!(!keystore.exists());
+
+        let unlocked = sealed.unlock(&keystore, "correct horse").unwrap();
+        assert_eq!(std::fs::read(keystore.join(IDENTITY)).unwrap(), b"identity");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(keystore.join(IDENTITY))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(sealed.unlock(&keystore, "correct horse").is_err());
+        unlocked.lock().unwrap();
+        assert!(!keystore.exists());
+    }
+
+    #[test]
+    fn lock_seals_keys_created_while_unlocked() {
+        let scratch = Scratch::new();
+        let keystore = scratch.0.join("keystore");
+        let sealed = SealedKeys::new(scratch.0.join("keys.sealed"));
+
+        // Nothing sealed yet: the keystore starts empty.
+        let unlocked = sealed.unlock(&keystore, "passphrase").unwrap();
+        write_key(&keystore, IDENTITY, b"identity");
+        drop(unlocked);
+        assert!(!keystore.exists());
+
+        let unlocked = sealed.unlock(&keystore, "passphrase").unwrap();
+        assert_eq!(std::fs::read(keystore.join(IDENTITY)).unwrap(), b"identity");
+        write_key(&keystore, "hss/service/ks_hs_desc_sign", b"signing");
+        unlocked.lock().unwrap();
+
+        let _unlocked = sealed.unlock(&keystore, "passphrase").unwrap();
+        assert_eq!(std::fs::read(keystore.join(IDENTITY)).unwrap(), b"identity");
+        assert_eq!(
+            std::fs::read(keystore.join("hss/service/ks_hs_desc_sign")).unwrap(),
+            b"signing"
+        );
+    }
+
+    #[test]
+    fn seal_refuses_an_empty_keystore() {
+        let scratch = Scratch::new();
+        let sealed = SealedKeys::new(scratch.0.join("keys.sealed"));
+        std::fs::write(sealed.path(), b"previous").unwrap();
+
+        assert!(sealed
+            .seal(scratch.0.join("missing"), "passphrase")
+            .is_err());
+        assert_eq!(std::fs::read(sealed.path()).unwrap(), b"previous");
+    }
+}
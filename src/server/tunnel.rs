@@ -0,0 +1,18 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// Connects a stream accepted by an onion service to a local TCP service,
+/// the reverse of [`Client::forward_port`](crate::Client::forward_port).
+///
+/// Returns the number of bytes sent to and received from the local service
+/// once both sides have closed.
+pub async fn forward_to_local<S>(
+    mut stream: S,
+    local: impl ToSocketAddrs,
+) -> std::io::Result<(u64, u64)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut socket = TcpStream::connect(local).await?;
+    tokio::io::copy_bidirectional(&mut stream, &mut socket).await
+}
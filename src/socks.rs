@@ -0,0 +1,77 @@
+use crate::common::{
+    refused, socks_connect, socks_greeting, socks_method, socks_password_auth, socks_reply,
+    Socks5Proxy,
+};
+use crate::error::{ConnectionError, Result};
+use crate::hooks::ConnectedStream;
+use crate::Client;
+use hyper::http::uri::Scheme;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+impl Client {
+    /// Connects through Tor to `proxy`, then asks it to connect to
+    /// `host:port`.
+    ///
+    /// The returned stream is a raw TCP tunnel to the destination; the leg
+    /// from the proxy onwards is not protected by Tor.
+    pub async fn connect_socks5(
+        &self,
+        proxy: &Socks5Proxy,
+        host: &str,
+        port: u16,
+    ) -> Result<ConnectedStream> {
+        let scheme = Scheme::try_from("socks5").map_err(hyper::http::Error::from)?;
+        let (mut stream, _) = self
+            .open_stream(&proxy.host, proxy.port, scheme, None, None)
+            .await
+            .map_err(ConnectionError::new)?;
+
+        let authenticate = proxy.credentials.is_some();
+        send(&mut stream, &socks_greeting(authenticate)).await?;
+        if socks_method(recv(&mut stream).await?, authenticate)? {
+            let (username, password) = proxy
+                .credentials
+                .as_ref()
+                .expect("offered only with credentials");
+            send(&mut stream, &socks_password_auth(username, password)?).await?;
+            let status: [u8; 2] = recv(&mut stream).await?;
+            if status[1] != 0 {
+                return Err(refused("SOCKS5 proxy rejected the credentials".into()));
+            }
+        }
+
+        send(&mut stream, &socks_connect(host, port)?).await?;
+        let mut remaining = socks_reply(recv(&mut stream).await?)?;
+        if remaining == 1 {
+            let [len] = recv(&mut stream).await?;
+            remaining = len as usize + 2;
+        }
+        let mut bound = vec![0; remaining];
+        stream
+            .read_exact(&mut bound)
+            .await
+            .map_err(ConnectionError::new)?;
+
+        Ok(stream)
+    }
+}
+
+/// Writes a handshake message to the proxy.
+async fn send(stream: &mut ConnectedStream, message: &[u8]) -> Result<()> {
+    stream
+        .write_all(message)
+        .await
+        .map_err(ConnectionError::new)?;
+    stream.flush().await.map_err(ConnectionError::new)?;
+    Ok(())
+}
+
+/// Reads a fixed-size handshake message from the proxy.
+async fn recv<const N: usize>(stream: &mut ConnectedStream) -> Result<[u8; N]> {
+    let mut message = [0; N];
+    stream
+        .read_exact(&mut message)
+        .await
+        .map_err(ConnectionError::new)?;
+    Ok(message)
+}
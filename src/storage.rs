@@ -0,0 +1,66 @@
+use crate::common::StorageUsage;
+use crate::error::{Error, Result};
+use crate::Client;
+use std::path::Path;
+
+impl Client {
+    /// Writes arti's unsaved state, such as its guards and circuit build
+    /// timings, to disk now.
+    ///
+    /// Arti saves its state on its own from time to time. Devices that must
+    /// spare their storage can call this at moments of their choosing, such
+    /// as before suspending or shutting down, to know that what was learned
+    /// so far is not lost.
+    pub fn persist_state(&self) -> Result<()> {
+        self.inner
+            .tor_client
+            .circmgr()
+            .store_persistent_state()
+            .map_err(|e| Error::Other(e.into()))
+    }
+
+    /// Returns how much disk space arti's state and directory cache take.
+    ///
+    /// Only known for clients whose directories were set with
+    /// [`ClientConfigBuilder::tor_storage`](crate::ClientConfigBuilder::tor_storage);
+    /// returns `None` otherwise.
+    pub async fn storage_usage(&self) -> Result<Option<StorageUsage>> {
+        let config = &self.inner.config;
+        let (Some(state_dir), Some(cache_dir)) =
+            (config.tor_state_dir.clone(), config.tor_cache_dir.clone())
+        else {
+            return Ok(None);
+        };
+        let usage = tokio::task::spawn_blocking(move || {
+            Ok::<_, std::io::Error>(StorageUsage {
+                state: dir_size(&state_dir)?,
+                cache: dir_size(&cache_dir)?,
+            })
+        })
+        .await
+        .map_err(|e| Error::Other(e.into()))?
+        .map_err(|e| Error::Other(e.into()))?;
+        Ok(Some(usage))
+    }
+}
+
+/// Adds up the sizes of the files below `dir`, not following links. A
+/// directory that does not exist yet is empty.
+fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut size = 0;
+    for entry in entries {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
@@ -0,0 +1,101 @@
+use hyper::body::{Body, Buf, Frame, SizeHint};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tracing::Span;
+
+/// Counts the body traffic of one exchange and records it on the
+/// exchange's span once both bodies are gone.
+pub(crate) struct Traffic {
+    span: Span,
+    bytes_sent: AtomicU64,
+    frames_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    frames_received: AtomicU64,
+}
+
+impl Traffic {
+    /// Creates counters for `span`, or `None` if no subscriber wants it.
+    pub(crate) fn new(span: &Span) -> Option<Arc<Self>> {
+        if span.is_disabled() {
+            return None;
+        }
+        Some(Arc::new(Traffic {
+            span: span.clone(),
+            bytes_sent: AtomicU64::new(0),
+            frames_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            frames_received: AtomicU64::new(0),
+        }))
+    }
+
+    /// Counts a data frame of `len` bytes read from the response body.
+    pub(crate) fn received(&self, len: usize) {
+        self.bytes_received.fetch_add(len as u64, Ordering::Relaxed);
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn sent(&self, len: usize) {
+        self.bytes_sent.fetch_add(len as u64, Ordering::Relaxed);
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Traffic {
+    fn drop(&mut self) {
+        let span = &self.span;
+        span.record("bytes_sent", self.bytes_sent.load(Ordering::Relaxed));
+        span.record("frames_sent", self.frames_sent.load(Ordering::Relaxed));
+        span.record(
+            "bytes_received",
+            self.bytes_received.load(Ordering::Relaxed),
+        );
+        span.record(
+            "frames_received",
+            self.frames_received.load(Ordering::Relaxed),
+        );
+    }
+}
+
+/// A request body whose data frames are counted into a [`Traffic`].
+pub(crate) struct Counted<B> {
+    inner: Pin<Box<B>>,
+    traffic: Option<Arc<Traffic>>,
+}
+
+impl<B> Counted<B> {
+    /// Wraps `body`, counting it into `traffic` if there is one.
+    pub(crate) fn new(body: B, traffic: Option<Arc<Traffic>>) -> Self {
+        Counted {
+            inner: Box::pin(body),
+            traffic,
+        }
+    }
+}
+
+impl<B: Body> Body for Counted<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = self.inner.as_mut().poll_frame(cx);
+        if let (Poll::Ready(Some(Ok(frame))), Some(traffic)) = (&poll, &self.traffic) {
+            if let Some(data) = frame.data_ref() {
+                traffic.sent(data.remaining());
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
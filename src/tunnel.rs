@@ -0,0 +1,47 @@
+use crate::error::{ConnectionError, Error, Result};
+use crate::{BoxFuture, Client};
+use hyper::http::uri::Scheme;
+use std::future::poll_fn;
+use std::task::Poll;
+use tokio::net::{TcpListener, TcpStream};
+
+impl Client {
+    /// Forwards every connection accepted on `listener` to `host:port`
+    /// through Tor, like `ssh -L`.
+    ///
+    /// Lets any TCP application reach onion services and clearnet hosts
+    /// without speaking SOCKS. Runs until accepting fails; a failed tunnel
+    /// only closes its own connection.
+    pub async fn forward_port(&self, listener: TcpListener, host: &str, port: u16) -> Result<()> {
+        let mut tunnels: Vec<BoxFuture<'_, ()>> = Vec::new();
+        loop {
+            // Drive open tunnels while waiting for the next connection.
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = poll_fn(|cx| {
+                    tunnels.retain_mut(|tunnel| tunnel.as_mut().poll(cx).is_pending());
+                    Poll::<()>::Pending
+                }) => unreachable!(),
+            };
+            let (socket, peer) = accepted.map_err(|e| Error::Other(e.into()))?;
+            tunnels.push(Box::pin(async move {
+                if let Err(e) = self.tunnel(socket, host, port).await {
+                    tracing::debug!(%peer, "tunnel to {host}:{port} failed: {e:#}");
+                }
+            }));
+        }
+    }
+
+    /// Copies data between `socket` and a new Tor stream to `host:port`.
+    async fn tunnel(&self, mut socket: TcpStream, host: &str, port: u16) -> Result<()> {
+        let scheme = Scheme::try_from("tcp").map_err(hyper::http::Error::from)?;
+        let (mut stream, _) = self
+            .open_stream(host, port, scheme, None, None)
+            .await
+            .map_err(ConnectionError::new)?;
+        tokio::io::copy_bidirectional(&mut socket, &mut stream)
+            .await
+            .map_err(ConnectionError::new)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use crate::common::UsageCaps;
+use arti_client::IsolationToken;
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// What a connection has carried so far.
+#[derive(Default)]
+pub(crate) struct Usage {
+    requests: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Usage {
+    /// Counts a request sent over the connection.
+    pub(crate) fn record_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_bytes(&self, n: usize) {
+        self.bytes.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+/// Enforces [`UsageCaps`] on reused connections.
+pub(crate) struct UsageLimiter {
+    caps: UsageCaps,
+    // Isolation of requests without their own token, replaced whenever one
+    // of their connections is retired.
+    circuit: Mutex<IsolationToken>,
+}
+
+impl UsageLimiter {
+    /// Creates a limiter enforcing `caps`.
+    pub(crate) fn new(caps: UsageCaps) -> Self {
+        UsageLimiter {
+            caps,
+            circuit: Mutex::new(IsolationToken::new()),
+        }
+    }
+
+    /// Returns the isolation of requests without their own token, if
+    /// circuits are retired along with connections.
+    pub(crate) fn circuit(&self) -> Option<IsolationToken> {
+        self.caps
+            .retire_circuit
+            .then(|| *self.circuit.lock().unwrap())
+    }
+
+    /// Moves requests without their own token to a fresh circuit.
+    pub(crate) fn renew(&self) {
+        *self.circuit.lock().unwrap() = IsolationToken::new();
+    }
+
+    /// Returns `true` if a connection with `usage`, sent over `isolation`,
+    /// has reached a cap, retiring its circuit if configured.
+    pub(crate) fn is_spent(&self, usage: &Usage, isolation: Option<IsolationToken>) -> bool {
+        let reached = |cap: Option<u64>, used: &AtomicU64| {
+            cap.is_some_and(|cap| used.load(Ordering::Relaxed) >= cap)
+        };
+        let spent = reached(self.caps.max_requests, &usage.requests)
+            || reached(self.caps.max_bytes, &usage.bytes);
+        if spent && self.caps.retire_circuit {
+            let mut circuit = self.circuit.lock().unwrap();
+            // Tokens supplied by callers or exit selection are left alone.
+            if isolation == Some(*circuit) {
+                *circuit = IsolationToken::new();
+            }
+        }
+        spent
+    }
+}
+
+/// A stream that counts the bytes read and written into a [`Usage`].
+pub(crate) struct Metered<S> {
+    inner: S,
+    usage: Arc<Usage>,
+}
+
+impl<S> Metered<S> {
+    /// Wraps `inner`, counting its traffic into `usage`.
+    pub(crate) fn new(inner: S, usage: Arc<Usage>) -> Self {
+        Metered { inner, usage }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Metered<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        this.usage.record_bytes(buf.filled().len() - before);
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Metered<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            this.usage.record_bytes(n);
+        }
+        poll
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(n)) = poll {
+            this.usage.record_bytes(n);
+        }
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
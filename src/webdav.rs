@@ -0,0 +1,111 @@
+use crate::common::{parse_multistatus, DavResource, Depth};
+use crate::error::{Error, Result};
+use crate::Client;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Method, Request, StatusCode, Uri};
+
+/// Upper bound on the size of a `PROPFIND` response.
+const MAX_MULTISTATUS: usize = 16 * 1024 * 1024;
+
+/// Properties requested by [`WebDav::propfind`].
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propfind xmlns:D="DAV:">
+  <D:prop>
+    <D:resourcetype/>
+    <D:getcontentlength/>
+    <D:getcontenttype/>
+    <D:getetag/>
+    <D:getlastmodified/>
+    <D:displayname/>
+  </D:prop>
+</D:propfind>"#;
+
+/// A thin WebDAV (RFC 4918) client on top of a [`Client`].
+///
+/// Requests go through [`Client::send_request`], so challenges and
+/// credentials are handled as for any other request.
+pub struct WebDav<'a> {
+    client: &'a Client,
+}
+
+impl Client {
+    /// Returns a WebDAV view of this client.
+    pub fn webdav(&self) -> WebDav<'_> {
+        WebDav { client: self }
+    }
+}
+
+impl WebDav<'_> {
+    /// Lists the properties of the resource at `uri` and, depending on
+    /// `depth`, of its members.
+    pub async fn propfind<T>(&self, uri: T, depth: Depth) -> Result<Vec<DavResource>>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::builder()
+            .method(Method::from_bytes(b"PROPFIND").expect("valid method"))
+            .uri(uri)
+            .header("Depth", depth.as_str())
+            .header(CONTENT_TYPE, "application/xml; charset=utf-8")
+            .body(Full::from(PROPFIND_BODY))?;
+
+        let resp = self.client.send_request(req).await?;
+        if resp.status() != StatusCode::MULTI_STATUS {
+            return Err(Error::Status(resp.status()));
+        }
+
+        let body = Limited::new(resp.into_body(), MAX_MULTISTATUS)
+            .collect()
+            .await
+            .map_err(Error::body)?
+            .to_bytes();
+        parse_multistatus(&body)
+    }
+
+    /// Creates a collection at `uri`.
+    pub async fn mkcol<T>(&self, uri: T) -> Result<()>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::builder()
+            .method(Method::from_bytes(b"MKCOL").expect("valid method"))
+            .uri(uri)
+            .body(Full::<Bytes>::default())?;
+        self.expect_success(req).await
+    }
+
+    /// Uploads `body` to `uri`, creating or replacing the resource.
+    pub async fn put<T>(&self, uri: T, content_type: &str, body: Bytes) -> Result<()>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::put(uri)
+            .header(CONTENT_TYPE, content_type)
+            .body(Full::from(body))?;
+        self.expect_success(req).await
+    }
+
+    /// Deletes the resource at `uri`; collections are deleted with their members.
+    pub async fn delete<T>(&self, uri: T) -> Result<()>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<hyper::http::Error>,
+    {
+        let req = Request::delete(uri).body(Full::<Bytes>::default())?;
+        self.expect_success(req).await
+    }
+
+    /// Sends `req` and fails unless the server answers with a 2xx status.
+    async fn expect_success(&self, req: Request<Full<Bytes>>) -> Result<()> {
+        let resp = self.client.send_request(req).await?;
+        if !resp.status().is_success() {
+            return Err(Error::Status(resp.status()));
+        }
+        Ok(())
+    }
+}